@@ -2,21 +2,27 @@
 extern crate drm;
 
 use drm::Device;
+use drm::fourcc::FourCC;
 use drm::mode::*;
 
 fn main() {
     let device = Device::first_card().unwrap();
 
+    let formats = [
+        FourCC::C8, FourCC::RGB565, FourCC::RGB888, FourCC::XRGB8888,
+        FourCC::XRGB2101010, FourCC::XRGB16161616, FourCC::YUYV,
+        FourCC::NV12, FourCC::YUV420, FourCC::P010,
+    ];
+
     println!("width = 64, height = 64");
-    for bpp in 0..33 {
-        for depth in 0..33 {
-            print!("bpp: {}, depth: {}\t", bpp, depth);
-            match DumbBuf::create_with_depth(&device, 64, 64, bpp, depth) {
-                Ok(buf) => {
-                    println!("Yes, size = {}, stride = {}", buf.bytes(), buf.pitch());
-                }
-                Err(error) => println!("No,  error = {}", error),
+    for format in formats.iter() {
+        print!("{}\t", format);
+        match DumbBuffer::create(&device, 64, 64, *format) {
+            Ok(buf) => {
+                println!("Yes, size = {}, pitches = {:?}, offsets = {:?}",
+                         buf.bytes(), buf.pitches(), buf.offsets());
             }
+            Err(error) => println!("No,  error = {}", error),
         }
     }
 }
@@ -2,31 +2,27 @@
 extern crate drm;
 
 use drm::Device;
-use drm::Event;
+use drm::event::EventContext;
 
 fn main()
 {
     let mut dev = Device::first_card().expect("Failed to get card");
 
     let mut prev_tv = None;
+    let mut ctx = EventContext::new();
+    ctx.on_vblank(|frame, _| {
+        print!("{:?}\t", frame);
+        if let Some(prev) = prev_tv {
+            print!("Difference: {:?}", frame.time.duration_since(prev));
+        }
+        prev_tv = Some(frame.time);
+        println!("");
+    });
+
     loop {
         // Request a vblank event.
         dev.request_vblank(0, 0).expect("Failed to request id");
         // Wait for the event.
-        let ev = dev.read_event().expect("Failed to read event");
-        print!("{:?}\t", ev);
-        
-        match (prev_tv, ev) {
-            (Some(prev), Event::VBlank { tv, .. }) => {
-                print!("Difference: {:?}", tv.duration_since(prev));
-                prev_tv = Some(tv)
-            },
-            (None, Event::VBlank {tv, ..}) => {
-                prev_tv = Some(tv)
-            }
-            _ => (),
-        }
-        
-        println!("");
+        dev.dispatch_events(&mut ctx).expect("Failed to read event");
     }
 }
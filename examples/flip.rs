@@ -0,0 +1,53 @@
+
+extern crate drm;
+
+use drm::draw::{self, Color, Rect};
+use drm::fourcc::FourCC;
+use drm::swapchain::Swapchain;
+use std::io::{ErrorKind, Result as IoResult};
+
+fn main() -> IoResult<()>
+{
+    let mut dev0 = drm::Device::first_card()?;
+    let mut dev = dev0.set_master()?;
+
+    let res = dev.get_resources()?;
+    let connector = res.connectors().iter()
+        .filter_map(|id| dev.get(*id).ok())
+        .find(|conn| conn.encoder_id().is_some())
+        .expect("No active connectors");
+    let encoder = dev.get(connector.encoder_id().unwrap())?;
+    let crtc = dev.get(encoder.crtc_id().unwrap())?;
+    let old_fb = crtc.fb_id().expect("Currently no fb");
+    let mode = crtc.mode().expect("mode").clone();
+    let (width, height) = (mode.hdisplay as u32, mode.vdisplay as u32);
+
+    let mut chain = Swapchain::new(&dev, crtc.id(), width, height, FourCC::XRGB8888, 2)?;
+    let first = chain.next_buffer().unwrap().index();
+    chain.set_mode(&dev, first, &[connector.id()], &mode)?;
+
+    // Slide a bar across the screen for 120 frames.
+    let bar = width / 8;
+    let mut frame = 0;
+    while frame < 120 {
+        if let Some(mut back) = chain.next_buffer() {
+            let x = (frame * (width - bar) / 120) as i32;
+            {
+                let mut image = back.image_mut()?;
+                draw::fill_rect(&mut image, Rect::new(0, 0, width, height), Color::rgb8(0, 0, 0))?;
+                draw::fill_rect(&mut image, Rect::new(x, 0, bar, height), Color::rgb8(0xff, 0, 0xff))?;
+            }
+            let index = back.index();
+            chain.queue(&dev, index)?;
+            frame += 1;
+        }
+        match dev.read_event() {
+            Ok(event) => { chain.handle_event(&event); }
+            Err(ref err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+
+    dev.set_crtc(crtc.id(), Some(old_fb), 0, 0, &[connector.id()], Some(&mode))?;
+    Ok(())
+}
@@ -1,6 +1,8 @@
 
 extern crate drm;
 
+use drm::fourcc::FourCC;
+use drm::pixels::Xrgb8888;
 use std::io::Result as IoResult;
 use std::thread::sleep;
 use std::time::Duration;
@@ -38,9 +40,10 @@ fn main() -> IoResult<()>
     let mode = crtc.mode().expect("mode")
         .clone();
 
-    let mut buffer = drm::mode::DumbBuf::create_with_depth(
-        &dev,
-        mode.hdisplay as u32, mode.vdisplay as u32, 32, 32)
+    let mut buffer = drm::mode::DumbBufOptions::new(&dev)
+        .width(mode.hdisplay as u32).height(mode.vdisplay as u32)
+        .format(FourCC::XRGB8888)
+        .create()
         .expect("creating buffer");
 
     dev.set_crtc(crtc.id(),  Some(buffer.fb().id()),
@@ -49,7 +52,9 @@ fn main() -> IoResult<()>
                  Some(&mode))
         .expect("set_crtc 1");
     
-    fill_buffer(&mut buffer);
+    buffer.pixels::<Xrgb8888>()
+        .expect("buffer is XRGB8888")
+        .fill(0xffff00ff);
 
     sleep(Duration::new(1, 0));
 
@@ -61,12 +66,3 @@ fn main() -> IoResult<()>
     
     Ok(())
 }
-
-
-fn fill_buffer<B:AsMut<[u32]>>(mut buffer_ref: B) {
-    let mut buffer = buffer_ref.as_mut();
-
-    for p in buffer.iter_mut() {
-        *p = 0xffff00ff;
-    }
-}
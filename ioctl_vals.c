@@ -1,5 +1,7 @@
 
 #include <libdrm/drm.h>
+#include <linux/dma-buf.h>
+#include <linux/sync_file.h>
 #include <stdio.h>
 
 int main()
@@ -69,6 +71,8 @@ int main()
     printf("pub const DRM_IOCTL_SG_FREE: c_ulong = %lu;\n", DRM_IOCTL_SG_FREE);
     printf("\n");
     printf("pub const DRM_IOCTL_WAIT_VBLANK: c_ulong = %lu;\n", DRM_IOCTL_WAIT_VBLANK);
+    printf("pub const DRM_IOCTL_CRTC_GET_SEQUENCE: c_ulong = %lu;\n", DRM_IOCTL_CRTC_GET_SEQUENCE);
+    printf("pub const DRM_IOCTL_CRTC_QUEUE_SEQUENCE: c_ulong = %lu;\n", DRM_IOCTL_CRTC_QUEUE_SEQUENCE);
     printf("\n");
     printf("pub const DRM_IOCTL_UPDATE_DRAW: c_ulong = %lu;\n", DRM_IOCTL_UPDATE_DRAW);
     printf("\n");
@@ -105,5 +109,20 @@ int main()
     printf("pub const DRM_IOCTL_MODE_ATOMIC: c_ulong = %lu;\n", DRM_IOCTL_MODE_ATOMIC);
     printf("pub const DRM_IOCTL_MODE_CREATEPROPBLOB: c_ulong = %lu;\n", DRM_IOCTL_MODE_CREATEPROPBLOB);
     printf("pub const DRM_IOCTL_MODE_DESTROYPROPBLOB: c_ulong = %lu;\n", DRM_IOCTL_MODE_DESTROYPROPBLOB);
+    printf("pub const DRM_IOCTL_SYNCOBJ_CREATE: c_ulong = %lu;\n", DRM_IOCTL_SYNCOBJ_CREATE);
+    printf("pub const DRM_IOCTL_SYNCOBJ_DESTROY: c_ulong = %lu;\n", DRM_IOCTL_SYNCOBJ_DESTROY);
+    printf("pub const DRM_IOCTL_SYNCOBJ_HANDLE_TO_FD: c_ulong = %lu;\n", DRM_IOCTL_SYNCOBJ_HANDLE_TO_FD);
+    printf("pub const DRM_IOCTL_SYNCOBJ_FD_TO_HANDLE: c_ulong = %lu;\n", DRM_IOCTL_SYNCOBJ_FD_TO_HANDLE);
+    printf("pub const DRM_IOCTL_SYNCOBJ_WAIT: c_ulong = %lu;\n", DRM_IOCTL_SYNCOBJ_WAIT);
+    printf("pub const DRM_IOCTL_SYNCOBJ_RESET: c_ulong = %lu;\n", DRM_IOCTL_SYNCOBJ_RESET);
+    printf("pub const DRM_IOCTL_SYNCOBJ_SIGNAL: c_ulong = %lu;\n", DRM_IOCTL_SYNCOBJ_SIGNAL);
+    printf("pub const DRM_IOCTL_SYNCOBJ_TIMELINE_WAIT: c_ulong = %lu;\n", DRM_IOCTL_SYNCOBJ_TIMELINE_WAIT);
+    printf("pub const DRM_IOCTL_SYNCOBJ_QUERY: c_ulong = %lu;\n", DRM_IOCTL_SYNCOBJ_QUERY);
+    printf("pub const DRM_IOCTL_SYNCOBJ_TRANSFER: c_ulong = %lu;\n", DRM_IOCTL_SYNCOBJ_TRANSFER);
+    printf("pub const DRM_IOCTL_SYNCOBJ_TIMELINE_SIGNAL: c_ulong = %lu;\n", DRM_IOCTL_SYNCOBJ_TIMELINE_SIGNAL);
+    printf("\n");
+    printf("pub const DMA_BUF_IOCTL_SYNC: c_ulong = %lu;\n", DMA_BUF_IOCTL_SYNC);
+    printf("pub const SYNC_IOC_MERGE: c_ulong = %lu;\n", SYNC_IOC_MERGE);
+    printf("pub const SYNC_IOC_FILE_INFO: c_ulong = %lu;\n", SYNC_IOC_FILE_INFO);
     return 0;
 }
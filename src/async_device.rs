@@ -0,0 +1,190 @@
+//! The part of reading events asynchronously that doesn't depend on the
+//! runtime.
+//!
+//! `AsyncDevice` keeps the queue of events and the page flips and
+//! vblanks being waited for. All it needs from a reactor is a way to
+//! wait for the device to be readable, which is `Readiness`. Use
+//! `drm::tokio::AsyncDevice` or `drm::async_io::AsyncDevice`.
+
+use Device;
+use event::{Completion, Event, EventQueue};
+use mode::{self, Crtc, Fb, Id, PageFlipFlags, PAGE_FLIP_EVENT};
+use vblank::Target;
+use futures_core::Stream;
+
+use std::future::Future;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// A non-blocking device registered with a reactor.
+pub trait Readiness {
+    fn get_ref(&self) -> &Device;
+
+    /// Read whatever events are available, waiting for the device to be
+    /// readable if there aren't any.
+    fn poll_read_events(&mut self, cx: &mut Context) -> Poll<io::Result<Vec<Event>>>;
+}
+
+/// A device whose events are read asynchronously.
+///
+/// Events read while waiting for a page flip or vblank are kept, and
+/// come out of the stream in order.
+#[derive(Debug)]
+pub struct AsyncDevice<R> {
+    pub(crate) io: R,
+    queue: EventQueue,
+    master: bool,
+}
+
+impl<R: Readiness> AsyncDevice<R> {
+    pub(crate) fn with_io(io: R) -> AsyncDevice<R> {
+        AsyncDevice {
+            io,
+            queue: EventQueue::default(),
+            master: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &Device {
+        self.io.get_ref()
+    }
+
+    /// Become card master, as `Device::set_master`, until
+    /// `drop_master` or the device is closed. Needed for `page_flip`.
+    pub fn set_master(&mut self) -> io::Result<()> {
+        if !self.master {
+            self.get_ref().acquire_master()?;
+            self.master = true;
+        }
+        Ok(())
+    }
+
+    /// Stop being card master.
+    pub fn drop_master(&mut self) {
+        if self.master {
+            self.get_ref().release_master();
+            self.master = false;
+        }
+    }
+
+    /// Wait for the next event.
+    pub fn next_event(&mut self) -> NextEvent<'_, R> {
+        NextEvent { dev: self }
+    }
+
+    /// Page flip (see `Master::page_flip`), and wait for the flip to
+    /// happen. Needs `set_master`.
+    ///
+    /// If the future is dropped before the flip, its event comes out of
+    /// the stream instead.
+    pub fn page_flip(&mut self, crtc_id: Id<Crtc>, fb_id: Id<Fb>, flags: PageFlipFlags)
+                     -> WaitCompletion<'_, R>
+    {
+        let token = self.queue.token();
+        let flipped = mode::page_flip(self.get_ref(), crtc_id, fb_id,
+                                      flags | PAGE_FLIP_EVENT, token);
+        WaitCompletion { dev: self, token: flipped.map(|_| token).map_err(Some) }
+    }
+
+    /// Wait for the next vblank on a CRTC.
+    pub fn next_vblank(&mut self, crtc: Id<Crtc>) -> WaitCompletion<'_, R> {
+        let token = self.queue.token();
+        let requested = self.get_ref()
+            .request_vblank_event(crtc, Target::Relative(1), false, token);
+        WaitCompletion { dev: self, token: requested.map(|_| token).map_err(Some) }
+    }
+
+    /// Read whatever events are available into the queue.
+    fn poll_read(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+        match self.io.poll_read_events(cx) {
+            Poll::Ready(Ok(events)) => {
+                self.queue.extend(events);
+                Poll::Ready(Ok(()))
+            }
+            Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn poll_event(&mut self, cx: &mut Context) -> Poll<io::Result<Event>> {
+        loop {
+            if let Some(event) = self.queue.pop() {
+                return Poll::Ready(Ok(event));
+            }
+            match self.poll_read(cx) {
+                Poll::Ready(Ok(())) => (),
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    fn poll_completion(&mut self, token: u64, cx: &mut Context) -> Poll<io::Result<Completion>> {
+        loop {
+            if let Some(completion) = self.queue.take(token) {
+                return Poll::Ready(Ok(completion));
+            }
+            match self.poll_read(cx) {
+                Poll::Ready(Ok(())) => (),
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<R: Readiness> AsRawFd for AsyncDevice<R> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.get_ref().as_raw_fd()
+    }
+}
+
+/// Never ends. Errors reading the device are yielded, and reading
+/// carries on after them.
+impl<R: Readiness + Unpin> Stream for AsyncDevice<R> {
+    type Item = io::Result<Event>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<io::Result<Event>>> {
+        self.get_mut().poll_event(cx).map(Some)
+    }
+}
+
+/// Future for `AsyncDevice::next_event`.
+#[derive(Debug)]
+pub struct NextEvent<'a, R: 'a> {
+    dev: &'a mut AsyncDevice<R>,
+}
+
+impl<'a, R: Readiness> Future for NextEvent<'a, R> {
+    type Output = io::Result<Event>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<Event>> {
+        self.dev.poll_event(cx)
+    }
+}
+
+/// Future for `AsyncDevice::page_flip` and `next_vblank`. Resolves
+/// when the event for the request arrives.
+#[derive(Debug)]
+pub struct WaitCompletion<'a, R: 'a> {
+    dev: &'a mut AsyncDevice<R>,
+    // The error from making the request, until it's returned.
+    token: Result<u64, Option<io::Error>>,
+}
+
+impl<'a, R: Readiness> Future for WaitCompletion<'a, R> {
+    type Output = io::Result<Completion>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<Completion>> {
+        let this = &mut *self;
+        match this.token {
+            Ok(token) => this.dev.poll_completion(token, cx),
+            Err(ref mut err) => {
+                let err = err.take().expect("Polled WaitCompletion after it failed");
+                Poll::Ready(Err(err))
+            }
+        }
+    }
+}
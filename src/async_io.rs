@@ -0,0 +1,61 @@
+//! Reading events with async-io (and so smol).
+//!
+//! The same as `drm::tokio`, on async-io's reactor instead:
+//!
+//! ```ignore
+//! let mut dev = AsyncDevice::new(Device::first_card()?)?;
+//! dev.set_master()?;
+//! let frame = dev.page_flip(crtc, fb, PageFlipFlags::empty()).await?;
+//! ```
+
+use Device;
+use async_device::{self, Readiness};
+use event::Event;
+use async_io_rt::Async;
+
+use std::io::{self, ErrorKind};
+use std::task::{Context, Poll};
+
+/// A device whose events are read asynchronously.
+pub type AsyncDevice = async_device::AsyncDevice<Async<Device>>;
+/// Future for `AsyncDevice::next_event`.
+pub type NextEvent<'a> = async_device::NextEvent<'a, Async<Device>>;
+/// Future for `AsyncDevice::page_flip` and `next_vblank`.
+pub type WaitCompletion<'a> = async_device::WaitCompletion<'a, Async<Device>>;
+
+impl AsyncDevice {
+    /// Make `dev` non-blocking and register it with async-io's
+    /// reactor.
+    pub fn new(dev: Device) -> io::Result<AsyncDevice> {
+        Ok(AsyncDevice::with_io(Async::new(dev)?))
+    }
+
+    /// Give back the device. It's left non-blocking, and still master
+    /// if it was. Events not read yet are lost.
+    pub fn into_inner(self) -> io::Result<Device> {
+        self.io.into_inner()
+    }
+}
+
+impl Readiness for Async<Device> {
+    fn get_ref(&self) -> &Device {
+        Async::get_ref(self)
+    }
+
+    fn poll_read_events(&mut self, cx: &mut Context) -> Poll<io::Result<Vec<Event>>> {
+        loop {
+            // Safe: reading events doesn't close or replace the fd.
+            match unsafe { self.get_mut() }.read_events() {
+                Err(ref err) if err.kind() == ErrorKind::Interrupted => (),
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock => {
+                    match self.poll_readable(cx) {
+                        Poll::Ready(Ok(())) => (),
+                        Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                        Poll::Pending => return Poll::Pending,
+                    }
+                }
+                result => return Poll::Ready(result),
+            }
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! A calloop event source for a device's events.
+//!
+//! ```ignore
+//! let source = DeviceSource::new(&dev)?;
+//! event_loop.handle().insert_source(source, |event, _, state| {
+//!     state.handle_drm_event(event);
+//! })?;
+//! ```
+
+use Device;
+use event::Event;
+use calloop_rt::{EventSource, Interest, Mode, Poll, PostAction, Readiness, Token, TokenFactory};
+use calloop_rt::generic::Generic;
+
+use std::io::{self, ErrorKind};
+use std::os::unix::io::{AsFd, FromRawFd, IntoRawFd};
+
+/// Emits the events read from a device.
+///
+/// Reads from a duplicate of the device's fd, so the device is still
+/// free for making requests (including becoming master). It's the same
+/// open file, so the events for those requests come here, and the
+/// device is made non-blocking too.
+#[derive(Debug)]
+pub struct DeviceSource {
+    source: Generic<Device>,
+}
+
+impl DeviceSource {
+    pub fn new(dev: &Device) -> io::Result<DeviceSource> {
+        let fd = dev.as_fd().try_clone_to_owned()?;
+        let mut reader = unsafe { Device::from_raw_fd(fd.into_raw_fd()) };
+        reader.set_nonblocking(true)?;
+        Ok(DeviceSource { source: Generic::new(reader, Interest::READ, Mode::Level) })
+    }
+}
+
+impl EventSource for DeviceSource {
+    type Event = Event;
+    type Metadata = ();
+    type Ret = ();
+    type Error = io::Error;
+
+    fn process_events<F>(&mut self, readiness: Readiness, token: Token, mut callback: F)
+                         -> Result<PostAction, io::Error>
+        where F: FnMut(Event, &mut ())
+    {
+        self.source.process_events(readiness, token, |_, reader| {
+            // Safe: reading events doesn't close or replace the fd.
+            let reader = unsafe { reader.get_mut() };
+            loop {
+                match reader.read_events() {
+                    Ok(events) => {
+                        for event in events {
+                            callback(event, &mut ());
+                        }
+                    }
+                    Err(ref err) if err.kind() == ErrorKind::Interrupted => (),
+                    Err(ref err) if err.kind() == ErrorKind::WouldBlock => break,
+                    Err(err) => return Err(err),
+                }
+            }
+            Ok(PostAction::Continue)
+        })
+    }
+
+    fn register(&mut self, poll: &mut Poll, token_factory: &mut TokenFactory)
+                -> calloop_rt::Result<()>
+    {
+        self.source.register(poll, token_factory)
+    }
+
+    fn reregister(&mut self, poll: &mut Poll, token_factory: &mut TokenFactory)
+                  -> calloop_rt::Result<()>
+    {
+        self.source.reregister(poll, token_factory)
+    }
+
+    fn unregister(&mut self, poll: &mut Poll) -> calloop_rt::Result<()> {
+        self.source.unregister(poll)
+    }
+}
@@ -0,0 +1,764 @@
+//! Software pixel format conversion.
+//!
+//! Converts images between `FourCC` formats on the CPU. This is meant
+//! as a fallback: for when a renderer draws in one format but the
+//! hardware only scans out another, or when a capture path hands us
+//! YUV and we want RGB.
+//!
+//! Conversions go through 16 bits per channel, so nothing is lost
+//! going between formats of 16 bits or less per channel. YUV <-> RGB
+//! conversions use the color encoding and range given in `Options`.
+//! When the destination has fewer bits per channel than the source
+//! an ordered dither is applied (unless disabled).
+//!
+//! Not every format is supported. Palette (C8) and floating point
+//! formats can't be converted. Use `is_supported` to check.
+
+use std::io;
+use fourcc::FourCC;
+
+/// A read only image in memory.
+///
+/// Each plane is a byte slice and its pitch (bytes between the start
+/// of each row).
+#[derive(Debug, Clone)]
+pub struct Image<'a> {
+    format: FourCC,
+    width: u32,
+    height: u32,
+    planes: Vec<(&'a [u8], usize)>,
+}
+
+/// A writable image in memory.
+#[derive(Debug)]
+pub struct ImageMut<'a> {
+    format: FourCC,
+    width: u32,
+    height: u32,
+    planes: Vec<(&'a mut [u8], usize)>,
+}
+
+fn invalid_input(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg.to_string())
+}
+
+/// Checks that the planes are big enough to hold an image.
+fn check_planes(format: FourCC, width: u32, height: u32,
+                planes: &[(usize, usize)]) -> io::Result<()> {
+    if layout(format).is_none() {
+        return Err(invalid_input("Pixel format not supported for conversion"));
+    }
+    let info = format.info().expect("supported formats have info");
+    if planes.len() != info.num_planes() {
+        return Err(invalid_input("Wrong number of planes for pixel format"));
+    }
+    for (i, &(len, pitch)) in planes.iter().enumerate() {
+        let row = info.min_pitch(i, width) as usize;
+        let rows = info.plane_height(i, height) as usize;
+        if pitch < row {
+            return Err(invalid_input("Pitch too small for image width"));
+        }
+        if rows > 0 && len < pitch * (rows - 1) + row {
+            return Err(invalid_input("Plane too small for image"));
+        }
+    }
+    Ok(())
+}
+
+/// Byte offsets of (x, y) in each plane, if it starts a block in every
+/// plane.
+// is_multiple_of needs a newer Rust than the rest of the crate.
+#[allow(unknown_lints, clippy::manual_is_multiple_of)]
+fn crop_offsets(format: FourCC, pitches: &[usize], x: u32, y: u32) -> io::Result<Vec<usize>> {
+    let info = format.info().expect("supported formats have info");
+    let (bw, bh) = info.block_size();
+    let (hsub, vsub) = (info.hsub(), info.vsub());
+    let (ax, ay) = if info.num_planes() > 1 { (hsub * bw, vsub * bh) } else { (bw, bh) };
+    if x % ax != 0 || y % ay != 0 {
+        return Err(invalid_input("Crop doesn't start on a pixel block"));
+    }
+    Ok(pitches.iter().enumerate().map(|(i, &pitch)| {
+        let (px, py) = if i == 0 { (x, y) } else { (x / hsub, y / vsub) };
+        (py / bh) as usize * pitch + (px / bw * info.bytes_per_block(i)) as usize
+    }).collect())
+}
+
+fn check_crop(size: (u32, u32), x: u32, y: u32, width: u32, height: u32) -> io::Result<()> {
+    let fits = |at: u32, len: u32, max: u32| at.checked_add(len).is_some_and(|end| end <= max);
+    if fits(x, width, size.0) && fits(y, height, size.1) {
+        Ok(())
+    } else {
+        Err(invalid_input("Crop is outside the image"))
+    }
+}
+
+impl<'a> Image<'a> {
+    /// Describe an image. `planes` is a list of (data, pitch) for each
+    /// plane of the format.
+    ///
+    /// # Errors
+    ///
+    /// `ErrorKind::InvalidInput` if the format isn't supported or the
+    /// planes don't fit the image.
+    pub fn new(format: FourCC, width: u32, height: u32,
+               planes: Vec<(&'a [u8], usize)>) -> io::Result<Image<'a>> {
+        let sizes: Vec<_> = planes.iter().map(|&(d, p)| (d.len(), p)).collect();
+        check_planes(format, width, height, &sizes)?;
+        Ok(Image { format, width, height, planes })
+    }
+
+    /// Describe an image with a single plane.
+    pub fn packed(format: FourCC, width: u32, height: u32,
+                  data: &'a [u8], pitch: usize) -> io::Result<Image<'a>> {
+        Image::new(format, width, height, vec![(data, pitch)])
+    }
+
+    pub fn format(&self) -> FourCC { self.format }
+
+    /// (width, height) in pixels.
+    pub fn size(&self) -> (u32, u32) { (self.width, self.height) }
+
+    /// The `width` x `height` part of the image at (x, y).
+    ///
+    /// # Errors
+    ///
+    /// `ErrorKind::InvalidInput` if the rectangle is outside the image,
+    /// or (x, y) would split a block of subsampled pixels.
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> io::Result<Image<'_>> {
+        check_crop(self.size(), x, y, width, height)?;
+        let pitches: Vec<_> = self.planes.iter().map(|&(_, p)| p).collect();
+        let offsets = crop_offsets(self.format, &pitches, x, y)?;
+        let planes = self.planes.iter().zip(offsets)
+            .map(|(&(data, pitch), offset)| (&data[offset..], pitch))
+            .collect();
+        Ok(Image { format: self.format, width, height, planes })
+    }
+
+    pub(crate) fn layout(&self) -> Layout {
+        layout(self.format).expect("checked by Image::new")
+    }
+
+    /// Decode `out.len()` pixels of row `y`, starting at `x`.
+    /// Only for `Layout::Bits` formats.
+    pub(crate) fn read_pixels(&self, x: usize, y: usize, out: &mut [Px]) {
+        match self.layout() {
+            Layout::Bits { bytes, c, a, .. } => {
+                let (data, pitch) = self.planes[0];
+                decode_bits(bytes, c, a, &data[y * pitch + x * bytes..], out);
+            }
+            _ => panic!("read_pixels on a subsampled format"),
+        }
+    }
+}
+
+impl<'a> ImageMut<'a> {
+    /// Describe a writable image. See `Image::new`.
+    pub fn new(format: FourCC, width: u32, height: u32,
+               planes: Vec<(&'a mut [u8], usize)>) -> io::Result<ImageMut<'a>> {
+        let sizes: Vec<_> = planes.iter().map(|&(ref d, p)| (d.len(), p)).collect();
+        check_planes(format, width, height, &sizes)?;
+        Ok(ImageMut { format, width, height, planes })
+    }
+
+    /// Describe a writable image with a single plane.
+    pub fn packed(format: FourCC, width: u32, height: u32,
+                  data: &'a mut [u8], pitch: usize) -> io::Result<ImageMut<'a>> {
+        ImageMut::new(format, width, height, vec![(data, pitch)])
+    }
+
+    pub fn format(&self) -> FourCC { self.format }
+
+    /// (width, height) in pixels.
+    pub fn size(&self) -> (u32, u32) { (self.width, self.height) }
+
+    /// The `width` x `height` part of the image at (x, y). See
+    /// `Image::crop`.
+    pub fn crop_mut(&mut self, x: u32, y: u32, width: u32, height: u32)
+                    -> io::Result<ImageMut<'_>> {
+        let image = ImageMut {
+            format: self.format,
+            width: self.width,
+            height: self.height,
+            planes: self.planes.iter_mut().map(|&mut (ref mut d, p)| (&mut d[..], p)).collect(),
+        };
+        image.into_crop(x, y, width, height)
+    }
+
+    /// Like `crop_mut`, but keeps the whole borrow.
+    pub fn into_crop(self, x: u32, y: u32, width: u32, height: u32)
+                     -> io::Result<ImageMut<'a>> {
+        check_crop(self.size(), x, y, width, height)?;
+        let pitches: Vec<_> = self.planes.iter().map(|&(_, p)| p).collect();
+        let offsets = crop_offsets(self.format, &pitches, x, y)?;
+        let planes = self.planes.into_iter().zip(offsets)
+            .map(|((data, pitch), offset)| (&mut data[offset..], pitch))
+            .collect();
+        Ok(ImageMut { format: self.format, width, height, planes })
+    }
+
+    pub(crate) fn planes_mut(&mut self) -> &mut [(&'a mut [u8], usize)] {
+        &mut self.planes
+    }
+
+    /// Encode pixels into row `y`, starting at `x`. Only for
+    /// `Layout::Bits` formats.
+    pub(crate) fn write_pixels(&mut self, x: usize, y: usize, pixels: &[Px]) {
+        match layout(self.format).expect("checked by ImageMut::new") {
+            Layout::Bits { bytes, c, a, .. } => {
+                let (ref mut data, pitch) = self.planes[0];
+                encode_bits(bytes, c, a, &mut data[y * pitch + x * bytes..], pixels, None);
+            }
+            _ => panic!("write_pixels on a subsampled format"),
+        }
+    }
+
+    /// Borrow as a read only image.
+    pub fn as_image(&self) -> Image<'_> {
+        Image {
+            format: self.format,
+            width: self.width,
+            height: self.height,
+            planes: self.planes.iter().map(|&(ref d, p)| (&d[..], p)).collect(),
+        }
+    }
+}
+
+/// Matrix used between RGB and YCbCr.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ColorEncoding {
+    /// ITU-R BT.601. (SD video)
+    Bt601,
+    /// ITU-R BT.709. (HD video)
+    Bt709,
+    /// ITU-R BT.2020. (UHD video)
+    Bt2020,
+}
+
+impl ColorEncoding {
+    // (Kr, Kb)
+    fn coefficients(&self) -> (f32, f32) {
+        match *self {
+            ColorEncoding::Bt601 => (0.299, 0.114),
+            ColorEncoding::Bt709 => (0.2126, 0.0722),
+            ColorEncoding::Bt2020 => (0.2627, 0.0593),
+        }
+    }
+}
+
+/// Range of values used by YCbCr samples.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ColorRange {
+    /// Y in 16..235 and Cb/Cr in 16..240. (For 8 bits, scaled up for more).
+    Limited,
+    /// The whole range of the sample.
+    Full,
+}
+
+/// How to convert.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Options {
+    /// Matrix of the YCbCr side of a conversion.
+    pub encoding: ColorEncoding,
+    /// Range of the YCbCr side of a conversion.
+    pub range: ColorRange,
+    /// Dither when the destination has fewer bits per channel.
+    pub dither: bool,
+}
+
+impl Default for Options {
+    /// BT.601, limited range, with dithering.
+    fn default() -> Options {
+        Options {
+            encoding: ColorEncoding::Bt601,
+            range: ColorRange::Limited,
+            dither: true,
+        }
+    }
+}
+
+/// Can images of this format be converted?
+pub fn is_supported(format: FourCC) -> bool {
+    layout(format).is_some()
+}
+
+/// Convert `src` into `dst`.
+///
+/// Both images must be the same size.
+pub fn convert(src: &Image, dst: &mut ImageMut, opts: &Options) -> io::Result<()> {
+    if src.size() != dst.size() {
+        return Err(invalid_input("Source and destination are different sizes"));
+    }
+    let src_layout = layout(src.format).expect("checked by Image::new");
+    let dst_layout = layout(dst.format).expect("checked by ImageMut::new");
+    let (width, height) = (src.width as usize, src.height as usize);
+
+    let dither = opts.dither && dst_layout.precision() < src_layout.precision();
+    let band = dst_layout.vsub();
+    let mut rows: Vec<Vec<Px>> = vec![vec![[0; 4]; width]; band];
+
+    let mut y = 0;
+    while y < height {
+        let n = band.min(height - y);
+        for (i, row) in rows.iter_mut().take(n).enumerate() {
+            decode_row(src_layout, &src.planes, y + i, row);
+            if src_layout.is_yuv() != dst_layout.is_yuv() {
+                for px in row.iter_mut() {
+                    *px = if src_layout.is_yuv() {
+                        yuv_to_rgb(*px, opts)
+                    } else {
+                        rgb_to_yuv(*px, opts)
+                    };
+                }
+            }
+        }
+        encode_rows(dst_layout, &mut dst.planes, y, &rows[..n], dither);
+        y += n;
+    }
+    Ok(())
+}
+
+/// A pixel in the middle of conversion: 3 color channels and alpha,
+/// each 16 bits. The color channels are either R, G, B or Y, Cb, Cr.
+pub(crate) type Px = [u16; 4];
+
+/// Where the samples of a format live.
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum Layout {
+    /// Every pixel is a little endian word of `bytes` bytes. The color
+    /// channels and alpha are (shift, bits) bitfields of it. Unused
+    /// channels have 0 bits.
+    Bits { bytes: usize, c: [(u8, u8); 3], a: (u8, u8), yuv: bool },
+    /// Packed 4:2:2. Two pixels share 4 samples of `sample_bytes`,
+    /// Y0 and Y1 are at `y`. Only the top `bits` bits of each sample
+    /// are used.
+    Packed422 { sample_bytes: usize, bits: u8, y: [usize; 2], cb: usize, cr: usize },
+    /// Y plane followed by an interleaved Cb/Cr plane.
+    SemiPlanar { sample_bytes: usize, bits: u8, hsub: usize, vsub: usize, cb_first: bool },
+    /// Separate Y, Cb and Cr planes of 8 bit samples.
+    Planar { hsub: usize, vsub: usize, cb_plane: usize },
+}
+
+/// Builds a `Layout::Bits` from a description like the comments in
+/// `drm_fourcc.h`: channel letters from most to least significant
+/// and their widths. (eg. "XRGB", [2, 10, 10, 10]).
+///
+/// R/Y, G/U, B/V and A are channels. X and x are padding.
+fn bits(bytes: usize, channels: &str, widths: &[u8], yuv: bool) -> Layout {
+    let mut c = [(0, 0); 3];
+    let mut a = (0, 0);
+    let mut shift: u8 = widths.iter().sum();
+    for (ch, &w) in channels.chars().zip(widths) {
+        shift -= w;
+        match ch {
+            'R' | 'Y' => c[0] = (shift, w),
+            'G' | 'U' => c[1] = (shift, w),
+            'B' | 'V' => c[2] = (shift, w),
+            'A' => a = (shift, w),
+            _ => (),
+        }
+    }
+    Layout::Bits { bytes, c, a, yuv }
+}
+
+pub(crate) fn layout(format: FourCC) -> Option<Layout> {
+    use fourcc::FourCC::*;
+    let rgb = |bytes, channels, widths: &[u8]| bits(bytes, channels, widths, false);
+    let yuv = |bytes, channels, widths: &[u8]| bits(bytes, channels, widths, true);
+    Some(match format {
+        R8 => rgb(1, "R", &[8]),
+        R16 => rgb(2, "R", &[16]),
+        RG88 => rgb(2, "RG", &[8, 8]),
+        GR88 => rgb(2, "GR", &[8, 8]),
+        RG1616 => rgb(4, "RG", &[16, 16]),
+        GR1616 => rgb(4, "GR", &[16, 16]),
+
+        RGB332 => rgb(1, "RGB", &[3, 3, 2]),
+        BGR233 => rgb(1, "BGR", &[2, 3, 3]),
+
+        XRGB4444 => rgb(2, "XRGB", &[4, 4, 4, 4]),
+        XBGR4444 => rgb(2, "XBGR", &[4, 4, 4, 4]),
+        RGBX4444 => rgb(2, "RGBX", &[4, 4, 4, 4]),
+        BGRX4444 => rgb(2, "BGRX", &[4, 4, 4, 4]),
+        ARGB4444 => rgb(2, "ARGB", &[4, 4, 4, 4]),
+        ABGR4444 => rgb(2, "ABGR", &[4, 4, 4, 4]),
+        RGBA4444 => rgb(2, "RGBA", &[4, 4, 4, 4]),
+        BGRA4444 => rgb(2, "BGRA", &[4, 4, 4, 4]),
+
+        XRGB1555 => rgb(2, "XRGB", &[1, 5, 5, 5]),
+        XBGR1555 => rgb(2, "XBGR", &[1, 5, 5, 5]),
+        RGBX5551 => rgb(2, "RGBX", &[5, 5, 5, 1]),
+        BGRX5551 => rgb(2, "BGRX", &[5, 5, 5, 1]),
+        ARGB1555 => rgb(2, "ARGB", &[1, 5, 5, 5]),
+        ABGR1555 => rgb(2, "ABGR", &[1, 5, 5, 5]),
+        RGBA5551 => rgb(2, "RGBA", &[5, 5, 5, 1]),
+        BGRA5551 => rgb(2, "BGRA", &[5, 5, 5, 1]),
+
+        RGB565 => rgb(2, "RGB", &[5, 6, 5]),
+        BGR565 => rgb(2, "BGR", &[5, 6, 5]),
+
+        RGB888 => rgb(3, "RGB", &[8, 8, 8]),
+        BGR888 => rgb(3, "BGR", &[8, 8, 8]),
+
+        XRGB8888 => rgb(4, "XRGB", &[8, 8, 8, 8]),
+        XBGR8888 => rgb(4, "XBGR", &[8, 8, 8, 8]),
+        RGBX8888 => rgb(4, "RGBX", &[8, 8, 8, 8]),
+        BGRX8888 => rgb(4, "BGRX", &[8, 8, 8, 8]),
+        ARGB8888 => rgb(4, "ARGB", &[8, 8, 8, 8]),
+        ABGR8888 => rgb(4, "ABGR", &[8, 8, 8, 8]),
+        RGBA8888 => rgb(4, "RGBA", &[8, 8, 8, 8]),
+        BGRA8888 => rgb(4, "BGRA", &[8, 8, 8, 8]),
+
+        XRGB2101010 => rgb(4, "XRGB", &[2, 10, 10, 10]),
+        XBGR2101010 => rgb(4, "XBGR", &[2, 10, 10, 10]),
+        RGBX1010102 => rgb(4, "RGBX", &[10, 10, 10, 2]),
+        BGRX1010102 => rgb(4, "BGRX", &[10, 10, 10, 2]),
+        ARGB2101010 => rgb(4, "ARGB", &[2, 10, 10, 10]),
+        ABGR2101010 => rgb(4, "ABGR", &[2, 10, 10, 10]),
+        RGBA1010102 => rgb(4, "RGBA", &[10, 10, 10, 2]),
+        BGRA1010102 => rgb(4, "BGRA", &[10, 10, 10, 2]),
+
+        XRGB16161616 => rgb(8, "XRGB", &[16, 16, 16, 16]),
+        XBGR16161616 => rgb(8, "XBGR", &[16, 16, 16, 16]),
+        ARGB16161616 => rgb(8, "ARGB", &[16, 16, 16, 16]),
+        ABGR16161616 => rgb(8, "ABGR", &[16, 16, 16, 16]),
+
+        AYUV => yuv(4, "AYUV", &[8, 8, 8, 8]),
+        XYUV8888 => yuv(4, "XYUV", &[8, 8, 8, 8]),
+        VUY888 => yuv(3, "VUY", &[8, 8, 8]),
+        XVYU2101010 => yuv(4, "XVYU", &[2, 10, 10, 10]),
+        Y410 => yuv(4, "AVYU", &[2, 10, 10, 10]),
+        Y412 => yuv(8, "AxVxYxUx", &[12, 4, 12, 4, 12, 4, 12, 4]),
+        Y416 => yuv(8, "AVYU", &[16, 16, 16, 16]),
+
+        YUYV => Layout::Packed422 { sample_bytes: 1, bits: 8, y: [0, 2], cb: 1, cr: 3 },
+        YVYU => Layout::Packed422 { sample_bytes: 1, bits: 8, y: [0, 2], cb: 3, cr: 1 },
+        UYVY => Layout::Packed422 { sample_bytes: 1, bits: 8, y: [1, 3], cb: 0, cr: 2 },
+        VYUY => Layout::Packed422 { sample_bytes: 1, bits: 8, y: [1, 3], cb: 2, cr: 0 },
+        Y210 => Layout::Packed422 { sample_bytes: 2, bits: 10, y: [0, 2], cb: 1, cr: 3 },
+        Y212 => Layout::Packed422 { sample_bytes: 2, bits: 12, y: [0, 2], cb: 1, cr: 3 },
+        Y216 => Layout::Packed422 { sample_bytes: 2, bits: 16, y: [0, 2], cb: 1, cr: 3 },
+
+        NV12 => semi_planar(1, 8, 2, 2, true),
+        NV21 => semi_planar(1, 8, 2, 2, false),
+        NV16 => semi_planar(1, 8, 2, 1, true),
+        NV61 => semi_planar(1, 8, 2, 1, false),
+        NV24 => semi_planar(1, 8, 1, 1, true),
+        NV42 => semi_planar(1, 8, 1, 1, false),
+        P010 => semi_planar(2, 10, 2, 2, true),
+        P012 => semi_planar(2, 12, 2, 2, true),
+        P016 => semi_planar(2, 16, 2, 2, true),
+        P210 => semi_planar(2, 10, 2, 1, true),
+
+        YUV410 => Layout::Planar { hsub: 4, vsub: 4, cb_plane: 1 },
+        YVU410 => Layout::Planar { hsub: 4, vsub: 4, cb_plane: 2 },
+        YUV411 => Layout::Planar { hsub: 4, vsub: 1, cb_plane: 1 },
+        YVU411 => Layout::Planar { hsub: 4, vsub: 1, cb_plane: 2 },
+        YUV420 => Layout::Planar { hsub: 2, vsub: 2, cb_plane: 1 },
+        YVU420 => Layout::Planar { hsub: 2, vsub: 2, cb_plane: 2 },
+        YUV422 => Layout::Planar { hsub: 2, vsub: 1, cb_plane: 1 },
+        YVU422 => Layout::Planar { hsub: 2, vsub: 1, cb_plane: 2 },
+        YUV444 => Layout::Planar { hsub: 1, vsub: 1, cb_plane: 1 },
+        YVU444 => Layout::Planar { hsub: 1, vsub: 1, cb_plane: 2 },
+
+        C8 | XRGB16161616F | XBGR16161616F | ARGB16161616F | ABGR16161616F |
+        Other(_) => return None,
+    })
+}
+
+fn semi_planar(sample_bytes: usize, bits: u8, hsub: usize, vsub: usize, cb_first: bool) -> Layout {
+    Layout::SemiPlanar { sample_bytes, bits, hsub, vsub, cb_first }
+}
+
+impl Layout {
+    pub(crate) fn is_yuv(&self) -> bool {
+        match *self {
+            Layout::Bits { yuv, .. } => yuv,
+            _ => true,
+        }
+    }
+
+    /// Rows that must be encoded together.
+    fn vsub(&self) -> usize {
+        match *self {
+            Layout::SemiPlanar { vsub, .. } | Layout::Planar { vsub, .. } => vsub,
+            _ => 1,
+        }
+    }
+
+    /// Most bits used by a color channel.
+    fn precision(&self) -> u8 {
+        match *self {
+            Layout::Bits { c, .. } => c.iter().map(|&(_, b)| b).max().unwrap_or(0),
+            Layout::Packed422 { bits, .. } | Layout::SemiPlanar { bits, .. } => bits,
+            Layout::Planar { .. } => 8,
+        }
+    }
+}
+
+/// Expand an n-bit value to 16 bits.
+fn expand(v: u64, bits: u8) -> u16 {
+    if bits == 0 {
+        0
+    } else if bits >= 16 {
+        v as u16
+    } else {
+        let max = (1u64 << bits) - 1;
+        ((v * 65535 + max / 2) / max) as u16
+    }
+}
+
+// 4x4 Bayer matrix.
+const BAYER: [[u8; 4]; 4] = [
+    [0, 8, 2, 10],
+    [12, 4, 14, 6],
+    [3, 11, 1, 9],
+    [15, 7, 13, 5],
+];
+
+/// Reduce a 16 bit value to n bits. If `dither` is given it's the
+/// (x, y) position of the pixel, used to pick an ordered dither offset.
+fn quantize(v: u16, bits: u8, dither: Option<(usize, usize)>) -> u64 {
+    if bits == 0 {
+        return 0;
+    }
+    if bits >= 16 {
+        return v as u64;
+    }
+    let max = (1u64 << bits) - 1;
+    match dither {
+        None => (v as u64 * max + 32767) / 65535,
+        Some((x, y)) => {
+            // Threshold in 1/32nds of a step, from -15 to +15.
+            let t = BAYER[y & 3][x & 3] as i64 * 2 - 15;
+            let scaled = v as i64 * max as i64 * 32 / 65535 + 16 + t;
+            (scaled.max(0) as u64 / 32).min(max)
+        }
+    }
+}
+
+fn read_le(bytes: &[u8]) -> u64 {
+    bytes.iter().rev().fold(0, |acc, &b| (acc << 8) | b as u64)
+}
+
+fn write_le(bytes: &mut [u8], v: u64) {
+    for (i, b) in bytes.iter_mut().enumerate() {
+        *b = (v >> (i * 8)) as u8;
+    }
+}
+
+/// Read a sample that keeps its value in the top `bits` of `size` bytes.
+fn read_sample(bytes: &[u8], at: usize, size: usize) -> u16 {
+    if size == 1 {
+        expand(bytes[at] as u64, 8)
+    } else {
+        read_le(&bytes[at..at + 2]) as u16
+    }
+}
+
+fn write_sample(bytes: &mut [u8], at: usize, size: usize, bits: u8, v: u16,
+                dither: Option<(usize, usize)>) {
+    let q = quantize(v, bits, dither);
+    if size == 1 {
+        bytes[at] = q as u8;
+    } else {
+        write_le(&mut bytes[at..at + 2], q << (16 - bits));
+    }
+}
+
+fn row<'a>(planes: &[(&'a [u8], usize)], plane: usize, y: usize) -> &'a [u8] {
+    let (data, pitch) = planes[plane];
+    &data[y * pitch..]
+}
+
+fn row_mut<'a>(planes: &'a mut [(&mut [u8], usize)], plane: usize, y: usize) -> &'a mut [u8] {
+    let (ref mut data, pitch) = planes[plane];
+    &mut data[y * pitch..]
+}
+
+fn decode_bits(bytes: usize, c: [(u8, u8); 3], a: (u8, u8), data: &[u8], out: &mut [Px]) {
+    for (x, px) in out.iter_mut().enumerate() {
+        let word = read_le(&data[x * bytes..(x + 1) * bytes]);
+        let field = |(shift, bits): (u8, u8)| {
+            expand((word >> shift) & ((1u64 << bits) - 1), bits)
+        };
+        *px = [field(c[0]), field(c[1]), field(c[2]),
+               if a.1 == 0 { 0xffff } else { field(a) }];
+    }
+}
+
+/// Encode a row of a `Layout::Bits` image. `dither_row` is the row
+/// number to dither with, if dithering.
+fn encode_bits(bytes: usize, c: [(u8, u8); 3], a: (u8, u8), data: &mut [u8], pixels: &[Px],
+               dither_row: Option<usize>) {
+    for (x, px) in pixels.iter().enumerate() {
+        let at = dither_row.map(|y| (x, y));
+        let field = |v: u16, (shift, bits): (u8, u8), d| quantize(v, bits, d) << shift;
+        let word = field(px[0], c[0], at)
+            | field(px[1], c[1], at)
+            | field(px[2], c[2], at)
+            | field(px[3], a, None);
+        write_le(&mut data[x * bytes..(x + 1) * bytes], word);
+    }
+}
+
+/// Decode a row of an image into 16 bit channels.
+pub(crate) fn decode_row(layout: Layout, planes: &[(&[u8], usize)], y: usize, out: &mut [Px]) {
+    match layout {
+        Layout::Bits { bytes, c, a, .. } => decode_bits(bytes, c, a, row(planes, 0, y), out),
+        Layout::Packed422 { sample_bytes, y: ys, cb, cr, .. } => {
+            let data = row(planes, 0, y);
+            for (x, px) in out.iter_mut().enumerate() {
+                let block = (x / 2) * 4 * sample_bytes;
+                let sample = |i: usize| read_sample(data, block + i * sample_bytes, sample_bytes);
+                *px = [sample(ys[x & 1]), sample(cb), sample(cr), 0xffff];
+            }
+        }
+        Layout::SemiPlanar { sample_bytes, hsub, vsub, cb_first, .. } => {
+            let luma = row(planes, 0, y);
+            let chroma = row(planes, 1, y / vsub);
+            let (cb, cr) = if cb_first { (0, 1) } else { (1, 0) };
+            for (x, px) in out.iter_mut().enumerate() {
+                let pair = (x / hsub) * 2 * sample_bytes;
+                *px = [read_sample(luma, x * sample_bytes, sample_bytes),
+                       read_sample(chroma, pair + cb * sample_bytes, sample_bytes),
+                       read_sample(chroma, pair + cr * sample_bytes, sample_bytes),
+                       0xffff];
+            }
+        }
+        Layout::Planar { hsub, vsub, cb_plane } => {
+            let luma = row(planes, 0, y);
+            let cb = row(planes, cb_plane, y / vsub);
+            let cr = row(planes, 3 - cb_plane, y / vsub);
+            for (x, px) in out.iter_mut().enumerate() {
+                *px = [expand(luma[x] as u64, 8),
+                       expand(cb[x / hsub] as u64, 8),
+                       expand(cr[x / hsub] as u64, 8),
+                       0xffff];
+            }
+        }
+    }
+}
+
+/// Average the chroma of the pixels in the block at (x, 0) of `rows`.
+fn chroma_average(rows: &[Vec<Px>], x: usize, hsub: usize) -> (u16, u16) {
+    let (mut cb, mut cr, mut n) = (0u32, 0u32, 0u32);
+    for row in rows {
+        for px in row.iter().skip(x).take(hsub) {
+            cb += px[1] as u32;
+            cr += px[2] as u32;
+            n += 1;
+        }
+    }
+    ((cb / n) as u16, (cr / n) as u16)
+}
+
+/// Encode the rows starting at `y0` into an image.
+///
+/// For subsampled formats `rows` must start on a chroma row and hold
+/// every row that shares it.
+pub(crate) fn encode_rows(layout: Layout, planes: &mut [(&mut [u8], usize)], y0: usize,
+                          rows: &[Vec<Px>], dither: bool) {
+    let at = |x: usize, y: usize| if dither { Some((x, y)) } else { None };
+    match layout {
+        Layout::Bits { bytes, c, a, .. } => {
+            for (i, pixels) in rows.iter().enumerate() {
+                let y = y0 + i;
+                encode_bits(bytes, c, a, row_mut(planes, 0, y), pixels, dither.then_some(y));
+            }
+        }
+        Layout::Packed422 { sample_bytes, bits, y: ys, cb, cr } => {
+            for (i, pixels) in rows.iter().enumerate() {
+                let y = y0 + i;
+                let data = row_mut(planes, 0, y);
+                for x in (0..pixels.len()).step_by(2) {
+                    let block = (x / 2) * 4 * sample_bytes;
+                    let (u, v) = chroma_average(&rows[i..i + 1], x, 2);
+                    let second = pixels.get(x + 1).unwrap_or(&pixels[x]);
+                    let mut put = |i: usize, value: u16, d| {
+                        write_sample(data, block + i * sample_bytes, sample_bytes, bits, value, d)
+                    };
+                    put(ys[0], pixels[x][0], at(x, y));
+                    put(ys[1], second[0], at(x + 1, y));
+                    put(cb, u, at(x / 2, y));
+                    put(cr, v, at(x / 2, y));
+                }
+            }
+        }
+        Layout::SemiPlanar { sample_bytes, bits, hsub, vsub, cb_first } => {
+            for (i, pixels) in rows.iter().enumerate() {
+                let y = y0 + i;
+                let luma = row_mut(planes, 0, y);
+                for (x, px) in pixels.iter().enumerate() {
+                    write_sample(luma, x * sample_bytes, sample_bytes, bits, px[0], at(x, y));
+                }
+            }
+            let cy = y0 / vsub;
+            let chroma = row_mut(planes, 1, cy);
+            let (cb, cr) = if cb_first { (0, 1) } else { (1, 0) };
+            let width = rows[0].len();
+            for cx in 0..width.div_ceil(hsub) {
+                let (u, v) = chroma_average(rows, cx * hsub, hsub);
+                let pair = cx * 2 * sample_bytes;
+                write_sample(chroma, pair + cb * sample_bytes, sample_bytes, bits, u, at(cx, cy));
+                write_sample(chroma, pair + cr * sample_bytes, sample_bytes, bits, v, at(cx, cy));
+            }
+        }
+        Layout::Planar { hsub, vsub, cb_plane } => {
+            for (i, pixels) in rows.iter().enumerate() {
+                let y = y0 + i;
+                let luma = row_mut(planes, 0, y);
+                for (x, px) in pixels.iter().enumerate() {
+                    luma[x] = quantize(px[0], 8, at(x, y)) as u8;
+                }
+            }
+            let cy = y0 / vsub;
+            let width = rows[0].len();
+            for cx in 0..width.div_ceil(hsub) {
+                let (u, v) = chroma_average(rows, cx * hsub, hsub);
+                row_mut(planes, cb_plane, cy)[cx] = quantize(u, 8, at(cx, cy)) as u8;
+                row_mut(planes, 3 - cb_plane, cy)[cx] = quantize(v, 8, at(cx, cy)) as u8;
+            }
+        }
+    }
+}
+
+fn unit(v: u16) -> f32 { v as f32 / 65535.0 }
+
+fn from_unit(v: f32) -> u16 { (v.clamp(0.0, 1.0) * 65535.0 + 0.5) as u16 }
+
+/// Y, Cb, Cr as 0..1, -0.5..0.5, -0.5..0.5.
+fn normalize_yuv(px: Px, range: ColorRange) -> (f32, f32, f32) {
+    let (y, cb, cr) = (unit(px[0]), unit(px[1]), unit(px[2]));
+    match range {
+        ColorRange::Limited => ((y * 255.0 - 16.0) / 219.0,
+                                (cb * 255.0 - 128.0) / 224.0,
+                                (cr * 255.0 - 128.0) / 224.0),
+        ColorRange::Full => (y, cb - 128.0 / 255.0, cr - 128.0 / 255.0),
+    }
+}
+
+fn yuv_to_rgb(px: Px, opts: &Options) -> Px {
+    let (kr, kb) = opts.encoding.coefficients();
+    let kg = 1.0 - kr - kb;
+    let (y, cb, cr) = normalize_yuv(px, opts.range);
+    let r = y + 2.0 * (1.0 - kr) * cr;
+    let b = y + 2.0 * (1.0 - kb) * cb;
+    let g = (y - kr * r - kb * b) / kg;
+    [from_unit(r), from_unit(g), from_unit(b), px[3]]
+}
+
+fn rgb_to_yuv(px: Px, opts: &Options) -> Px {
+    let (kr, kb) = opts.encoding.coefficients();
+    let kg = 1.0 - kr - kb;
+    let (r, g, b) = (unit(px[0]), unit(px[1]), unit(px[2]));
+    let y = kr * r + kg * g + kb * b;
+    let cb = (b - y) / (2.0 * (1.0 - kb));
+    let cr = (r - y) / (2.0 * (1.0 - kr));
+    let (y, cb, cr) = match opts.range {
+        ColorRange::Limited => ((y * 219.0 + 16.0) / 255.0,
+                                (cb * 224.0 + 128.0) / 255.0,
+                                (cr * 224.0 + 128.0) / 255.0),
+        ColorRange::Full => (y, cb + 128.0 / 255.0, cr + 128.0 / 255.0),
+    };
+    [from_unit(y), from_unit(cb), from_unit(cr), px[3]]
+}
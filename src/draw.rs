@@ -0,0 +1,287 @@
+//! Simple software drawing.
+//!
+//! Enough to draw a boot splash or a recovery screen into a `DumbBuf`
+//! (or any other pitched buffer) without a GPU. Everything works on
+//! `convert::Image` and `convert::ImageMut`, so it handles padded rows
+//! and any format `convert` supports. Drawing is done with 16 bits per
+//! channel.
+//!
+//! `blit` converts between any two formats. The other operations need
+//! packed RGB formats (every pixel a whole number of bytes), convert
+//! YUV images first.
+//!
+//! Drawing is clipped to the destination: parts of a rectangle or
+//! source image that fall outside it are skipped.
+
+use std::io;
+use convert::{self, Image, ImageMut, Layout, Options, Px};
+
+/// A rectangle. The position may be negative or past the edge of an
+/// image: it's clipped when drawn.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Rect {
+        Rect { x, y, width, height }
+    }
+
+    /// Clip to an image of `size`. Returns the visible part, and how
+    /// far its corner moved from (x, y). `None` if nothing is visible.
+    fn clip(&self, size: (u32, u32)) -> Option<(Rect, (u32, u32))> {
+        let clip_axis = |at: i32, len: u32, max: u32| {
+            let start = (at as i64).max(0);
+            let end = (at as i64 + len as i64).min(max as i64);
+            if start < end {
+                Some((start as u32, (end - start) as u32, (start - at as i64) as u32))
+            } else {
+                None
+            }
+        };
+        let (x, width, dx) = clip_axis(self.x, self.width, size.0)?;
+        let (y, height, dy) = clip_axis(self.y, self.height, size.1)?;
+        Some((Rect::new(x as i32, y as i32, width, height), (dx, dy)))
+    }
+}
+
+/// An RGBA color with 16 bits per channel. Alpha isn't premultiplied.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Color {
+    pub r: u16,
+    pub g: u16,
+    pub b: u16,
+    pub a: u16,
+}
+
+impl Color {
+    /// From 8 bit channels.
+    pub fn rgba8(r: u8, g: u8, b: u8, a: u8) -> Color {
+        let wide = |v: u8| v as u16 * 0x101;
+        Color { r: wide(r), g: wide(g), b: wide(b), a: wide(a) }
+    }
+
+    /// An opaque color from 8 bit channels.
+    pub fn rgb8(r: u8, g: u8, b: u8) -> Color {
+        Color::rgba8(r, g, b, 0xff)
+    }
+
+    fn px(&self) -> Px {
+        [self.r, self.g, self.b, self.a]
+    }
+}
+
+/// How the colors of an image relate to its alpha.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Alpha {
+    /// Colors are independent of alpha.
+    Straight,
+    /// Colors have been multiplied by alpha.
+    Premultiplied,
+}
+
+/// How to sample when scaling.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Filter {
+    /// Closest source pixel. Keeps hard edges.
+    Nearest,
+    /// Blend the 4 closest source pixels.
+    Bilinear,
+}
+
+fn needs_rgb(layout: Layout) -> io::Result<()> {
+    match layout {
+        Layout::Bits { yuv: false, .. } => Ok(()),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                "Drawing needs a packed RGB format")),
+    }
+}
+
+/// Fill part of an image with a color. Alpha is written as is, not
+/// blended.
+pub fn fill_rect(dst: &mut ImageMut, rect: Rect, color: Color) -> io::Result<()> {
+    needs_rgb(dst.as_image().layout())?;
+    let (rect, _) = match rect.clip(dst.size()) {
+        Some(clipped) => clipped,
+        None => return Ok(()),
+    };
+    let row = vec![color.px(); rect.width as usize];
+    for y in rect.y..rect.y + rect.height as i32 {
+        dst.write_pixels(rect.x as usize, y as usize, &row);
+    }
+    Ok(())
+}
+
+/// Copy `src` into `dst` with its top left corner at (x, y),
+/// converting between formats.
+///
+/// # Errors
+///
+/// `ErrorKind::InvalidInput` if clipping would split a block of
+/// subsampled pixels (eg. an odd position with NV12).
+pub fn blit(src: &Image, dst: &mut ImageMut, x: i32, y: i32) -> io::Result<()> {
+    let (width, height) = src.size();
+    let (rect, (sx, sy)) = match Rect::new(x, y, width, height).clip(dst.size()) {
+        Some(clipped) => clipped,
+        None => return Ok(()),
+    };
+    let src = src.crop(sx, sy, rect.width, rect.height)?;
+    let mut dst = dst.crop_mut(rect.x as u32, rect.y as u32, rect.width, rect.height)?;
+    convert::convert(&src, &mut dst, &Options::default())
+}
+
+fn over(s: Px, d: Px, alpha: Alpha) -> Px {
+    let sa = s[3] as u32;
+    let inv = 0xffff - sa;
+    match alpha {
+        Alpha::Premultiplied => {
+            let mut out = [0; 4];
+            for i in 0..4 {
+                out[i] = (s[i] as u32 + (d[i] as u32 * inv + 0x7fff) / 0xffff).min(0xffff) as u16;
+            }
+            out
+        }
+        Alpha::Straight => {
+            // Work in premultiplied alpha, then divide back out.
+            let da = d[3] as u32 * inv / 0xffff;
+            let oa = sa + da;
+            if oa == 0 {
+                return [0; 4];
+            }
+            let mut out = [0, 0, 0, oa as u16];
+            for i in 0..3 {
+                out[i] = ((s[i] as u32 * sa + d[i] as u32 * da) / oa) as u16;
+            }
+            out
+        }
+    }
+}
+
+/// Draw `src` over `dst` with its top left corner at (x, y), using the
+/// alpha of `src` (Porter-Duff "over").
+///
+/// Both images must store colors the same way, given by `alpha`.
+pub fn composite(src: &Image, dst: &mut ImageMut, x: i32, y: i32, alpha: Alpha)
+                 -> io::Result<()> {
+    needs_rgb(src.layout())?;
+    needs_rgb(dst.as_image().layout())?;
+    let (rect, (sx, sy)) = match Rect::new(x, y, src.size().0, src.size().1).clip(dst.size()) {
+        Some(clipped) => clipped,
+        None => return Ok(()),
+    };
+    let width = rect.width as usize;
+    let mut s = vec![[0; 4]; width];
+    let mut d = vec![[0; 4]; width];
+    for row in 0..rect.height as usize {
+        let dy = rect.y as usize + row;
+        src.read_pixels(sx as usize, sy as usize + row, &mut s);
+        dst.as_image().read_pixels(rect.x as usize, dy, &mut d);
+        for (d, s) in d.iter_mut().zip(&s) {
+            *d = over(*s, *d, alpha);
+        }
+        dst.write_pixels(rect.x as usize, dy, &d);
+    }
+    Ok(())
+}
+
+fn lerp(a: Px, b: Px, t: u32) -> Px {
+    let mut out = [0; 4];
+    for i in 0..4 {
+        out[i] = ((a[i] as u32 * (256 - t) + b[i] as u32 * t + 128) >> 8) as u16;
+    }
+    out
+}
+
+/// Position in the source of the center of destination pixel `d`, in
+/// 1/256ths of a pixel.
+fn source_position(d: u32, dst_len: u32, src_len: u32) -> i64 {
+    ((2 * d as i64 + 1) * src_len as i64 * 256) / (2 * dst_len as i64) - 128
+}
+
+/// Scale all of `src` to fill `rect` of `dst`.
+pub fn scale(src: &Image, dst: &mut ImageMut, rect: Rect, filter: Filter) -> io::Result<()> {
+    needs_rgb(src.layout())?;
+    needs_rgb(dst.as_image().layout())?;
+    let (src_w, src_h) = src.size();
+    if src_w == 0 || src_h == 0 {
+        return Ok(());
+    }
+    let (clipped, (cx, cy)) = match rect.clip(dst.size()) {
+        Some(clipped) => clipped,
+        None => return Ok(()),
+    };
+
+    let mut top = vec![[0; 4]; src_w as usize];
+    let mut bottom = vec![[0; 4]; src_w as usize];
+    let mut out = vec![[0; 4]; clipped.width as usize];
+    let clamp = |v: i64, len: u32| v.max(0).min(len as i64 - 1) as usize;
+
+    for row in 0..clipped.height {
+        let sy = source_position(cy + row, rect.height, src_h);
+        match filter {
+            Filter::Nearest => {
+                src.read_pixels(0, clamp((sy + 128) >> 8, src_h), &mut top);
+                for (col, px) in out.iter_mut().enumerate() {
+                    let sx = source_position(cx + col as u32, rect.width, src_w);
+                    *px = top[clamp((sx + 128) >> 8, src_w)];
+                }
+            }
+            Filter::Bilinear => {
+                src.read_pixels(0, clamp(sy >> 8, src_h), &mut top);
+                src.read_pixels(0, clamp((sy >> 8) + 1, src_h), &mut bottom);
+                let ty = (sy & 0xff) as u32;
+                for (col, px) in out.iter_mut().enumerate() {
+                    let sx = source_position(cx + col as u32, rect.width, src_w);
+                    let (x0, x1) = (clamp(sx >> 8, src_w), clamp((sx >> 8) + 1, src_w));
+                    let tx = (sx & 0xff) as u32;
+                    *px = lerp(lerp(top[x0], top[x1], tx), lerp(bottom[x0], bottom[x1], tx), ty);
+                }
+            }
+        }
+        dst.write_pixels(clipped.x as usize, (clipped.y as u32 + row) as usize, &out);
+    }
+    Ok(())
+}
+
+fn packed_bytes(dst: &ImageMut) -> io::Result<usize> {
+    match dst.as_image().layout() {
+        Layout::Bits { bytes, .. } => Ok(bytes),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                "Flipping needs a packed format")),
+    }
+}
+
+/// Mirror an image left to right.
+pub fn flip_horizontal(img: &mut ImageMut) -> io::Result<()> {
+    let bytes = packed_bytes(img)?;
+    let (width, height) = img.size();
+    let (ref mut data, pitch) = img.planes_mut()[0];
+    for y in 0..height as usize {
+        let row = &mut data[y * pitch..y * pitch + width as usize * bytes];
+        // Reversing the bytes reverses the pixels, and the bytes inside
+        // each pixel. Reverse those back.
+        row.reverse();
+        for px in row.chunks_mut(bytes) {
+            px.reverse();
+        }
+    }
+    Ok(())
+}
+
+/// Mirror an image top to bottom.
+pub fn flip_vertical(img: &mut ImageMut) -> io::Result<()> {
+    let bytes = packed_bytes(img)?;
+    let (width, height) = img.size();
+    let row = width as usize * bytes;
+    let (ref mut data, pitch) = img.planes_mut()[0];
+    for y in 0..height as usize / 2 {
+        let other = height as usize - 1 - y;
+        let (first, second) = data.split_at_mut(other * pitch);
+        first[y * pitch..y * pitch + row].swap_with_slice(&mut second[..row]);
+    }
+    Ok(())
+}
@@ -0,0 +1,482 @@
+//! Parsing events read from a device.
+//!
+//! Reading a DRM device returns as many whole events as fit in the
+//! buffer, each starting with a `struct drm_event` header giving its
+//! type and length. `Device::read_event` and `Device::read_events` do
+//! the reading; `parse_events` is the parser on its own, for event
+//! loops that read the fd themselves.
+//!
+//! Event times are `DrmTimestamp`s, on whichever clock the device
+//! reports (see `Device::event_clock`).
+//!
+//! Rather than matching on `Event`s, an `EventContext` can route them
+//! to callbacks, like libdrm's `drmHandleEvent`:
+//!
+//! ```ignore
+//! let mut ctx = EventContext::new();
+//! ctx.on_page_flip(crtc, |frame, _| println!("Flipped at {:?}", frame.time));
+//! master.page_flip_with(&mut ctx, crtc, fb, PageFlipFlags::empty(), |frame| {
+//!     // This flip in particular.
+//! })?;
+//! dev.dispatch_events(&mut ctx)?;
+//! ```
+
+use std::{cmp, fmt, io, mem};
+use std::collections::HashMap;
+#[cfg(any(feature = "tokio", feature = "async-io"))]
+use std::collections::VecDeque;
+use std::os::raw::c_ulong;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use libc;
+use mode::{Crtc, Id};
+
+const DRM_EVENT_VBLANK: u32 = 0x01;
+const DRM_EVENT_FLIP_COMPLETE: u32 = 0x02;
+const DRM_EVENT_CRTC_SEQUENCE: u32 = 0x03;
+
+/// Size of `struct drm_event`.
+const HEADER_BYTES: usize = 8;
+/// Size of `struct drm_event_vblank`.
+const VBLANK_BYTES: usize = 32;
+/// Size of `struct drm_event_crtc_sequence`.
+const CRTC_SEQUENCE_BYTES: usize = 32;
+
+/// A clock the kernel reports times on.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Clock {
+    /// CLOCK_MONOTONIC. Used when `Capability::TimestampMonotonic` is
+    /// set, which is almost always.
+    Monotonic,
+    /// CLOCK_REALTIME. Can jump when the system time is changed.
+    Realtime,
+}
+
+impl Clock {
+    /// The current time on this clock, since its epoch.
+    pub fn now(&self) -> Duration {
+        let id = match *self {
+            Clock::Monotonic => libc::CLOCK_MONOTONIC,
+            Clock::Realtime => libc::CLOCK_REALTIME,
+        };
+        let mut now = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+        // Only fails for clocks that don't exist.
+        unsafe { libc::clock_gettime(id, &mut now) };
+        Duration::new(now.tv_sec as u64, now.tv_nsec as u32)
+    }
+}
+
+/// A time reported by the kernel, and the clock it's on.
+///
+/// Vblank times can be in the future: drivers may send the event
+/// before the vblank it's for. See `time_until`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct DrmTimestamp {
+    clock: Clock,
+    time: Duration,
+}
+
+impl DrmTimestamp {
+    /// A time on `clock`, since its epoch.
+    pub fn new(clock: Clock, time: Duration) -> DrmTimestamp {
+        DrmTimestamp { clock, time }
+    }
+
+    pub fn clock(&self) -> Clock { self.clock }
+
+    /// Time since the epoch of the clock.
+    pub fn as_duration(&self) -> Duration { self.time }
+
+    /// The time on CLOCK_MONOTONIC, so timestamps on either clock can be
+    /// compared. Realtime timestamps are converted using the current
+    /// difference between the clocks.
+    pub fn monotonic(&self) -> Duration {
+        match self.clock {
+            Clock::Monotonic => self.time,
+            Clock::Realtime => {
+                let (mono, real) = (Clock::Monotonic.now(), Clock::Realtime.now());
+                if real >= mono {
+                    self.time.saturating_sub(real - mono)
+                } else {
+                    self.time + (mono - real)
+                }
+            }
+        }
+    }
+
+    /// The wall clock time.
+    pub fn to_system_time(&self) -> SystemTime {
+        match self.clock {
+            Clock::Realtime => UNIX_EPOCH + self.time,
+            Clock::Monotonic => {
+                let (now, system) = (Clock::Monotonic.now(), SystemTime::now());
+                if self.time >= now {
+                    system + (self.time - now)
+                } else {
+                    system - (now - self.time)
+                }
+            }
+        }
+    }
+
+    /// How long until this time. Zero if it's passed.
+    pub fn time_until(&self) -> Duration {
+        self.time.saturating_sub(self.clock.now())
+    }
+
+    /// How long since this time. Zero if it's in the future.
+    pub fn elapsed(&self) -> Duration {
+        self.clock.now().saturating_sub(self.time)
+    }
+
+    /// The time from `earlier` to this, or `None` if `earlier` is
+    /// later.
+    pub fn duration_since(&self, earlier: DrmTimestamp) -> Option<Duration> {
+        if self.clock == earlier.clock {
+            self.time.checked_sub(earlier.time)
+        } else {
+            self.monotonic().checked_sub(earlier.monotonic())
+        }
+    }
+}
+
+impl PartialOrd for DrmTimestamp {
+    /// Only timestamps on the same clock are ordered.
+    fn partial_cmp(&self, other: &DrmTimestamp) -> Option<cmp::Ordering> {
+        if self.clock == other.clock {
+            Some(self.time.cmp(&other.time))
+        } else {
+            None
+        }
+    }
+}
+
+/// Event types read from device. Event's are sent only after a
+/// request vblank has been called or a page flip has been issued.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Event {
+    /// Event type unknown to this library. `data` is the event after
+    /// the header.
+    Unknown { type_: u32, data: Vec<u8> },
+    /// A vblank requested with `Device::request_vblank`.
+    ///
+    /// `crtc_id` is 0 on kernels older than 4.12.
+    VBlank { seq: u32, tv: DrmTimestamp, user: u64, crtc_id: u32 },
+    /// A page flip (or atomic commit) asked for an event.
+    ///
+    /// `crtc_id` is 0 on kernels older than 4.12.
+    PageFlip { seq: u32, tv: DrmTimestamp, user: u64, crtc_id: u32 },
+    /// A vblank queued with `Device::queue_sequence`. The time is
+    /// always on the monotonic clock.
+    CrtcSequence { seq: u64, time: DrmTimestamp, user: u64 },
+}
+
+impl Event {
+    /// The user data the event was requested with. `None` for
+    /// `Unknown` events.
+    pub fn user_data(&self) -> Option<u64> {
+        match *self {
+            Event::VBlank { user, .. } | Event::PageFlip { user, .. }
+                | Event::CrtcSequence { user, .. } => Some(user),
+            Event::Unknown { .. } => None,
+        }
+    }
+
+    /// When and on which vblank it happened. `None` for `Unknown`
+    /// events.
+    pub fn completion(&self) -> Option<Completion> {
+        match *self {
+            Event::VBlank { seq, tv, .. } | Event::PageFlip { seq, tv, .. } => {
+                Some(Completion { seq: seq as u64, time: tv, crtc_id: self.crtc_id() })
+            }
+            Event::CrtcSequence { seq, time, .. } => {
+                Some(Completion { seq, time, crtc_id: None })
+            }
+            Event::Unknown { .. } => None,
+        }
+    }
+
+    /// The CRTC the event is for, if the event says.
+    pub fn crtc_id(&self) -> Option<u32> {
+        match *self {
+            Event::VBlank { crtc_id, .. } | Event::PageFlip { crtc_id, .. } if crtc_id != 0 => {
+                Some(crtc_id)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Iterator over the events in a buffer. See `parse_events`.
+#[derive(Debug, Clone)]
+pub struct Events<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    clock: Clock,
+}
+
+/// Parse the events in bytes read from a device, whose vblank and page
+/// flip times are on `clock`.
+///
+/// Yields an error for an event that's too short for its type, and
+/// carries on with the next one. If the headers themselves don't add
+/// up, it yields an error and stops: there's no telling where the next
+/// event starts.
+pub fn parse_events(buf: &[u8], clock: Clock) -> Events<'_> {
+    Events { buf, pos: 0, clock }
+}
+
+impl<'a> Events<'a> {
+    /// Number of bytes parsed so far. After a bad header, this is the
+    /// whole buffer.
+    pub fn consumed(&self) -> usize { self.pos }
+
+    fn fail(&mut self, msg: &str) -> Option<io::Result<Event>> {
+        self.pos = self.buf.len();
+        Some(Err(invalid_data(msg)))
+    }
+}
+
+impl<'a> Iterator for Events<'a> {
+    type Item = io::Result<Event>;
+
+    fn next(&mut self) -> Option<io::Result<Event>> {
+        if self.pos >= self.buf.len() {
+            return None;
+        }
+        let rest = &self.buf[self.pos..];
+        if rest.len() < HEADER_BYTES {
+            return self.fail("Short DRM event header");
+        }
+        let type_ = read_u32(rest, 0);
+        let length = read_u32(rest, 4) as usize;
+        if length < HEADER_BYTES || length > rest.len() {
+            return self.fail("Bad DRM event length");
+        }
+        self.pos += length;
+        Some(decode(type_, &rest[..length], self.clock))
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn read_u32(data: &[u8], at: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&data[at..at + 4]);
+    u32::from_ne_bytes(bytes)
+}
+
+fn read_u64(data: &[u8], at: usize) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&data[at..at + 8]);
+    u64::from_ne_bytes(bytes)
+}
+
+/// Decode one event. `data` includes the header.
+fn decode(type_: u32, data: &[u8], clock: Clock) -> io::Result<Event> {
+    match type_ {
+        DRM_EVENT_VBLANK | DRM_EVENT_FLIP_COMPLETE => {
+            if data.len() < VBLANK_BYTES {
+                return Err(invalid_data("Short DRM vblank event"));
+            }
+            // struct drm_event_vblank
+            let user = read_u64(data, 8);
+            let (sec, usec) = (read_u32(data, 16), read_u32(data, 20));
+            let tv = DrmTimestamp::new(clock, Duration::new(sec as u64, usec.saturating_mul(1000)));
+            let seq = read_u32(data, 24);
+            let crtc_id = read_u32(data, 28);
+            Ok(if type_ == DRM_EVENT_VBLANK {
+                Event::VBlank { seq, tv, user, crtc_id }
+            } else {
+                Event::PageFlip { seq, tv, user, crtc_id }
+            })
+        }
+        DRM_EVENT_CRTC_SEQUENCE => {
+            if data.len() < CRTC_SEQUENCE_BYTES {
+                return Err(invalid_data("Short DRM CRTC sequence event"));
+            }
+            // struct drm_event_crtc_sequence
+            Ok(Event::CrtcSequence {
+                user: read_u64(data, 8),
+                time: DrmTimestamp::new(Clock::Monotonic, Duration::from_nanos(read_u64(data, 16))),
+                seq: read_u64(data, 24),
+            })
+        }
+        _ => Ok(Event::Unknown { type_, data: data[HEADER_BYTES..].to_vec() }),
+    }
+}
+
+/// A vblank, page flip or CRTC sequence that happened. See
+/// `EventContext`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Completion {
+    /// The vblank counter.
+    pub seq: u64,
+    pub time: DrmTimestamp,
+    /// `None` for CRTC sequence events, and vblanks and page flips on
+    /// kernels older than 4.12.
+    pub crtc_id: Option<u32>,
+}
+
+/// User data values with this bit set are tokens from
+/// `EventContext::token`. It's the top bit of a C `unsigned long`, as
+/// that's all the user data of a vblank request can hold: bit 31 on
+/// 32-bit targets.
+const TOKEN_BIT: u64 = 1 << (mem::size_of::<c_ulong>() * 8 - 1);
+
+type Handler<'a> = Box<dyn FnMut(Completion, u64) + 'a>;
+
+/// Routes events to callbacks. See `Device::dispatch_events`.
+///
+/// Each event goes to one callback:
+///
+/// * If its user data is a token from `token` (which is what
+///   `Device::request_vblank_with` and `Master::page_flip_with` use),
+///   the callback given for the token, once.
+/// * Otherwise, the handler set for its type, which also gets the user
+///   data. Page flips go to the handler for their CRTC if there is one,
+///   or else the one from `on_any_page_flip`.
+///
+/// Events nothing handles are dropped.
+#[derive(Default)]
+pub struct EventContext<'a> {
+    vblank: Option<Handler<'a>>,
+    page_flip: HashMap<u32, Handler<'a>>,
+    any_page_flip: Option<Handler<'a>>,
+    sequence: Option<Handler<'a>>,
+    pending: HashMap<u64, Box<dyn FnOnce(Completion) + 'a>>,
+    next_token: u64,
+}
+
+impl<'a> EventContext<'a> {
+    pub fn new() -> EventContext<'a> {
+        EventContext::default()
+    }
+
+    /// Handle vblank events.
+    pub fn on_vblank<F>(&mut self, handler: F)
+        where F: FnMut(Completion, u64) + 'a
+    {
+        self.vblank = Some(Box::new(handler));
+    }
+
+    /// Handle page flips on one CRTC.
+    pub fn on_page_flip<F>(&mut self, crtc_id: Id<Crtc>, handler: F)
+        where F: FnMut(Completion, u64) + 'a
+    {
+        self.page_flip.insert(crtc_id.as_u32(), Box::new(handler));
+    }
+
+    /// Handle page flips on CRTCs without their own handler. (And every
+    /// page flip on kernels that don't say which CRTC it was.)
+    pub fn on_any_page_flip<F>(&mut self, handler: F)
+        where F: FnMut(Completion, u64) + 'a
+    {
+        self.any_page_flip = Some(Box::new(handler));
+    }
+
+    /// Handle CRTC sequence events.
+    pub fn on_sequence<F>(&mut self, handler: F)
+        where F: FnMut(Completion, u64) + 'a
+    {
+        self.sequence = Some(Box::new(handler));
+    }
+
+    /// Get user data that calls `callback` when an event carrying it
+    /// arrives. For requests that take raw user data, such as
+    /// `Master::atomic_commit`.
+    ///
+    /// Tokens have the top bit of a C `unsigned long` set, so they won't
+    /// be mistaken for user data that doesn't.
+    pub fn token<F>(&mut self, callback: F) -> u64
+        where F: FnOnce(Completion) + 'a
+    {
+        let token = TOKEN_BIT | self.next_token;
+        self.next_token = (self.next_token + 1) & (TOKEN_BIT - 1);
+        self.pending.insert(token, Box::new(callback));
+        token
+    }
+
+    /// Forget a token, without calling its callback. For when the
+    /// request it was for failed.
+    pub fn cancel(&mut self, token: u64) -> bool {
+        self.pending.remove(&token).is_some()
+    }
+
+    /// Number of tokens whose events haven't arrived.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Route one event. Returns `false` if nothing handled it.
+    pub fn dispatch(&mut self, event: &Event) -> bool {
+        let (completion, user) = match (event.completion(), event.user_data()) {
+            (Some(completion), Some(user)) => (completion, user),
+            _ => return false,
+        };
+        if let Some(callback) = self.pending.remove(&user) {
+            callback(completion);
+            return true;
+        }
+        let handler = match *event {
+            Event::VBlank { .. } => self.vblank.as_mut(),
+            Event::PageFlip { .. } => {
+                match completion.crtc_id.and_then(|id| self.page_flip.get_mut(&id)) {
+                    Some(handler) => Some(handler),
+                    None => self.any_page_flip.as_mut(),
+                }
+            }
+            _ => self.sequence.as_mut(),
+        };
+        match handler {
+            Some(handler) => {
+                handler(completion, user);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<'a> fmt::Debug for EventContext<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EventContext")
+            .field("page_flip_crtcs", &self.page_flip.keys().collect::<Vec<_>>())
+            .field("pending", &self.pending.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// Events read ahead while the async wrappers wait for a particular
+/// one.
+#[cfg(any(feature = "tokio", feature = "async-io"))]
+#[derive(Debug, Default)]
+pub(crate) struct EventQueue {
+    queued: VecDeque<Event>,
+    next_token: u64,
+}
+
+#[cfg(any(feature = "tokio", feature = "async-io"))]
+impl EventQueue {
+    /// User data for a request to wait for with `take`.
+    pub fn token(&mut self) -> u64 {
+        let token = TOKEN_BIT | self.next_token;
+        self.next_token = (self.next_token + 1) & (TOKEN_BIT - 1);
+        token
+    }
+
+    pub fn extend(&mut self, events: Vec<Event>) {
+        self.queued.extend(events);
+    }
+
+    pub fn pop(&mut self) -> Option<Event> {
+        self.queued.pop_front()
+    }
+
+    /// Remove the event for `token`, if it's arrived.
+    pub fn take(&mut self, token: u64) -> Option<Completion> {
+        let index = self.queued.iter().position(|event| event.user_data() == Some(token))?;
+        self.queued.remove(index).and_then(|event| event.completion())
+    }
+}
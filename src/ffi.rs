@@ -494,7 +494,8 @@ pub enum vblank_seq_type {
 #[derive(Copy, Clone)]
 #[derive(Debug)]
 pub struct wait_vblank_request {
-    pub type_: vblank_seq_type,
+    /// `vblank_seq_type` flags, OR'd together.
+    pub type_: ::std::os::raw::c_uint,
     pub sequence: ::std::os::raw::c_uint,
     pub signal: ::std::os::raw::c_ulong,
 }
@@ -505,7 +506,8 @@ impl ::std::default::Default for wait_vblank_request {
 #[derive(Copy, Clone)]
 #[derive(Debug)]
 pub struct wait_vblank_reply {
-    pub type_: vblank_seq_type,
+    /// `vblank_seq_type` flags, OR'd together.
+    pub type_: ::std::os::raw::c_uint,
     pub sequence: ::std::os::raw::c_uint,
     pub tval_sec: ::std::os::raw::c_long,
     pub tval_usec: ::std::os::raw::c_long,
@@ -535,6 +537,30 @@ impl ::std::default::Default for wait_vblank {
 #[repr(C)]
 #[derive(Copy, Clone)]
 #[derive(Debug)]
+pub struct crtc_get_sequence {
+    pub crtc_id: __u32,
+    pub active: __u32,
+    pub sequence: __u64,
+    pub sequence_ns: __s64,
+}
+impl ::std::default::Default for crtc_get_sequence {
+    fn default() -> Self { unsafe { ::std::mem::zeroed() } }
+}
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub struct crtc_queue_sequence {
+    pub crtc_id: __u32,
+    pub flags: __u32,
+    pub sequence: __u64,
+    pub user_data: __u64,
+}
+impl ::std::default::Default for crtc_queue_sequence {
+    fn default() -> Self { unsafe { ::std::mem::zeroed() } }
+}
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
 pub struct modeset_ctl {
     pub crtc: __u32,
     pub cmd: __u32,
@@ -678,6 +704,105 @@ impl ::std::default::Default for prime_handle {
 #[repr(C)]
 #[derive(Copy, Clone)]
 #[derive(Debug)]
+pub struct syncobj_create {
+    pub handle: __u32,
+    pub flags: __u32,
+}
+impl ::std::default::Default for syncobj_create {
+    fn default() -> Self { unsafe { ::std::mem::zeroed() } }
+}
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub struct syncobj_destroy {
+    pub handle: __u32,
+    pub pad: __u32,
+}
+impl ::std::default::Default for syncobj_destroy {
+    fn default() -> Self { unsafe { ::std::mem::zeroed() } }
+}
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub struct syncobj_handle {
+    pub handle: __u32,
+    pub flags: __u32,
+    pub fd: __s32,
+    pub pad: __u32,
+    pub point: __u64,
+}
+impl ::std::default::Default for syncobj_handle {
+    fn default() -> Self { unsafe { ::std::mem::zeroed() } }
+}
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub struct syncobj_transfer {
+    pub src_handle: __u32,
+    pub dst_handle: __u32,
+    pub src_point: __u64,
+    pub dst_point: __u64,
+    pub flags: __u32,
+    pub pad: __u32,
+}
+impl ::std::default::Default for syncobj_transfer {
+    fn default() -> Self { unsafe { ::std::mem::zeroed() } }
+}
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub struct syncobj_wait {
+    pub handles: __u64,
+    pub timeout_nsec: __s64,
+    pub count_handles: __u32,
+    pub flags: __u32,
+    pub first_signaled: __u32,
+    pub pad: __u32,
+}
+impl ::std::default::Default for syncobj_wait {
+    fn default() -> Self { unsafe { ::std::mem::zeroed() } }
+}
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub struct syncobj_timeline_wait {
+    pub handles: __u64,
+    pub points: __u64,
+    pub timeout_nsec: __s64,
+    pub count_handles: __u32,
+    pub flags: __u32,
+    pub first_signaled: __u32,
+    pub pad: __u32,
+}
+impl ::std::default::Default for syncobj_timeline_wait {
+    fn default() -> Self { unsafe { ::std::mem::zeroed() } }
+}
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub struct syncobj_array {
+    pub handles: __u64,
+    pub count_handles: __u32,
+    pub pad: __u32,
+}
+impl ::std::default::Default for syncobj_array {
+    fn default() -> Self { unsafe { ::std::mem::zeroed() } }
+}
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
+pub struct syncobj_timeline_array {
+    pub handles: __u64,
+    pub points: __u64,
+    pub count_handles: __u32,
+    pub flags: __u32,
+}
+impl ::std::default::Default for syncobj_timeline_array {
+    fn default() -> Self { unsafe { ::std::mem::zeroed() } }
+}
+#[repr(C)]
+#[derive(Copy, Clone)]
+#[derive(Debug)]
 pub struct mode_modeinfo {
     pub clock: __u32,
     pub hdisplay: __u16,
@@ -1,6 +1,9 @@
 
 #![allow(dead_code)]
 
+use std::{error, fmt};
+use std::str::FromStr;
+
 macro_rules! fourcc_code {
     ($a:expr, $b:expr, $c:expr, $d:expr) => {
         (($a as u32) | (($b as u32) << 8) |
@@ -17,13 +20,22 @@ fn from_u32(u: u32) -> (char, char, char, char) {
 
 macro_rules! decl_fourcc_list {
     ( $( $name:ident ($a:expr, $b:expr, $c:expr, $d:expr)),* ) => {
-        #[repr(u32)]
+        /// A DRM pixel format code.
+        ///
+        /// Codes that this library doesn't know about are kept as
+        /// `Other`. Always convert codes with `FourCC::from` so that known
+        /// codes don't end up as `Other`.
+        ///
+        /// Breaking change: this used to be `#[repr(u32)]`, with the codes
+        /// as discriminants. `FourCC::XRGB8888 as u32` no longer gives the
+        /// code, use `FourCC::XRGB8888.as_u32()` (or `u32::from`).
         #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
         pub enum FourCC {
-            Unknown = 0,
             $(
-                $name = fourcc_code!($a, $b, $c, $d),
+                $name,
             )*
+            /// A code unknown to this library.
+            Other(u32),
         }
 
         impl From<u32> for FourCC {
@@ -32,7 +44,18 @@ macro_rules! decl_fourcc_list {
                     $(
                         ($a, $b, $c, $d) => FourCC::$name,
                     )*
-                    _ => FourCC::Unknown
+                    _ => FourCC::Other(n)
+                }
+            }
+        }
+
+        impl From<FourCC> for u32 {
+            fn from(f: FourCC) -> u32 {
+                match f {
+                    $(
+                        FourCC::$name => fourcc_code!($a, $b, $c, $d),
+                    )*
+                    FourCC::Other(n) => n,
                 }
             }
         }
@@ -134,5 +157,284 @@ decl_fourcc_list! {
     YUV422 ('Y', 'U', '1', '6'), /* 2x1 subsampled Cb (1) and Cr (2) planes */
     YVU422 ('Y', 'V', '1', '6'), /* 2x1 subsampled Cr (1) and Cb (2) planes */
     YUV444 ('Y', 'U', '2', '4'), /* non-subsampled Cb (1) and Cr (2) planes */
-    YVU444 ('Y', 'V', '2', '4') /* non-subsampled Cr (1) and Cb (2) planes */
+    YVU444 ('Y', 'V', '2', '4'), /* non-subsampled Cr (1) and Cb (2) planes */
+
+    /* 8/16 bpp Red and Red-Green */
+    R8 ('R', '8', ' ', ' '), /* [7:0] R */
+    R16 ('R', '1', '6', ' '), /* [15:0] R little endian */
+    RG88 ('R', 'G', '8', '8'), /* [15:0] R:G 8:8 little endian */
+    GR88 ('G', 'R', '8', '8'), /* [15:0] G:R 8:8 little endian */
+    RG1616 ('R', 'G', '3', '2'), /* [31:0] R:G 16:16 little endian */
+    GR1616 ('G', 'R', '3', '2'), /* [31:0] G:R 16:16 little endian */
+
+    /* 64 bpp RGB */
+    XRGB16161616 ('X', 'R', '4', '8'), /* [63:0] x:R:G:B 16:16:16:16 little endian */
+    XBGR16161616 ('X', 'B', '4', '8'), /* [63:0] x:B:G:R 16:16:16:16 little endian */
+    ARGB16161616 ('A', 'R', '4', '8'), /* [63:0] A:R:G:B 16:16:16:16 little endian */
+    ABGR16161616 ('A', 'B', '4', '8'), /* [63:0] A:B:G:R 16:16:16:16 little endian */
+
+    /* Floating point 64 bpp RGB (IEEE 754-2008 half-precision) */
+    XRGB16161616F ('X', 'R', '4', 'H'), /* [63:0] x:R:G:B 16:16:16:16 little endian */
+    XBGR16161616F ('X', 'B', '4', 'H'), /* [63:0] x:B:G:R 16:16:16:16 little endian */
+    ARGB16161616F ('A', 'R', '4', 'H'), /* [63:0] A:R:G:B 16:16:16:16 little endian */
+    ABGR16161616F ('A', 'B', '4', 'H'), /* [63:0] A:B:G:R 16:16:16:16 little endian */
+
+    /* packed YCbCr, 444 */
+    XYUV8888 ('X', 'Y', 'U', 'V'), /* [31:0] X:Y:Cb:Cr 8:8:8:8 little endian */
+    VUY888 ('V', 'U', '2', '4'), /* [23:0] Cr:Cb:Y 8:8:8 little endian */
+    XVYU2101010 ('X', 'V', '3', '0'), /* [31:0] X:Cr:Y:Cb 2:10:10:10 little endian */
+
+    /* packed YCbCr, 16 bits per sample */
+    Y210 ('Y', '2', '1', '0'), /* [63:0] Cr0:0:Y1:0:Cb0:0:Y0:0 10:6:10:6:10:6:10:6 little endian */
+    Y212 ('Y', '2', '1', '2'), /* [63:0] Cr0:0:Y1:0:Cb0:0:Y0:0 12:4:12:4:12:4:12:4 little endian */
+    Y216 ('Y', '2', '1', '6'), /* [63:0] Cr0:Y1:Cb0:Y0 16:16:16:16 little endian */
+    Y410 ('Y', '4', '1', '0'), /* [31:0] A:Cr:Y:Cb 2:10:10:10 little endian */
+    Y412 ('Y', '4', '1', '2'), /* [63:0] A:0:Cr:0:Y:0:Cb:0 12:4:12:4:12:4:12:4 little endian */
+    Y416 ('Y', '4', '1', '6'), /* [63:0] A:Cr:Y:Cb 16:16:16:16 little endian */
+
+    /* 2 plane YCbCr, more subsampling */
+    NV24 ('N', 'V', '2', '4'), /* non-subsampled Cr:Cb plane */
+    NV42 ('N', 'V', '4', '2'), /* non-subsampled Cb:Cr plane */
+
+    /*
+     * 2 plane YCbCr, 16 bits per sample
+     * index 0 = Y plane, [15:0] Y:x little endian
+     * index 1 = Cr:Cb plane, [31:0] Cr:x:Cb:x little endian
+     */
+    P210 ('P', '2', '1', '0'), /* 2x1 subsampled Cr:Cb plane, 10 bit per channel */
+    P010 ('P', '0', '1', '0'), /* 2x2 subsampled Cr:Cb plane, 10 bit per channel */
+    P012 ('P', '0', '1', '2'), /* 2x2 subsampled Cr:Cb plane, 12 bit per channel */
+    P016 ('P', '0', '1', '6') /* 2x2 subsampled Cr:Cb plane, 16 bit per channel */
+}
+
+/// Set on formats stored in big endian byte order.
+pub const BIG_ENDIAN: u32 = 1 << 31;
+
+impl FourCC {
+    /// The code, as the kernel takes it.
+    pub fn as_u32(self) -> u32 {
+        u32::from(self)
+    }
+
+    /// Layout information of the format. `None` for `Other` formats.
+    pub fn info(self) -> Option<FormatInfo> {
+        use self::FourCC::*;
+        let info = match self {
+            C8 | R8 | RGB332 | BGR233 => FormatInfo::rgb(8, 1, false),
+            R16 | RG88 | GR88 => FormatInfo::rgb(16, 2, false),
+            RG1616 | GR1616 => FormatInfo::rgb(32, 4, false),
+
+            XRGB4444 | XBGR4444 | RGBX4444 | BGRX4444 => FormatInfo::rgb(12, 2, false),
+            ARGB4444 | ABGR4444 | RGBA4444 | BGRA4444 => FormatInfo::rgb(16, 2, true),
+            XRGB1555 | XBGR1555 | RGBX5551 | BGRX5551 => FormatInfo::rgb(15, 2, false),
+            ARGB1555 | ABGR1555 | RGBA5551 | BGRA5551 => FormatInfo::rgb(16, 2, true),
+            RGB565 | BGR565 => FormatInfo::rgb(16, 2, false),
+
+            RGB888 | BGR888 => FormatInfo::rgb(24, 3, false),
+            XRGB8888 | XBGR8888 | RGBX8888 | BGRX8888 => FormatInfo::rgb(24, 4, false),
+            ARGB8888 | ABGR8888 | RGBA8888 | BGRA8888 => FormatInfo::rgb(32, 4, true),
+            XRGB2101010 | XBGR2101010 | RGBX1010102 | BGRX1010102 => FormatInfo::rgb(30, 4, false),
+            ARGB2101010 | ABGR2101010 | RGBA1010102 | BGRA1010102 => FormatInfo::rgb(32, 4, true),
+
+            // Depth is only meaningful for formats the legacy AddFB
+            // takes, which these aren't.
+            XRGB16161616 | XBGR16161616 => FormatInfo::rgb(0, 8, false),
+            ARGB16161616 | ABGR16161616 => FormatInfo::rgb(0, 8, true),
+            XRGB16161616F | XBGR16161616F => FormatInfo::rgb(0, 8, false),
+            ARGB16161616F | ABGR16161616F => FormatInfo::rgb(0, 8, true),
+
+            YUYV | YVYU | UYVY | VYUY => FormatInfo::yuv_packed(4, (2, 1), false),
+            Y210 | Y212 | Y216 => FormatInfo::yuv_packed(8, (2, 1), false),
+            AYUV | Y410 => FormatInfo::yuv_packed(4, (1, 1), true),
+            Y412 | Y416 => FormatInfo::yuv_packed(8, (1, 1), true),
+            XYUV8888 | XVYU2101010 => FormatInfo::yuv_packed(4, (1, 1), false),
+            VUY888 => FormatInfo::yuv_packed(3, (1, 1), false),
+
+            NV12 | NV21 => FormatInfo::yuv_planar(&[1, 2], 2, 2),
+            NV16 | NV61 => FormatInfo::yuv_planar(&[1, 2], 2, 1),
+            NV24 | NV42 => FormatInfo::yuv_planar(&[1, 2], 1, 1),
+            P010 | P012 | P016 => FormatInfo::yuv_planar(&[2, 4], 2, 2),
+            P210 => FormatInfo::yuv_planar(&[2, 4], 2, 1),
+
+            YUV410 | YVU410 => FormatInfo::yuv_planar(&[1, 1, 1], 4, 4),
+            YUV411 | YVU411 => FormatInfo::yuv_planar(&[1, 1, 1], 4, 1),
+            YUV420 | YVU420 => FormatInfo::yuv_planar(&[1, 1, 1], 2, 2),
+            YUV422 | YVU422 => FormatInfo::yuv_planar(&[1, 1, 1], 2, 1),
+            YUV444 | YVU444 => FormatInfo::yuv_planar(&[1, 1, 1], 1, 1),
+
+            Other(_) => return None,
+        };
+        Some(info)
+    }
+}
+
+impl fmt::Display for FourCC {
+    /// Formats as the four character code. (eg. "XR24").
+    ///
+    /// Codes that aren't printable are shown in hex.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let code = u32::from(*self);
+        let (a, b, c, d) = from_u32(code & !BIG_ENDIAN);
+        if [a, b, c, d].iter().all(|ch| ch.is_ascii_graphic() || *ch == ' ') {
+            write!(f, "{}{}{}{}", a, b, c, d)?;
+            if code & BIG_ENDIAN != 0 {
+                f.write_str(" (big endian)")?;
+            }
+            Ok(())
+        } else {
+            write!(f, "0x{:08x}", code)
+        }
+    }
+}
+
+/// Error returned when parsing a `FourCC` from a string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseFourCCError(String);
+
+impl fmt::Display for ParseFourCCError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid fourcc code: {:?}", self.0)
+    }
+}
+
+impl error::Error for ParseFourCCError {}
+
+impl FromStr for FourCC {
+    type Err = ParseFourCCError;
+
+    /// Parses a code of 1 to 4 ascii characters. Short codes are
+    /// padded with spaces. (So "C8" is `FourCC::C8`).
+    fn from_str(s: &str) -> Result<FourCC, ParseFourCCError> {
+        if s.is_empty() || s.len() > 4 || !s.bytes().all(|b| b.is_ascii_graphic() || b == b' ') {
+            return Err(ParseFourCCError(s.to_string()));
+        }
+        let mut code = [b' '; 4];
+        code[..s.len()].copy_from_slice(s.as_bytes());
+        Ok(FourCC::from(u32::from_le_bytes(code)))
+    }
+}
+
+/// Layout of a pixel format.
+///
+/// A plane is made of blocks. For most formats a block is a single
+/// pixel. Packed YUV formats that share chroma between neighbouring
+/// pixels (eg. YUYV) use bigger blocks.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct FormatInfo {
+    depth: u8,
+    num_planes: u8,
+    bytes_per_block: [u8; 3],
+    block_size: (u8, u8),
+    hsub: u8,
+    vsub: u8,
+    has_alpha: bool,
+    is_yuv: bool,
+}
+
+impl FormatInfo {
+    fn rgb(depth: u8, cpp: u8, has_alpha: bool) -> FormatInfo {
+        FormatInfo {
+            depth,
+            num_planes: 1,
+            bytes_per_block: [cpp, 0, 0],
+            block_size: (1, 1),
+            hsub: 1, vsub: 1,
+            has_alpha,
+            is_yuv: false,
+        }
+    }
+
+    fn yuv_packed(bytes: u8, block_size: (u8, u8), has_alpha: bool) -> FormatInfo {
+        FormatInfo {
+            depth: 0,
+            num_planes: 1,
+            bytes_per_block: [bytes, 0, 0],
+            block_size,
+            hsub: block_size.0, vsub: block_size.1,
+            has_alpha,
+            is_yuv: true,
+        }
+    }
+
+    fn yuv_planar(cpp: &[u8], hsub: u8, vsub: u8) -> FormatInfo {
+        let mut bytes_per_block = [0; 3];
+        bytes_per_block[..cpp.len()].copy_from_slice(cpp);
+        FormatInfo {
+            depth: 0,
+            num_planes: cpp.len() as u8,
+            bytes_per_block,
+            block_size: (1, 1),
+            hsub, vsub,
+            has_alpha: false,
+            is_yuv: true,
+        }
+    }
+
+    /// Color depth as used by the legacy `Fb::add`. Zero for formats
+    /// that don't have one (YUV and 16 bits per channel), as the kernel
+    /// reports.
+    pub fn depth(&self) -> u32 { self.depth as u32 }
+
+    /// Number of planes (separate buffers) the format is made of.
+    pub fn num_planes(&self) -> usize { self.num_planes as usize }
+
+    /// Bytes used by a block of pixels in a plane.
+    pub fn bytes_per_block(&self, plane: usize) -> u32 {
+        self.bytes_per_block.get(plane).cloned().unwrap_or(0) as u32
+    }
+
+    /// (width, height) of a block in pixels. Same for every plane.
+    pub fn block_size(&self) -> (u32, u32) {
+        (self.block_size.0 as u32, self.block_size.1 as u32)
+    }
+
+    /// Bits used per pixel in a plane. (Rounded down).
+    pub fn bits_per_pixel(&self, plane: usize) -> u32 {
+        let (w, h) = self.block_size();
+        self.bytes_per_block(plane) * 8 / (w * h)
+    }
+
+    /// Horizontal chroma subsampling factor.
+    pub fn hsub(&self) -> u32 { self.hsub as u32 }
+
+    /// Vertical chroma subsampling factor.
+    pub fn vsub(&self) -> u32 { self.vsub as u32 }
+
+    pub fn has_alpha(&self) -> bool { self.has_alpha }
+
+    pub fn is_yuv(&self) -> bool { self.is_yuv }
+
+    /// Width in pixels of a plane of an image `width` pixels wide.
+    ///
+    /// Only the chroma planes of multi-plane formats are subsampled.
+    pub fn plane_width(&self, plane: usize, width: u32) -> u32 {
+        if plane == 0 || self.num_planes == 1 {
+            width
+        } else {
+            width.div_ceil(self.hsub())
+        }
+    }
+
+    /// Height in pixels of a plane of an image `height` pixels high.
+    pub fn plane_height(&self, plane: usize, height: u32) -> u32 {
+        if plane == 0 || self.num_planes == 1 {
+            height
+        } else {
+            height.div_ceil(self.vsub())
+        }
+    }
+
+    /// The smallest pitch (bytes per row) that can hold a row of a plane.
+    pub fn min_pitch(&self, plane: usize, width: u32) -> u32 {
+        let (bw, _) = self.block_size();
+        let blocks = self.plane_width(plane, width).div_ceil(bw);
+        blocks * self.bytes_per_block(plane)
+    }
+
+    /// Bytes needed for a plane with the given pitch.
+    pub fn plane_size(&self, plane: usize, height: u32, pitch: u32) -> u64 {
+        let (_, bh) = self.block_size();
+        let rows = self.plane_height(plane, height).div_ceil(bh);
+        rows as u64 * pitch as u64
+    }
 }
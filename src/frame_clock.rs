@@ -0,0 +1,270 @@
+//! Estimating when vblanks happen.
+//!
+//! A `FrameClock` is fed the vblank and page flip events for a CRTC
+//! and works out the real refresh period from their timestamps (the
+//! mode's is only nominal), predicts when the next vblanks will be,
+//! counts dropped frames and keeps presentation latency statistics.
+//!
+//! It doesn't read the clock or the device itself, so it can be fed
+//! made up timestamps.
+//!
+//! ```ignore
+//! let mut clock = FrameClock::from_mode(&mode).unwrap().crtc(crtc);
+//! clock.flip_submitted(DrmTimestamp::new(dev.event_clock(), dev.event_clock().now()));
+//! master.page_flip(crtc, fb, PAGE_FLIP_EVENT, 0)?;
+//! clock.observe(&dev.read_event()?);
+//! let next = clock.next_vblank();
+//! ```
+
+use std::time::Duration;
+use event::{DrmTimestamp, Event};
+use mode::{Crtc, Id, ModeInfo};
+
+/// Weight of a new interval in the period estimate, as a shift: each
+/// interval moves the estimate 1/8 of the way.
+const SMOOTHING_SHIFT: u32 = 3;
+/// After this many intervals in a row have been rejected, the estimate
+/// is assumed wrong (the mode changed, say) and restarted.
+const MAX_REJECTED: u32 = 8;
+/// Intervals shorter than this are ignored. Some drivers give a disabled
+/// CRTC's vblanks all the same timestamp.
+const MIN_INTERVAL_NS: u64 = 1_000;
+
+/// Presentation latency statistics. See `FrameClock::latency`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct LatencyStats {
+    /// Number of flips measured.
+    pub count: u64,
+    pub min: Duration,
+    pub max: Duration,
+    pub mean: Duration,
+    pub last: Duration,
+}
+
+/// Estimates the refresh period of a CRTC from its events.
+#[derive(Clone, Debug)]
+pub struct FrameClock {
+    crtc_id: Option<u32>,
+    nominal: Duration,
+    period_ns: u64,
+    last_vblank: Option<(u64, DrmTimestamp)>,
+    last_flip_seq: Option<u64>,
+    samples: u64,
+    rejected: u32,
+    dropped: u64,
+    submitted: Option<DrmTimestamp>,
+    latency: Option<LatencyStats>,
+    latency_total: Duration,
+}
+
+impl FrameClock {
+    /// A clock whose refresh period is thought to be `nominal`, until
+    /// events say otherwise.
+    ///
+    /// # Panics
+    ///
+    /// If `nominal` is zero.
+    pub fn new(nominal: Duration) -> FrameClock {
+        assert!(nominal > Duration::from_secs(0), "Refresh period must be more than zero");
+        FrameClock {
+            crtc_id: None,
+            nominal,
+            period_ns: duration_ns(nominal),
+            last_vblank: None,
+            last_flip_seq: None,
+            samples: 0,
+            rejected: 0,
+            dropped: 0,
+            submitted: None,
+            latency: None,
+            latency_total: Duration::from_secs(0),
+        }
+    }
+
+    /// A clock starting from the refresh period of a mode. `None` if
+    /// the mode has no timings.
+    pub fn from_mode(mode: &ModeInfo) -> Option<FrameClock> {
+        mode.frame_period().map(FrameClock::new)
+    }
+
+    /// Only use events for this CRTC. Events that don't say which CRTC
+    /// they're for (on kernels older than 4.12) are still used.
+    pub fn crtc(mut self, crtc_id: Id<Crtc>) -> FrameClock {
+        self.crtc_id = Some(crtc_id.as_u32());
+        self
+    }
+
+    /// Feed an event. Returns `false` if it was ignored: not a vblank
+    /// or page flip, or for another CRTC.
+    pub fn observe(&mut self, event: &Event) -> bool {
+        if let (Some(want), Some(crtc_id)) = (self.crtc_id, event.crtc_id()) {
+            if want != crtc_id {
+                return false;
+            }
+        }
+        match *event {
+            Event::VBlank { seq, tv, .. } => self.add_vblank(seq as u64, tv),
+            Event::PageFlip { seq, tv, .. } => self.add_flip(seq as u64, tv),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Record that vblank `seq` happened at `time`.
+    pub fn add_vblank(&mut self, seq: u64, time: DrmTimestamp) {
+        if let Some((last_seq, last_time)) = self.last_vblank {
+            // The legacy counter is 32 bits, so allow for it wrapping.
+            let frames = (seq as u32).wrapping_sub(last_seq as u32) as u64;
+            if frames == 0 || frames > u32::MAX as u64 / 2 {
+                // A repeat, or older than the last one.
+                return;
+            }
+            if let Some(elapsed) = time.duration_since(last_time) {
+                self.add_interval(duration_ns(elapsed) / frames);
+            }
+        }
+        self.last_vblank = Some((seq, time));
+    }
+
+    /// Record that a page flip happened on vblank `seq`, at `time`.
+    ///
+    /// Vblanks between this flip and the last one are counted as
+    /// dropped frames: this assumes a new frame is meant to be shown
+    /// every vblank.
+    pub fn add_flip(&mut self, seq: u64, time: DrmTimestamp) {
+        if let Some(last) = self.last_flip_seq {
+            let gap = (seq as u32).wrapping_sub(last as u32) as u64;
+            if gap > 1 && gap <= u32::MAX as u64 / 2 {
+                self.dropped += gap - 1;
+            }
+        }
+        self.last_flip_seq = Some(seq);
+        if let Some(submitted) = self.submitted.take() {
+            if let Some(latency) = time.duration_since(submitted) {
+                self.add_latency(latency);
+            }
+        }
+        self.add_vblank(seq, time);
+    }
+
+    /// Record when the next page flip was submitted, to measure the
+    /// latency until it's shown.
+    pub fn flip_submitted(&mut self, time: DrmTimestamp) {
+        self.submitted = Some(time);
+    }
+
+    fn add_interval(&mut self, interval_ns: u64) {
+        if interval_ns < MIN_INTERVAL_NS {
+            return;
+        }
+        let period = self.period_ns;
+        if interval_ns < period - period / 4 || interval_ns > period + period / 4 {
+            // Way off: a late timestamp, or the period has changed.
+            self.rejected += 1;
+            if self.rejected < MAX_REJECTED {
+                return;
+            }
+            self.period_ns = interval_ns;
+        } else if interval_ns >= period {
+            self.period_ns += (interval_ns - period) >> SMOOTHING_SHIFT;
+        } else {
+            self.period_ns -= (period - interval_ns) >> SMOOTHING_SHIFT;
+        }
+        self.rejected = 0;
+        self.samples += 1;
+    }
+
+    fn add_latency(&mut self, latency: Duration) {
+        self.latency_total += latency;
+        let stats = match self.latency {
+            Some(stats) => LatencyStats {
+                count: stats.count + 1,
+                min: stats.min.min(latency),
+                max: stats.max.max(latency),
+                mean: stats.mean,
+                last: latency,
+            },
+            None => LatencyStats { count: 1, min: latency, max: latency, mean: latency, last: latency },
+        };
+        let mean = self.latency_total.as_nanos() / stats.count as u128;
+        self.latency = Some(LatencyStats { mean: Duration::from_nanos(mean as u64), ..stats });
+    }
+
+    /// The refresh period it started with.
+    pub fn nominal_period(&self) -> Duration { self.nominal }
+
+    /// The estimated refresh period.
+    pub fn period(&self) -> Duration { Duration::from_nanos(self.period_ns) }
+
+    /// The estimated refresh rate, in Hz.
+    pub fn refresh_rate(&self) -> f64 {
+        1e9 / self.period_ns as f64
+    }
+
+    /// Number of intervals between vblanks the estimate is based on.
+    pub fn samples(&self) -> u64 { self.samples }
+
+    /// Number of vblanks that passed without a page flip.
+    pub fn dropped_frames(&self) -> u64 { self.dropped }
+
+    /// Latency from `flip_submitted` to the flip. `None` until a flip
+    /// has been measured.
+    pub fn latency(&self) -> Option<LatencyStats> { self.latency }
+
+    /// The last vblank seen, and when it was.
+    pub fn last_vblank(&self) -> Option<(u64, DrmTimestamp)> { self.last_vblank }
+
+    /// When the `n` vblanks after the last one seen should happen. Empty
+    /// until a vblank has been seen.
+    pub fn predict(&self, n: usize) -> Vec<(u64, DrmTimestamp)> {
+        match self.last_vblank {
+            Some((seq, time)) => self.predict_from(seq, time, 1, n),
+            None => Vec::new(),
+        }
+    }
+
+    /// The `n` vblanks after `time` (say, now). Empty until a vblank
+    /// has been seen.
+    pub fn predict_after(&self, time: DrmTimestamp, n: usize) -> Vec<(u64, DrmTimestamp)> {
+        match self.last_vblank {
+            Some((seq, last)) => {
+                let skip = match time.duration_since(last) {
+                    Some(elapsed) => duration_ns(elapsed) / self.period_ns,
+                    None => 0,
+                };
+                self.predict_from(seq, last, skip + 1, n)
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// The vblank nearest to `time`, but after the last one seen. For
+    /// showing a frame as close to a time as possible. `None` until a
+    /// vblank has been seen.
+    pub fn vblank_at(&self, time: DrmTimestamp) -> Option<(u64, DrmTimestamp)> {
+        let (seq, last) = self.last_vblank?;
+        let k = match time.duration_since(last) {
+            Some(elapsed) => ((duration_ns(elapsed) + self.period_ns / 2) / self.period_ns).max(1),
+            None => 1,
+        };
+        self.predict_from(seq, last, k, 1).pop()
+    }
+
+    /// The next vblank after the last one seen.
+    pub fn next_vblank(&self) -> Option<(u64, DrmTimestamp)> {
+        self.predict(1).pop()
+    }
+
+    fn predict_from(&self, seq: u64, time: DrmTimestamp, first: u64, n: usize)
+                    -> Vec<(u64, DrmTimestamp)>
+    {
+        (first..first + n as u64).map(|k| {
+            let offset = Duration::from_nanos(self.period_ns.saturating_mul(k));
+            (seq + k, DrmTimestamp::new(time.clock(), time.as_duration() + offset))
+        }).collect()
+    }
+}
+
+fn duration_ns(duration: Duration) -> u64 {
+    duration.as_nanos().min(u64::MAX as u128) as u64
+}
@@ -0,0 +1,125 @@
+//! GEM buffer objects.
+//!
+//! GEM is how DRM drivers manage buffers. A process refers to a buffer
+//! by a handle, which belongs to its open device file. Handles can be
+//! shared with other processes by giving the buffer a global "flink"
+//! name, which anyone can open.
+//!
+//! Flink names are a legacy interface: any process that can guess a
+//! name can open the buffer. New code should share buffers with PRIME
+//! instead.
+
+use std::{io, fmt};
+use std::os::raw::c_ulong;
+use std::os::unix::io::OwnedFd;
+use ioctl_vals::*;
+use prime::PrimeFlags;
+use {ffi, Device, DrmIoctl};
+
+impl DrmIoctl for ffi::gem_close {
+    fn request() -> c_ulong { DRM_IOCTL_GEM_CLOSE }
+}
+
+impl DrmIoctl for ffi::gem_flink {
+    fn request() -> c_ulong { DRM_IOCTL_GEM_FLINK }
+}
+
+impl DrmIoctl for ffi::gem_open {
+    fn request() -> c_ulong { DRM_IOCTL_GEM_OPEN }
+}
+
+/// A global name for a GEM object. See `GemObject::flink`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FlinkName(pub u32);
+
+/// An owned GEM handle. Closed on drop.
+///
+/// Handles are counted per device: opening a flink name that's already
+/// open (or was given out by this device) gives back the same handle,
+/// and it's only closed once every `GemObject` for it has been dropped.
+pub struct GemObject {
+    handle: u32,
+    size: Option<u64>,
+    dev: Device,
+}
+
+impl GemObject {
+    /// Take ownership of a handle.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must be a GEM handle of `dev`, that won't be closed by
+    /// anything else.
+    pub unsafe fn from_handle(dev: &Device, handle: u32) -> io::Result<GemObject> {
+        GemObject::adopt(dev, handle, None)
+    }
+
+    /// Take a reference to a handle we got from the kernel.
+    pub(crate) fn adopt(dev: &Device, handle: u32, size: Option<u64>) -> io::Result<GemObject> {
+        let dev = dev.try_clone()?;
+        dev.gem_ref(handle);
+        Ok(GemObject { handle, size, dev })
+    }
+
+    /// Open a buffer by its flink name.
+    pub fn open(dev: &Device, name: FlinkName) -> io::Result<GemObject> {
+        let dev = dev.try_clone()?;
+        let (handle, size) = dev.gem_open(name.0)?;
+        Ok(GemObject { handle, size, dev })
+    }
+
+    /// Give the buffer a global name, so other processes can open it.
+    ///
+    /// A buffer only ever has one name, calling this again returns the
+    /// same one.
+    pub fn flink(&self) -> io::Result<FlinkName> {
+        flink(&self.dev, self.handle, self.size)
+    }
+
+    /// Export the buffer as a dma-buf file descriptor. See
+    /// `Device::prime_export`.
+    pub fn prime_export(&self, flags: PrimeFlags) -> io::Result<OwnedFd> {
+        self.dev.prime_export(self.handle, flags)
+    }
+
+    /// Another owner of the same handle.
+    pub fn try_clone(&self) -> io::Result<GemObject> {
+        let dev = self.dev.try_clone()?;
+        dev.gem_ref(self.handle);
+        Ok(GemObject { handle: self.handle, size: self.size, dev })
+    }
+
+    /// The handle. It's only valid as long as this object is alive.
+    pub fn handle(&self) -> u32 { self.handle }
+
+    /// Size of the buffer in bytes, if known. (Only known for buffers
+    /// opened by name or imported.)
+    pub fn size(&self) -> Option<u64> { self.size }
+}
+
+/// Give the buffer behind a handle a flink name.
+pub(crate) fn flink(dev: &Device, handle: u32, size: Option<u64>) -> io::Result<FlinkName> {
+    let mut flink = ffi::gem_flink { handle, ..Default::default() };
+    dev.ioctl(&mut flink)?;
+    dev.gem_named(flink.name, handle, size);
+    Ok(FlinkName(flink.name))
+}
+
+impl fmt::Debug for GemObject {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GemObject")
+            .field("handle", &self.handle)
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+impl Drop for GemObject {
+    fn drop(&mut self) {
+        if self.dev.gem_unref(self.handle) {
+            // Nothing sensible to do if this fails.
+            let mut close = ffi::gem_close { handle: self.handle, ..Default::default() };
+            self.dev.ioctl(&mut close).ok();
+        }
+    }
+}
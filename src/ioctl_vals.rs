@@ -68,6 +68,8 @@ pub const DRM_IOCTL_SG_ALLOC: c_ulong = 3222299704;
 pub const DRM_IOCTL_SG_FREE: c_ulong = 1074816057;
 
 pub const DRM_IOCTL_WAIT_VBLANK: c_ulong = 3222823994;
+pub const DRM_IOCTL_CRTC_GET_SEQUENCE: c_ulong = 3222823995;
+pub const DRM_IOCTL_CRTC_QUEUE_SEQUENCE: c_ulong = 3222823996;
 
 pub const DRM_IOCTL_UPDATE_DRAW: c_ulong = 1075340351;
 
@@ -104,3 +106,18 @@ pub const DRM_IOCTL_MODE_CURSOR2: c_ulong = 3223610555;
 pub const DRM_IOCTL_MODE_ATOMIC: c_ulong = 3224921276;
 pub const DRM_IOCTL_MODE_CREATEPROPBLOB: c_ulong = 3222299837;
 pub const DRM_IOCTL_MODE_DESTROYPROPBLOB: c_ulong = 3221513406;
+pub const DRM_IOCTL_SYNCOBJ_CREATE: c_ulong = 3221775551;
+pub const DRM_IOCTL_SYNCOBJ_DESTROY: c_ulong = 3221775552;
+pub const DRM_IOCTL_SYNCOBJ_HANDLE_TO_FD: c_ulong = 3222824129;
+pub const DRM_IOCTL_SYNCOBJ_FD_TO_HANDLE: c_ulong = 3222824130;
+pub const DRM_IOCTL_SYNCOBJ_WAIT: c_ulong = 3223348419;
+pub const DRM_IOCTL_SYNCOBJ_RESET: c_ulong = 3222299844;
+pub const DRM_IOCTL_SYNCOBJ_SIGNAL: c_ulong = 3222299845;
+pub const DRM_IOCTL_SYNCOBJ_TIMELINE_WAIT: c_ulong = 3223872714;
+pub const DRM_IOCTL_SYNCOBJ_QUERY: c_ulong = 3222824139;
+pub const DRM_IOCTL_SYNCOBJ_TRANSFER: c_ulong = 3223348428;
+pub const DRM_IOCTL_SYNCOBJ_TIMELINE_SIGNAL: c_ulong = 3222824141;
+
+pub const DMA_BUF_IOCTL_SYNC: c_ulong = 1074291200;
+pub const SYNC_IOC_MERGE: c_ulong = 3224387075;
+pub const SYNC_IOC_FILE_INFO: c_ulong = 3224911364;
@@ -12,27 +12,55 @@ extern crate libc;
 #[macro_use]
 extern crate bitflags;
 extern crate memmap;
+#[cfg(feature = "futures-core")]
+extern crate futures_core;
 #[cfg(feature = "tokio")]
-extern crate futures;
-#[cfg(feature = "tokio")]
-extern crate tokio_core;
-#[cfg(feature = "tokio")]
-extern crate mio;
+extern crate tokio as tokio_rt;
+#[cfg(feature = "async-io")]
+extern crate async_io as async_io_rt;
+#[cfg(feature = "calloop")]
+extern crate calloop as calloop_rt;
+#[cfg(feature = "mio")]
+extern crate mio as mio_rt;
 
 mod ioctl_vals;
 mod ffi;
 pub mod fourcc;
+pub mod modifier;
+pub mod convert;
+pub mod pixels;
+pub mod draw;
+pub mod shadow;
+pub mod swapchain;
+pub mod event;
+pub mod gem;
+pub mod prime;
+pub mod share;
+pub mod syncobj;
+pub mod vblank;
+pub mod frame_clock;
+pub mod present;
+pub mod sync_file;
+#[cfg(any(feature = "tokio", feature = "async-io"))]
+pub mod async_device;
 #[cfg(feature = "tokio")]
 pub mod tokio;
+#[cfg(feature = "async-io")]
+pub mod async_io;
+#[cfg(feature = "calloop")]
+pub mod calloop;
+#[cfg(feature = "mio")]
+mod mio;
 pub mod mode;
 
 // Local imports
 use ioctl_vals::*;
 use mode::*;
+pub use event::Event;
+use event::{Clock, Completion, EventContext};
+use vblank::Target;
 
 // External imports
-#[cfg(feature = "tokio")]
-use tokio_core::reactor::Handle;
 use libc::ioctl;
 use std::ops::{Deref, DerefMut};
 use std::fs::{self, File, OpenOptions};
@@ -41,15 +69,17 @@ use std::io::prelude::*;
 use std::io::ErrorKind;
 use std::io;
 use std::iter::repeat;
-use std::mem::{size_of, transmute};
+use std::mem::transmute;
 use std::os::raw::*;
 use std::os::unix::prelude::*;
 use std::path::{Path, PathBuf};
 use std::str;
 use std::string::FromUtf8Error;
-use std::time::Instant;
 use std::env::var;
 use std::fmt::Debug;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::atomic::{AtomicU32, Ordering};
 
 #[allow(dead_code)]
 mod consts {
@@ -75,6 +105,18 @@ fn check_ioctl_err(ret: c_int) -> io::Result<()> {
     }
 }
 
+/// An ioctl on something other than a DRM device, such as a dma-buf or
+/// sync_file. Retries if interrupted.
+fn fd_ioctl<T>(fd: RawFd, request: c_ulong, arg: &mut T) -> io::Result<()> {
+    loop {
+        let ret = unsafe { ioctl(fd, request, arg as *mut T) };
+        return match check_ioctl_err(ret) {
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            other => other,
+        };
+    }
+}
+
 const BUFFER_CAPACITY: usize = 1024;
 
 /// Open fd for a dri device. Such as /dev/dri/card0.
@@ -82,6 +124,38 @@ const BUFFER_CAPACITY: usize = 1024;
 pub struct Device {
     fd: BufReader<File>,
     trace: bool,
+    shared: Arc<Shared>,
+}
+
+/// State shared by a `Device` and its clones, which use the same open
+/// file.
+#[derive(Debug, Default)]
+struct Shared {
+    /// GEM handles owned, which belong to the open file.
+    gem_handles: Mutex<GemHandles>,
+    /// Clock event times are on, once it's been asked for.
+    event_clock: OnceLock<Clock>,
+    /// Client capabilities turned on, as bits `1 << cap`.
+    client_caps: AtomicU32,
+    /// The CRTCs, in pipe order, once they've been asked for. They
+    /// don't change while the device is open.
+    crtcs: OnceLock<Vec<Id<Crtc>>>,
+    /// Capabilities checked on hot paths. They don't change either.
+    caps: Mutex<HashMap<Capability, u64>>,
+    /// Which properties used in async commits are "FB_ID".
+    fb_id_props: Mutex<HashMap<u32, bool>>,
+}
+
+#[derive(Debug, Default)]
+struct GemHandles {
+    /// Number of owners of each handle. The same handle can be behind
+    /// several objects, so it can only be closed when all are dropped.
+    counts: HashMap<u32, usize>,
+    /// The handle (and size, if known) of each flink name opened or
+    /// given out. The kernel makes a new handle every time a name is
+    /// opened, so this is used to hand back the one already open, as
+    /// libdrm does.
+    names: HashMap<u32, (u32, Option<u64>)>,
 }
 
 impl Device {    
@@ -122,6 +196,7 @@ impl Device {
                 Device {
                     fd: BufReader::with_capacity(BUFFER_CAPACITY, f),
                     trace: trace,
+                    shared: Arc::default(),
                 }
             })
     }
@@ -130,9 +205,57 @@ impl Device {
         Ok(Device {
             fd: BufReader::with_capacity(0, self.fd.get_ref().try_clone()?),
             trace: self.trace,
+            shared: self.shared.clone(),
         })
     }
 
+    /// Take a reference to a GEM handle.
+    fn gem_ref(&self, handle: u32) {
+        let mut handles = self.shared.gem_handles.lock().unwrap();
+        *handles.counts.entry(handle).or_insert(0) += 1;
+    }
+
+    /// Take a reference to the handle a flink name is open as, opening
+    /// it if it isn't. Returns the handle and the buffer's size, if
+    /// known.
+    fn gem_open(&self, name: u32) -> io::Result<(u32, Option<u64>)> {
+        let mut handles = self.shared.gem_handles.lock().unwrap();
+        let (handle, size) = match handles.names.get(&name) {
+            Some(&open) => open,
+            None => {
+                let mut open = ffi::gem_open { name, ..Default::default() };
+                self.ioctl(&mut open)?;
+                handles.names.insert(name, (open.handle, Some(open.size)));
+                (open.handle, Some(open.size))
+            }
+        };
+        *handles.counts.entry(handle).or_insert(0) += 1;
+        Ok((handle, size))
+    }
+
+    /// Remember that a handle has been given a flink name, so opening
+    /// the name gives the handle back.
+    fn gem_named(&self, name: u32, handle: u32, size: Option<u64>) {
+        let mut handles = self.shared.gem_handles.lock().unwrap();
+        handles.names.entry(name).or_insert((handle, size));
+    }
+
+    /// Drop a reference to a GEM handle. Returns `true` if it was the
+    /// last one, and the handle should be closed.
+    fn gem_unref(&self, handle: u32) -> bool {
+        let mut handles = self.shared.gem_handles.lock().unwrap();
+        match handles.counts.get_mut(&handle) {
+            Some(count) if *count > 1 => {
+                *count -= 1;
+                return false;
+            }
+            _ => (),
+        }
+        handles.counts.remove(&handle);
+        handles.names.retain(|_, &mut (named, _)| named != handle);
+        true
+    }
+
     /// Set the file descriptor to non-blocking mode.
     pub fn set_nonblocking(&mut self, nonblocking: bool) -> io::Result<()> {
         unsafe {
@@ -199,13 +322,23 @@ impl Device {
     /// Returns a Invalid Arguement error (`ErrorKind::InvalidInput`)
     /// when another process already is already card master.
     pub fn set_master<'a>(&'a mut self) -> io::Result<Master<'a>> {
+        self.acquire_master()?;
+        Ok(Master { dev: self })
+    }
+
+    /// SET_MASTER, without a guard. For wrappers that track it
+    /// themselves.
+    fn acquire_master(&self) -> io::Result<()> {
         let ret = unsafe {
             ioctl(self.as_raw_fd(), DRM_IOCTL_SET_MASTER, 0)
         };
-        match check_ioctl_err(ret) {
-            Ok(_) => Ok(Master { dev: self }),
-            Err(err) => Err(err),
-        }
+        check_ioctl_err(ret)
+    }
+
+    fn release_master(&self) {
+        let _ret = unsafe {
+            ioctl(self.as_raw_fd(), DRM_IOCTL_DROP_MASTER, 0)
+        };
     }
 
     /// Get driver version information.
@@ -280,6 +413,21 @@ impl Device {
         Ok(props.into_iter().zip(prop_values.into_iter()).collect())
     }
 
+    /// Look up a property of an object by name.
+    ///
+    /// Returns the property's id and current value, or `None` if the
+    /// object doesn't have a property with that name.
+    pub fn find_property<T: mode::Resource>(&self, id: Id<T>, name: &str)
+                                            -> io::Result<Option<(Id<Property>, u64)>>
+    {
+        for (prop_id, value) in self.get_object_props(id)? {
+            if self.get(prop_id)?.name() == name {
+                return Ok(Some((prop_id, value)));
+            }
+        }
+        Ok(None)
+    }
+
     /// Fetches the busid of the card.
     ///
     /// Dev notes: on my system this is an empty string. In `xf86drm.h`
@@ -308,33 +456,35 @@ impl Device {
     /// Request a vblank event, that can be late read by an read_event.
     ///
     /// `crtc_num` is the index of the CRTC id returned by `Resources::crtcs`.
+    /// See `request_vblank_event` to use the CRTC's id instead.
     pub fn request_vblank(&self, data: usize, crtc_num: u32) -> io::Result<()> {
+        self.vblank_ioctl(crtc_num, Target::Relative(1), false, Some(data as u64)).map(|_| ())
+    }
 
-        // Originally there were at most 2 CRTC's and so a "Secondary"
-        // flag was sufficient to switch between them.
-        // Now we have more that 2 so things are weird.
-        let pipe = crtc_num;
-        let mut seq_type = if pipe > 1 {
-            ((pipe << 1) as u32) & consts::DRM_VBLANK_HIGH_CRTC_MASK
-        } else if pipe > 0 {
-            consts::DRM_VBLANK_SECONDARY
-        } else {
-            0
-        };
-
-        seq_type |= consts::DRM_VBLANK_RELATIVE;
-        seq_type |= consts::DRM_VBLANK_EVENT;
+    /// Request a vblank event that calls `callback` when it's
+    /// dispatched by `ctx`. See `dispatch_events`.
+    pub fn request_vblank_with<'a, F>(&self, ctx: &mut EventContext<'a>, crtc: Id<Crtc>,
+                                      callback: F) -> io::Result<()>
+        where F: FnOnce(Completion) + 'a
+    {
+        let token = ctx.token(callback);
+        self.request_vblank_event(crtc, Target::Relative(1), false, token).map(|_| ())
+            .inspect_err(|_| {
+                ctx.cancel(token);
+            })
+    }
 
-        impl DrmIoctl for ffi::wait_vblank_request {
-            fn request() -> c_ulong { DRM_IOCTL_WAIT_VBLANK }
-        }
-        let mut req = ffi::wait_vblank_request {
-            type_: unsafe { transmute(seq_type) },
-            sequence: 1,
-            signal: data as c_ulong,
-        };
-        
-        self.ioctl(&mut req)
+    /// The clock vblank and page flip event times are on.
+    ///
+    /// Worked out from `Capability::TimestampMonotonic` the first time
+    /// it's needed. Drivers that don't report it use the realtime clock.
+    pub fn event_clock(&self) -> Clock {
+        *self.shared.event_clock.get_or_init(|| {
+            match self.capability(Capability::TimestampMonotonic) {
+                Ok(0) | Err(_) => Clock::Realtime,
+                Ok(_) => Clock::Monotonic,
+            }
+        })
     }
 
     /// Reads the next available event.
@@ -347,65 +497,64 @@ impl Device {
     /// * If suprising data is returned `ErrorKind::InvalidData`.
     /// * Any other `io:Error` from the kernel.
     pub fn read_event(&mut self) -> io::Result<Event> {
-
-        // This needs a good cleanup.
-        
-        let nbytes;
-        let result = {
-            let buf: &[u8] = try!(self.fd.fill_buf());
-
-            if buf.len() == 0 {
-                // I have a feeling this won't happen.
-                // But if it does this is most sensible.
+        let clock = self.event_clock();
+        let (result, used) = {
+            let buf = self.fd.fill_buf()?;
+            if buf.is_empty() {
                 return Err(io::Error::new(io::ErrorKind::WouldBlock, "No events available"));
             }
+            let mut events = event::parse_events(buf, clock);
+            let result = events.next().expect("buffer is not empty");
+            (result, events.consumed())
+        };
+        self.fd.consume(used);
+        result
+    }
 
-            assert!(buf.len() >= size_of::<ffi::event>(),
-                    "Malformed event from drm device: {} < {}",
-                    buf.len(), size_of::<ffi::event>());
-
-            // Doing things the C way. There shouldn't be any byte-order issues.
-            let ev_header: ffi::event = unsafe {
-                *transmute::<_, *const ffi::event>(buf.as_ptr())
-            };
-
-            if buf.len() < ev_header.length as usize {
-                nbytes = buf.len();
-                Err(io::Error::new(ErrorKind::InvalidData, "Short DRM event"))
-            } else {
-                nbytes = ev_header.length as usize;
-                match ev_header.type_ {
-                    consts::DRM_EVENT_VBLANK | consts::DRM_EVENT_FLIP_COMPLETE => {
-                        let ev: ffi::event_vblank = unsafe {
-                            *transmute::<_, *const ffi::event_vblank>(buf.as_ptr())
-                        };
-                        
-                        // This is not good.  This works fine on linux
-                        // for now, as Instant is stored as a timespec.
-                        // But it may not be portable and it may break.
-                        let at: Instant = unsafe {
-                            transmute(libc::timespec { tv_sec: ev.tv_sec as i64,
-                                                       tv_nsec: ev.tv_usec as i64 * 1000 })
-                        };
-                        
-                        match ev.base.type_ {
-                            consts::DRM_EVENT_VBLANK => Ok(Event::VBlank {
-                                seq: ev.sequence,
-                                tv: at,
-                                user: ev.user_data }),
-                            consts::DRM_EVENT_FLIP_COMPLETE => Ok(Event::PageFlip {
-                                seq: ev.sequence,
-                                tv: at,
-                                user: ev.user_data }),
-                            _ => unreachable!(),
-                        }
-                    }
-                    _ => Ok(Event::Unknown),
+    /// Reads every event returned by a single read of the device.
+    ///
+    /// Events that fail to parse are skipped.
+    ///
+    /// # Errors
+    ///
+    /// As `read_event`. `ErrorKind::InvalidData` is only returned if no
+    /// event could be parsed.
+    pub fn read_events(&mut self) -> io::Result<Vec<Event>> {
+        let clock = self.event_clock();
+        let (events, error) = {
+            let buf = self.fd.fill_buf()?;
+            if buf.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::WouldBlock, "No events available"));
+            }
+            let mut events = Vec::new();
+            let mut error = None;
+            for event in event::parse_events(buf, clock) {
+                match event {
+                    Ok(event) => events.push(event),
+                    Err(err) => error = Some(err),
                 }
             }
+            (events, error)
         };
-        self.fd.consume(nbytes);
-        result
+        self.fd.consume(self.fd.buffer().len());
+        match error {
+            Some(err) if events.is_empty() => Err(err),
+            _ => Ok(events),
+        }
+    }
+
+    /// Read the pending events and pass each to `ctx`. Returns how
+    /// many were read.
+    ///
+    /// # Errors
+    ///
+    /// As `read_events`.
+    pub fn dispatch_events(&mut self, ctx: &mut EventContext) -> io::Result<usize> {
+        let events = self.read_events()?;
+        for event in &events {
+            ctx.dispatch(event);
+        }
+        Ok(events.len())
     }
 
     /// Get the capability or value associated with a given capability.
@@ -422,6 +571,13 @@ impl Device {
         Ok(call.value)
     }
 
+    /// `capability`, only asked for the first time. Errors (from
+    /// capabilities the kernel doesn't know) count as 0.
+    pub(crate) fn cached_capability(&self, cap: Capability) -> u64 {
+        let mut caps = self.shared.caps.lock().unwrap();
+        *caps.entry(cap).or_insert_with(|| self.capability(cap).unwrap_or(0))
+    }
+
     /// Note: All the current caps are boolean (0 or 1) values. If
     /// this changes in future we may need to adjust this.
     ///
@@ -431,18 +587,39 @@ impl Device {
             capability: cap as u64,
             value: to as u64,
         };
-        self.ioctl(&mut call)
+        self.ioctl(&mut call)?;
+        let mut bits = 1 << cap as u32;
+        if cap == ClientCapability::Atomic && to {
+            // Atomic turns on universal planes too.
+            bits |= 1 << ClientCapability::UniversalPlanes as u32;
+        }
+        if to {
+            self.shared.client_caps.fetch_or(bits, Ordering::Relaxed);
+        } else {
+            self.shared.client_caps.fetch_and(!bits, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /// Has a client capability been turned on with
+    /// `set_client_capability`? (On this `Device`: the kernel can't be
+    /// asked.)
+    pub fn has_client_capability(&self, cap: ClientCapability) -> bool {
+        self.shared.client_caps.load(Ordering::Relaxed) & (1 << cap as u32) != 0
     }
 
 
+    /// Make the device non-blocking and read its events
+    /// asynchronously. Must be called inside a tokio runtime.
     #[cfg(feature = "tokio")]
-    pub fn event_stream(self, handle: &Handle) -> io::Result<tokio::EventFuture> {
-        tokio::EventFuture::new(self, handle)
+    pub fn event_stream(self) -> io::Result<tokio::AsyncDevice> {
+        tokio::AsyncDevice::new(self)
     }
 }
 
 
 #[repr(u64)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum Capability {
     DumbBuffer = 0x1,
     VblankHighCrtc = 0x2,
@@ -457,6 +634,14 @@ pub enum Capability {
     CursorWidth = 0x8,
     CursorHeight = 0x9,
     Addfb2Modifiers = 0x10,
+    /// Page flips can target a vblank. See `Master::page_flip_target`.
+    PageFlipTarget = 0x11,
+    /// Sync objects are supported. See `syncobj`.
+    SyncObj = 0x13,
+    /// Sync objects have timeline points.
+    SyncObjTimeline = 0x14,
+    /// Atomic commits can use `ATOMIC_PAGE_FLIP_ASYNC`.
+    AtomicAsyncPageFlip = 0x15,
 }
 
 // TODO: Should  probably make Rusty-enums for these.
@@ -464,6 +649,7 @@ pub enum Capability {
 /// change the behavior.  This is usaully to add features that would
 /// break existing software if features were added everywhere.
 #[repr(u64)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum ClientCapability {
     /// DRM_CLIENT_CAP_STEREO_3D
     ///
@@ -482,6 +668,12 @@ pub enum ClientCapability {
     ///
     /// If set to 1, the DRM core will expose atomic properties to userspace
     Atomic = 3,
+
+    /// DRM_CLIENT_CAP_WRITEBACK_CONNECTORS
+    ///
+    /// If set to 1, the DRM core will expose writeback connectors to
+    /// userspace. Needs `Atomic` to be set first.
+    WritebackConnectors = 5,
 }
  
 impl AsRawFd for Device {
@@ -489,6 +681,11 @@ impl AsRawFd for Device {
         self.fd.get_ref().as_raw_fd()
     }
 }
+impl AsFd for Device {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.get_ref().as_fd()
+    }
+}
 impl IntoRawFd for Device {
     fn into_raw_fd(self) -> RawFd {
         self.fd.into_inner().into_raw_fd()
@@ -498,7 +695,8 @@ impl FromRawFd for Device {
     unsafe fn from_raw_fd(fd: RawFd) -> Device {
         Device {
             fd: BufReader::with_capacity(BUFFER_CAPACITY, File::from_raw_fd(fd)),
-            trace: false
+            trace: false,
+            shared: Arc::default(),
         }
     }
 }
@@ -564,9 +762,7 @@ impl<'a> DerefMut for Master<'a> {
 
 impl<'a> Drop for Master<'a> {
     fn drop(&mut self) {
-        let _ret = unsafe {
-            ioctl(self.dev.as_raw_fd(), DRM_IOCTL_DROP_MASTER, 0)
-        };
+        self.dev.release_master();
     }
 }
 
@@ -700,119 +896,3 @@ pub enum EventType {
     /// Event is after page flip has finshed.
     FlipComplete,
 }
-
-/// Event types read from device. Event's are sent only after a
-/// request vblank has been called or a page flip has been issued.
-///
-/// Warning: `Instant` values may be from the future.
-/// The driver appears to return when a VBlank *will* happen. Not when it did happen.
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
-pub enum Event {
-    /// Event type unknown to this library.
-    Unknown,
-    VBlank { seq: u32, tv: Instant, user: u64 },
-    PageFlip { seq: u32, tv: Instant, user: u64 },
-}
-
-// First attempt at event.
-// I should probably remove it.
-// But it is actaully cleaner than the current one.
-
-/// Device event.
-///
-/// Event's are a way for the compositor to tell us when vblank or a
-/// pageflip occurs. They are accessed via the read syscall and the
-/// device can be added to select, poll or epoll to check if has any
-/// event's available.
-#[repr(C)]
-#[derive(Copy, Clone, Debug)]
-struct Event_ {
-    type_: EventType,
-    user_data: u64,
-    time: Instant,
-    sequence: u32,
-}
-
-#[allow(dead_code)]
-impl Event_
-{
-    /// User data passed to event request or page flip command.
-    /// (vblank requests can only accept usize, but pageflip commands
-    /// can pass u64, this is probably due to the relative ages of apis)
-    pub fn user_data(&self) -> u64 { self.user_data }
-    /// Type of the event. Either a `VBlank` or `FlipComplete`.
-    pub fn event_type(&self) -> EventType { self.type_ }
-    /// Monotonic sequence number generated by the driver.
-    pub fn sequence(&self) -> u32 { self.sequence }
-    /// The time of the event.
-    /// 
-    /// This time may be in the future. So avoid calling
-    /// `.elapsed`. Consider only comparing it to other times produced
-    /// by events.
-    pub fn time_val(&self) -> Instant { self.time }
-    
-    /// Read the next event on the socket.
-    ///
-    /// This reads a single event from the socket returns it.
-    /// This requires mut because:
-    ///
-    /// * Consuming an event could cause problems if done in multiple places
-    ///   as mut guarentees on aliasing, we can safely read it.
-    /// * &mut is required for calling Read::read.
-    fn read_event(dev: &mut Device) -> io::Result<Event_>
-    {
-        // There are several transmutes here.
-        // transumte does require both types have the same 
-        
-        // This is kinda gross, but it saves me from manually
-        // implementing an iterator (TODO!).
-        const EVENT_BYTES: usize = 32;
-        let mut buf = [0; EVENT_BYTES];
-        
-        let n = try!(dev.fd.read(&mut buf[..]));
-        if n == 0 {
-            return Err(io::Error::new(io::ErrorKind::WouldBlock, "no events available"));
-        }
-        // TODO: do something smarter than panicing.
-        // (Future versions of drm might add smaller events).
-        // (We don't get partial events. DRM guarantess that).
-        assert_eq!(EVENT_BYTES, n);
-
-        let ev: ffi::event_vblank = unsafe {
-            transmute(buf)
-        };
-
-        let type_ = match ev.base.type_ {
-            consts::DRM_EVENT_VBLANK => EventType::VBlank,
-            consts::DRM_EVENT_FLIP_COMPLETE => EventType::FlipComplete,
-            _ => return Err(io::Error::new(io::ErrorKind::InvalidData,
-                                           "Unknow DRM event type")),
-        };
-
-        // Well... std::time::Instant is nice.
-        // There's no nice way to create one.
-        let at: Instant = unsafe {
-            transmute(libc::timespec { tv_sec: ev.tv_sec as i64,
-                                       tv_nsec: ev.tv_usec as i64 * 1000 })
-        };
-
-        println!("current instant: {:?}", Instant::now());
-        println!("   evil instant: {:?}", at);
-
-        // // Calling this will check if at is in the past.
-        // // Otherwise it will panic.
-        // at.elapsed();
-        
-        // Turns out that always panics: my driver returns vblank
-        // events from the Future.  I'm assuming that the driver is
-        // returned the time vblank actually occurs. But the event is
-        // sent before it occurs. 
-
-        Ok(Event_ {
-            type_: type_,
-            user_data: ev.user_data,
-            time: at,
-            sequence: ev.sequence,
-        })
-    }
-}
@@ -0,0 +1,30 @@
+//! mio 0.8 support.
+
+use Device;
+use mio_rt::{Interest, Registry, Token};
+use mio_rt::event::Source;
+use mio_rt::unix::SourceFd;
+
+use std::io;
+use std::os::unix::io::AsRawFd;
+
+/// Register a device with a `mio::Poll`, to be woken when it has events
+/// to read. Make it non-blocking first (`Device::set_nonblocking`), and
+/// read until `read_event` returns `ErrorKind::WouldBlock`.
+impl Source for Device {
+    fn register(&mut self, registry: &Registry, token: Token, interests: Interest)
+                -> io::Result<()>
+    {
+        SourceFd(&self.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(&mut self, registry: &Registry, token: Token, interests: Interest)
+                  -> io::Result<()>
+    {
+        SourceFd(&self.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        SourceFd(&self.as_raw_fd()).deregister(registry)
+    }
+}
@@ -0,0 +1,268 @@
+//! Atomic mode setting.
+//!
+//! An atomic commit sets any number of properties on any number of
+//! objects at once, and either all of it happens or none of it does.
+//! Needs `ClientCapability::Atomic`.
+//!
+//! ```ignore
+//! let mut req = AtomicRequest::new();
+//! req.add_property_by_name(&dev, plane, "FB_ID", fb.as_u32() as u64)?;
+//! req.in_fence(&dev, plane, &render_done)?;
+//! req.out_fence(&dev, crtc)?;
+//! let fences = master.atomic_commit(&mut req, ATOMIC_NONBLOCK, 0)?;
+//! for (crtc, fence) in fences.crtcs { ... }
+//! ```
+
+use std::{io, fmt};
+use std::os::raw::c_ulong;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use ioctl_vals::*;
+use sync_file::SyncFile;
+use super::{ffi, Connector, Crtc, FlipMode, Id, Plane, Property, Resource};
+use {Capability, ClientCapability, Device, DrmIoctl, Master};
+
+bitflags! {
+    pub flags AtomicFlags: u32
+    {
+        /// Send an `Event::PageFlip` for each CRTC when the commit
+        /// completes.
+        const ATOMIC_PAGE_FLIP_EVENT = 0x01,
+        /// Flip now, without waiting for vblank. (May tear.)
+        const ATOMIC_PAGE_FLIP_ASYNC = 0x02,
+        /// Only check the commit would work.
+        const ATOMIC_TEST_ONLY       = 0x0100,
+        /// Return without waiting for the commit to complete.
+        const ATOMIC_NONBLOCK        = 0x0200,
+        /// Allow changes that need a full mode set.
+        const ATOMIC_ALLOW_MODESET   = 0x0400,
+    }
+}
+
+impl DrmIoctl for ffi::atomic {
+    fn request() -> c_ulong { DRM_IOCTL_MODE_ATOMIC }
+}
+
+/// What an out-fence is for.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum FenceFor {
+    Crtc(Id<Crtc>),
+    Writeback(Id<Connector>),
+}
+
+/// Out-fences from `Master::atomic_commit`.
+#[derive(Debug, Default)]
+pub struct OutFences {
+    /// Signal when the commit takes effect on each CRTC.
+    pub crtcs: Vec<(Id<Crtc>, SyncFile)>,
+    /// Signal when each writeback connector has finished writing.
+    pub writeback: Vec<(Id<Connector>, SyncFile)>,
+}
+
+/// A set of property changes to commit together.
+#[derive(Default)]
+pub struct AtomicRequest {
+    // (object, property, value), in the order they were added.
+    props: Vec<(u32, u32, u64)>,
+    // Where the kernel writes each out-fence. Boxed so the addresses
+    // stay put.
+    out_fences: Vec<(FenceFor, Box<i32>)>,
+}
+
+impl AtomicRequest {
+    pub fn new() -> AtomicRequest {
+        AtomicRequest::default()
+    }
+
+    /// Set a property of an object. Setting the same property again
+    /// replaces the value.
+    pub fn add_property<T: Resource>(&mut self, obj: Id<T>, prop: Id<Property>, value: u64) {
+        let (obj, prop) = (obj.as_u32(), prop.as_u32());
+        match self.props.iter_mut().find(|&&mut (o, p, _)| o == obj && p == prop) {
+            Some(entry) => entry.2 = value,
+            None => self.props.push((obj, prop, value)),
+        }
+    }
+
+    /// Set a property of an object, looking it up by name.
+    ///
+    /// # Errors
+    ///
+    /// `ErrorKind::NotFound` if the object has no such property.
+    pub fn add_property_by_name<T: Resource>(&mut self, dev: &Device, obj: Id<T>,
+                                             name: &str, value: u64) -> io::Result<()>
+    {
+        let prop = find_property(dev, obj, name)?;
+        self.add_property(obj, prop, value);
+        Ok(())
+    }
+
+    /// Don't scan out a plane until a fence has signaled, by setting
+    /// its `IN_FENCE_FD` property.
+    ///
+    /// The fd is only read by the commit, so `fence` must stay open
+    /// until then.
+    pub fn in_fence<F: AsRawFd>(&mut self, dev: &Device, plane: Id<Plane>, fence: &F)
+                                -> io::Result<()>
+    {
+        self.add_property_by_name(dev, plane, "IN_FENCE_FD", fence.as_raw_fd() as u64)
+    }
+
+    /// Ask for a fence that signals when the commit takes effect on a
+    /// CRTC, by setting its `OUT_FENCE_PTR` property. It's returned by
+    /// `Master::atomic_commit`.
+    pub fn out_fence(&mut self, dev: &Device, crtc: Id<Crtc>) -> io::Result<()> {
+        let prop = find_property(dev, crtc, "OUT_FENCE_PTR")?;
+        self.add_out_fence(FenceFor::Crtc(crtc), crtc, prop);
+        Ok(())
+    }
+
+    /// Ask for a fence that signals when a writeback connector has
+    /// finished writing, by setting its `WRITEBACK_OUT_FENCE_PTR`
+    /// property. See `Writeback`.
+    pub fn writeback_out_fence(&mut self, dev: &Device, connector: Id<Connector>)
+                               -> io::Result<()>
+    {
+        let prop = find_property(dev, connector, "WRITEBACK_OUT_FENCE_PTR")?;
+        self.add_out_fence(FenceFor::Writeback(connector), connector, prop);
+        Ok(())
+    }
+
+    fn add_out_fence<T: Resource>(&mut self, target: FenceFor, obj: Id<T>, prop: Id<Property>) {
+        let mut fence = Box::new(-1);
+        let ptr = &mut *fence as *mut i32 as u64;
+        self.out_fences.retain(|&(t, _)| t != target);
+        self.out_fences.push((target, fence));
+        self.add_property(obj, prop, ptr);
+    }
+
+    /// Is anything being changed?
+    pub fn is_empty(&self) -> bool {
+        self.props.is_empty()
+    }
+}
+
+impl fmt::Debug for AtomicRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AtomicRequest")
+            .field("props", &self.props)
+            .field("out_fences", &self.out_fences.iter().map(|&(c, _)| c).collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+fn find_property<T: Resource>(dev: &Device, obj: Id<T>, name: &str) -> io::Result<Id<Property>> {
+    match dev.find_property(obj, name)? {
+        Some((prop, _)) => Ok(prop),
+        None => Err(io::Error::new(io::ErrorKind::NotFound,
+                                   format!("Object has no {} property", name))),
+    }
+}
+
+impl<'a> Master<'a> {
+    /// Commit an atomic request without waiting for vblank
+    /// (`ATOMIC_PAGE_FLIP_ASYNC`) if the kernel allows it, or else as
+    /// `atomic_commit`. Returns which it was.
+    ///
+    /// Async commits need `Capability::AtomicAsyncPageFlip` and
+    /// `ClientCapability::Atomic` set on this device, can't be
+    /// modesets, and can only change planes' `FB_ID`s. Anything else
+    /// (out-fences included) makes a vsynced commit.
+    pub fn atomic_commit_async(&self, req: &mut AtomicRequest, flags: AtomicFlags,
+                               user_data: u64) -> io::Result<(OutFences, FlipMode)>
+    {
+        let flags = flags - ATOMIC_PAGE_FLIP_ASYNC;
+        if self.can_commit_async(req, flags)? {
+            match self.atomic_commit(req, flags | ATOMIC_PAGE_FLIP_ASYNC, user_data) {
+                Ok(fences) => return Ok((fences, FlipMode::Async)),
+                Err(ref err) if err.kind() == io::ErrorKind::InvalidInput => (),
+                Err(err) => return Err(err),
+            }
+        }
+        let fences = self.atomic_commit(req, flags, user_data)?;
+        Ok((fences, FlipMode::Vsync))
+    }
+
+    fn can_commit_async(&self, req: &AtomicRequest, flags: AtomicFlags) -> io::Result<bool> {
+        if flags.contains(ATOMIC_ALLOW_MODESET) || req.is_empty()
+            || !self.has_client_capability(ClientCapability::Atomic)
+            || self.cached_capability(Capability::AtomicAsyncPageFlip) == 0 {
+            return Ok(false);
+        }
+        // Property ids don't change, so each is only looked up once.
+        let mut fb_id_props = self.shared.fb_id_props.lock().unwrap();
+        for &(_, prop, _) in &req.props {
+            let is_fb_id = match fb_id_props.get(&prop) {
+                Some(&is_fb_id) => is_fb_id,
+                None => {
+                    let id = unsafe { Id::<Property>::from_u32(prop) }.expect("property ids aren't 0");
+                    let is_fb_id = self.get(id)?.name() == "FB_ID";
+                    fb_id_props.insert(prop, is_fb_id);
+                    is_fb_id
+                }
+            };
+            if !is_fb_id {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Commit an atomic request.
+    ///
+    /// Returns the out-fences asked for with `AtomicRequest::out_fence`
+    /// and `writeback_out_fence`, for the objects that got one. (None
+    /// are made with `ATOMIC_TEST_ONLY`.)
+    pub fn atomic_commit(&self, req: &mut AtomicRequest, flags: AtomicFlags, user_data: u64)
+                         -> io::Result<OutFences>
+    {
+        // The kernel wants the properties grouped by object.
+        let mut objs: Vec<u32> = Vec::new();
+        for &(obj, _, _) in &req.props {
+            if !objs.contains(&obj) {
+                objs.push(obj);
+            }
+        }
+        let mut count_props = Vec::with_capacity(objs.len());
+        let mut props = Vec::with_capacity(req.props.len());
+        let mut values = Vec::with_capacity(req.props.len());
+        for &obj in &objs {
+            let mut count = 0u32;
+            for &(o, prop, value) in &req.props {
+                if o == obj {
+                    props.push(prop);
+                    values.push(value);
+                    count += 1;
+                }
+            }
+            count_props.push(count);
+        }
+        for (_, fence) in req.out_fences.iter_mut() {
+            **fence = -1;
+        }
+
+        let mut atomic = ffi::atomic {
+            flags: flags.bits(),
+            count_objs: objs.len() as u32,
+            objs_ptr: objs.as_ptr() as u64,
+            count_props_ptr: count_props.as_ptr() as u64,
+            props_ptr: props.as_ptr() as u64,
+            prop_values_ptr: values.as_ptr() as u64,
+            user_data,
+            ..Default::default()
+        };
+        self.ioctl(&mut atomic)?;
+
+        let mut fences = OutFences::default();
+        for &(target, ref fence) in &req.out_fences {
+            if **fence < 0 {
+                continue;
+            }
+            let fence = SyncFile::new(unsafe { OwnedFd::from_raw_fd(**fence) });
+            match target {
+                FenceFor::Crtc(crtc) => fences.crtcs.push((crtc, fence)),
+                FenceFor::Writeback(connector) => fences.writeback.push((connector, fence)),
+            }
+        }
+        Ok(fences)
+    }
+}
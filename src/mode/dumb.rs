@@ -0,0 +1,494 @@
+//! Dumb buffers: simple CPU accessible buffers that every driver with
+//! modesetting supports.
+//!
+//! The pieces can be used separately:
+//!
+//! * `DumbBuffer` is the buffer itself (a GEM object). It's destroyed
+//!   when dropped.
+//! * `DumbMap` maps it into memory.
+//! * `OwnedFb` is a frame buffer for scanning it out.
+//!
+//! A cursor or a buffer to export with PRIME doesn't need a mapping,
+//! and a buffer that only gets drawn to doesn't need a frame buffer.
+//! `DumbBuf` bundles all three for the common case.
+
+use std::{io, fmt, slice};
+use std::os::raw::c_ulong;
+use std::mem::size_of;
+use memmap::{Mmap, Protection};
+use super::ffi;
+use super::{Fb, OwnedFb, Resource};
+use ioctl_vals::*;
+use convert::{Image, ImageMut};
+use fourcc::FourCC;
+use gem::{self, FlinkName, GemObject};
+use pixels::{PixelFormat, Pixels};
+use {Device, DrmIoctl, GemHandle};
+
+impl DrmIoctl for ffi::create_dumb {
+    fn request() -> c_ulong { DRM_IOCTL_MODE_CREATE_DUMB }
+}
+
+impl DrmIoctl for ffi::map_dumb {
+    fn request() -> c_ulong { DRM_IOCTL_MODE_MAP_DUMB }
+}
+
+impl DrmIoctl for ffi::destroy_dumb {
+    fn request() -> c_ulong { DRM_IOCTL_MODE_DESTROY_DUMB }
+}
+
+/// A dumb buffer. Destroyed when dropped.
+///
+/// Multi-planar formats (eg. NV12) are put in a single buffer, one
+/// plane after the other.
+pub struct DumbBuffer {
+    handle: u32,
+    width: u32,
+    height: u32,
+    format: FourCC,
+    bpp: u32,
+    size: u64,
+    // (offset, pitch) of each plane.
+    planes: Vec<(u32, u32)>,
+    dev: Device,
+}
+
+impl DumbBuffer {
+    /// Create a buffer for `width` x `height` pixels of `format`.
+    ///
+    /// # Errors
+    ///
+    /// `ErrorKind::InvalidInput` if the layout of `format` isn't known.
+    /// Otherwise any error from the driver.
+    pub fn create(dev: &Device, width: u32, height: u32, format: FourCC)
+                  -> io::Result<DumbBuffer> {
+        let info = format.info()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput,
+                                          "Unknown layout for pixel format"))?;
+        let dev = dev.try_clone()?;
+        let (bw, bh) = info.block_size();
+
+        // Extra planes go below the first, so they need to be given as
+        // extra rows of the first plane. Each plane's pitch is a fixed
+        // ratio of the first's.
+        let first = info.bytes_per_block(0);
+        let ratio = |plane: usize| {
+            let hsub = if plane == 0 { 1 } else { info.hsub() };
+            (info.bytes_per_block(plane), first * hsub)
+        };
+        let rows = |plane: usize| info.plane_height(plane, height).div_ceil(bh);
+        let virtual_height = (0..info.num_planes())
+            .map(|plane| {
+                let (num, den) = ratio(plane);
+                (rows(plane) * num).div_ceil(den)
+            })
+            .sum();
+
+        let mut create = ffi::create_dumb {
+            width: width.div_ceil(bw),
+            height: virtual_height,
+            bpp: first * 8,
+            ..Default::default()
+        };
+        dev.ioctl(&mut create)?;
+        dev.gem_ref(create.handle);
+
+        let mut planes = Vec::with_capacity(info.num_planes());
+        let mut offset = 0;
+        for plane in 0..info.num_planes() {
+            let (num, den) = ratio(plane);
+            let pitch = create.pitch * num / den;
+            planes.push((offset, pitch));
+            offset += rows(plane) * pitch;
+        }
+
+        Ok(DumbBuffer {
+            handle: create.handle,
+            width, height, format,
+            bpp: info.bits_per_pixel(0),
+            size: create.size,
+            planes,
+            dev,
+        })
+    }
+
+    /// (width, height) in pixels
+    pub fn size(&self) -> (u32, u32) { (self.width, self.height) }
+
+    /// Pixel format the buffer was made for.
+    pub fn format(&self) -> FourCC { self.format }
+
+    /// Bits per pixel of the first plane.
+    pub fn bpp(&self) -> u32 { self.bpp }
+
+    /// Size of buffer in bytes
+    pub fn bytes(&self) -> usize { self.size as usize }
+
+    /// Distance between rows of the first plane in bytes
+    pub fn pitch(&self) -> usize { self.planes[0].1 as usize }
+
+    /// Distance between rows of each plane in bytes.
+    pub fn pitches(&self) -> Vec<u32> {
+        self.planes.iter().map(|&(_, pitch)| pitch).collect()
+    }
+
+    /// Offset in bytes of the start of each plane.
+    pub fn offsets(&self) -> Vec<u32> {
+        self.planes.iter().map(|&(offset, _)| offset).collect()
+    }
+
+    /// (offset, pitch) of each plane.
+    pub(crate) fn layout(&self) -> &[(u32, u32)] { &self.planes }
+
+    pub(crate) fn device(&self) -> &Device { &self.dev }
+
+    /// Map the buffer into memory.
+    ///
+    /// The mapping stays valid after the buffer is dropped.
+    pub fn map(&self, protection: MapProtection) -> io::Result<DumbMap> {
+        let mut map_dumb = ffi::map_dumb {
+            handle: self.handle,
+            ..Default::default()
+        };
+        self.dev.ioctl(&mut map_dumb)?;
+
+        let (prot, writable) = match protection {
+            MapProtection::Read => (Protection::Read, false),
+            MapProtection::ReadWrite => (Protection::ReadWrite, true),
+        };
+        // Possible issue: if the size > usize::MAX then this could cause issues.
+        // If you need buffers that large you're going to have many issues anyway.
+        // (Eg, it won't map into your address space anyway.)
+        let map = Mmap::open_with_offset(self.dev.fd.get_ref(), prot,
+                                         map_dumb.offset as usize, self.size as usize)?;
+        Ok(DumbMap {
+            map, writable,
+            width: self.width, height: self.height,
+            format: self.format,
+            planes: self.planes.clone(),
+        })
+    }
+
+    /// Give the buffer a global name, so other processes can open it.
+    /// See `GemObject::flink`.
+    pub fn flink(&self) -> io::Result<FlinkName> {
+        gem::flink(&self.dev, self.handle, Some(self.size))
+    }
+
+    /// Another owner of the buffer's handle. The buffer is destroyed
+    /// once it and every `GemObject` for it are dropped.
+    pub fn gem_object(&self) -> io::Result<GemObject> {
+        unsafe { GemObject::from_handle(&self.dev, self.handle) }
+    }
+
+    /// Add a frame buffer for this buffer, so it can be shown.
+    pub fn add_fb(&self) -> io::Result<OwnedFb> {
+        let mut handles = [0; 4];
+        let mut pitches = [0; 4];
+        let mut offsets = [0; 4];
+        for (plane, &(offset, pitch)) in self.planes.iter().enumerate() {
+            handles[plane] = self.handle;
+            pitches[plane] = pitch;
+            offsets[plane] = offset;
+        }
+        let id = Fb::add_fb2(&self.dev, self.width, self.height, self.format,
+                             handles, pitches, offsets, None, 0)?;
+        // Don't leak the frame buffer if we fail to get it.
+        let fb = Fb::get(&self.dev, id).inspect_err(|_| {
+            Fb::rm(&self.dev, id).ok();
+        })?;
+        OwnedFb::new(&self.dev, fb)
+    }
+}
+
+impl fmt::Debug for DumbBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DumbBuffer")
+            .field("handle", &self.handle)
+            .field("size", &self.size())
+            .field("format", &self.format)
+            .field("planes", &self.planes)
+            .finish()
+    }
+}
+
+impl Drop for DumbBuffer {
+    fn drop(&mut self) {
+        // The handle may have been shared with a `GemObject`.
+        if self.dev.gem_unref(self.handle) {
+            // We need to continue if this fails.
+            let mut destroy = ffi::destroy_dumb { handle: self.handle };
+            self.dev.ioctl(&mut destroy).ok();
+        }
+    }
+}
+
+impl GemHandle for DumbBuffer {
+    fn bo_handle(&self) -> u32 {
+        self.handle
+    }
+    fn width(&self) -> u32 {
+        self.width
+    }
+    fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+/// What a mapping may be used for.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum MapProtection {
+    Read,
+    ReadWrite,
+}
+
+/// A dumb buffer mapped into memory.
+pub struct DumbMap {
+    map: Mmap,
+    writable: bool,
+    width: u32,
+    height: u32,
+    format: FourCC,
+    planes: Vec<(u32, u32)>,
+}
+
+impl DumbMap {
+    /// The whole mapping.
+    ///
+    /// Rows may be padded: row `y` starts at `y * pitch`, not at
+    /// `y * width`.
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.map.ptr(), self.map.len()) }
+    }
+
+    /// The whole mapping, or `None` if it was mapped read only.
+    pub fn as_mut_slice(&mut self) -> Option<&mut [u8]> {
+        if self.writable {
+            Some(unsafe { slice::from_raw_parts_mut(self.map.mut_ptr(), self.map.len()) })
+        } else {
+            None
+        }
+    }
+
+    /// Each plane's data and pitch, for filling in multi-planar formats
+    /// by hand. Each slice runs to the start of the next plane. `None`
+    /// if it was mapped read only.
+    pub fn planes_mut(&mut self) -> Option<Vec<(&mut [u8], usize)>> {
+        let layout = self.planes.clone();
+        self.as_mut_slice().map(|data| split_planes_mut(data, &layout))
+    }
+
+    fn planes<'a>(&self, data: &'a [u8]) -> Vec<(&'a [u8], usize)> {
+        self.planes.iter()
+            .map(|&(offset, pitch)| (&data[offset as usize..], pitch as usize))
+            .collect()
+    }
+
+    /// The buffer as an image, for `convert` and `draw`.
+    ///
+    /// # Errors
+    ///
+    /// `ErrorKind::InvalidInput` if `convert` doesn't support the format.
+    pub fn image(&self) -> io::Result<Image<'_>> {
+        let planes = self.planes(self.as_slice());
+        Image::new(self.format, self.width, self.height, planes)
+    }
+
+    /// The buffer as a writable image, for `convert` and `draw`.
+    ///
+    /// # Errors
+    ///
+    /// As for `image`, and `ErrorKind::PermissionDenied` if the mapping
+    /// is read only.
+    pub fn image_mut(&mut self) -> io::Result<ImageMut<'_>> {
+        let (format, width, height) = (self.format, self.width, self.height);
+        let planes = self.planes_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::PermissionDenied,
+                                          "Buffer is mapped read only"))?;
+        ImageMut::new(format, width, height, planes)
+    }
+
+    /// A pitch aware view of the pixels.
+    ///
+    /// Returns `None` if `F` isn't the format of the buffer, or it's
+    /// mapped read only.
+    pub fn pixels<F: PixelFormat>(&mut self) -> Option<Pixels<'_, F>> {
+        if F::FOURCC != self.format {
+            return None;
+        }
+        let (width, height, pitch) = (self.width, self.height, self.planes[0].1 as usize);
+        Pixels::new(self.as_mut_slice()?, width, height, pitch).ok()
+    }
+}
+
+/// Split a buffer into (data, pitch) for each plane, given the
+/// (offset, pitch) of each. Planes must be in order.
+pub(crate) fn split_planes_mut<'a>(mut data: &'a mut [u8], layout: &[(u32, u32)])
+                                   -> Vec<(&'a mut [u8], usize)> {
+    let mut planes = Vec::with_capacity(layout.len());
+    let mut start = 0;
+    for (i, &(offset, pitch)) in layout.iter().enumerate() {
+        let end = layout.get(i + 1).map_or(start + data.len(), |&(next, _)| next as usize);
+        let (plane, tail) = data.split_at_mut(end - start);
+        planes.push((&mut plane[offset as usize - start..], pitch as usize));
+        data = tail;
+        start = end;
+    }
+    planes
+}
+
+impl fmt::Debug for DumbMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DumbMap")
+            .field("len", &self.map.len())
+            .field("writable", &self.writable)
+            .finish()
+    }
+}
+
+/// Options for creating a `DumbBuf`.
+pub struct DumbBufOptions<'a> {
+    device: &'a Device,
+    width: u32, height: u32,
+    format: FourCC,
+}
+
+impl<'a> DumbBufOptions<'a> {
+    pub fn new(device: &'a Device) -> DumbBufOptions<'a> {
+        DumbBufOptions {
+            device,
+            width: 0, height: 0,
+            format: FourCC::XRGB8888,
+        }
+    }
+    pub fn width(&mut self, width: u32) -> &mut DumbBufOptions<'a> {
+        self.width = width; self
+    }
+    pub fn height(&mut self, height: u32) -> &mut DumbBufOptions<'a> {
+        self.height = height; self
+    }
+    pub fn format(&mut self, format: FourCC) -> &mut DumbBufOptions<'a> {
+        self.format = format; self
+    }
+
+    /// Create the buffer, a frame buffer for it, and map it.
+    pub fn create(&mut self) -> io::Result<DumbBuf> {
+        let buffer = DumbBuffer::create(self.device, self.width, self.height, self.format)?;
+        let fb = buffer.add_fb()?;
+        let map = buffer.map(MapProtection::ReadWrite)?;
+        Ok(DumbBuf { fb, map, buffer })
+    }
+}
+
+/// A dumb buffer with a frame buffer, mapped into memory.
+pub struct DumbBuf
+{
+    // Fields are dropped in order: remove the frame buffer, unmap,
+    // then destroy the buffer.
+    fb: OwnedFb,
+    map: DumbMap,
+    buffer: DumbBuffer,
+}
+
+impl DumbBuf {
+    /// Not sure what this used for other than creating/mapping the
+    /// frame buffer.
+    ///
+    /// # Safety
+    ///
+    /// The handle is owned by this buffer, don't close it.
+    pub unsafe fn handle(&self) -> u32 { self.buffer.handle }
+
+    /// (width, height) in pixels
+    pub fn size(&self) -> (u32, u32) { self.buffer.size() }
+
+    /// Size of buffer in bytes
+    pub fn bytes(&self) -> usize { self.buffer.bytes() }
+
+    /// Distance between rows in bytes
+    pub fn pitch(&self) -> usize { self.buffer.pitch() }
+
+    /// Bits per pixel
+    pub fn bpp(&self) -> u32 { self.buffer.bpp() }
+
+    /// Pixel format of the framebuffer.
+    pub fn format(&self) -> FourCC { self.buffer.format() }
+
+    /// Access the Fb object associated with this.
+    pub fn fb(&self) -> &Fb {
+        self.fb.fb()
+    }
+
+    /// The dumb buffer.
+    pub fn buffer(&self) -> &DumbBuffer { &self.buffer }
+
+    /// The mapping.
+    pub fn map(&mut self) -> &mut DumbMap { &mut self.map }
+
+    /// A pitch aware view of the pixels.
+    ///
+    /// Returns `None` if `F` isn't the format of the buffer.
+    pub fn pixels<F: PixelFormat>(&mut self) -> Option<Pixels<'_, F>> {
+        self.map.pixels()
+    }
+
+    /// The buffer as an image, for `convert` and `draw`.
+    ///
+    /// # Errors
+    ///
+    /// `ErrorKind::InvalidInput` if `convert` doesn't support the format.
+    pub fn image(&self) -> io::Result<Image<'_>> {
+        self.map.image()
+    }
+
+    /// The buffer as a writable image, for `convert` and `draw`.
+    pub fn image_mut(&mut self) -> io::Result<ImageMut<'_>> {
+        self.map.image_mut()
+    }
+}
+
+/// The raw mapping.
+///
+/// This is the whole buffer, including any padding the driver adds to
+/// the end of each row: row `y` starts at `y * pitch()`, not at
+/// `y * width`. Prefer `DumbBuf::pixels` unless you handle the pitch
+/// yourself.
+impl AsMut<[u8]> for DumbBuf {
+    fn as_mut(&mut self) -> &mut [u8] {
+        self.map.as_mut_slice().expect("DumbBuf is mapped read/write")
+    }
+}
+/// The raw mapping as 16 bit units. Rows are padded, see `AsMut<[u8]>`.
+impl AsMut<[u16]> for DumbBuf {
+    fn as_mut(&mut self) -> &mut [u16] {
+        let bytes: &mut [u8] = self.as_mut();
+        unsafe {
+            let ptr = bytes.as_mut_ptr() as *mut _;
+            let size = bytes.len() / size_of::<u16>();
+            slice::from_raw_parts_mut(ptr, size)
+        }
+    }
+}
+/// The raw mapping as 32 bit units. Rows are padded, see `AsMut<[u8]>`.
+impl AsMut<[u32]> for DumbBuf {
+    fn as_mut(&mut self) -> &mut [u32] {
+        let bytes: &mut [u8] = self.as_mut();
+        unsafe {
+            let ptr = bytes.as_mut_ptr() as *mut _;
+            let size = bytes.len() / size_of::<u32>();
+            slice::from_raw_parts_mut(ptr, size)
+        }
+    }
+}
+
+impl GemHandle for DumbBuf {
+    fn bo_handle(&self) -> u32 {
+        self.buffer.handle
+    }
+    fn width(&self) -> u32 {
+        self.buffer.width
+    }
+    fn height(&self) -> u32 {
+        self.buffer.height
+    }
+}
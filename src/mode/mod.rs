@@ -1,19 +1,26 @@
 
 mod ffi;
+mod dumb;
+mod atomic;
+mod writeback;
+
+pub use self::dumb::*;
+pub use self::atomic::*;
+pub use self::writeback::*;
 
-use std::slice;
 use std::{io, fmt, str};
-use std::mem::{transmute, zeroed, size_of};
+use std::mem::{transmute, zeroed};
 use std::os::raw::c_ulong;
 use std::cmp::Ordering;
 use super::ioctl_vals::*;
-use super::Device;
+use super::{Capability, Device, Master};
 use super::fourcc::FourCC;
+use super::modifier::Modifier;
 use super::DrmIoctl;
-use GemHandle;
+use super::event::{Completion, EventContext};
 use std::marker::PhantomData;
-use memmap::{Mmap, Protection};
 use std::hash::{Hash, Hasher};
+use std::time::Duration;
 
 
 const DRM_MODE_OBJECT_CRTC: u32 = 0xcccccccc;
@@ -26,6 +33,9 @@ const DRM_MODE_OBJECT_BLOB: u32 = 0xbbbbbbbb;
 const DRM_MODE_OBJECT_PLANE: u32 = 0xeeeeeeee;
 // const DRM_MODE_OBJECT_ANY: u32 = 0;
 
+// const DRM_MODE_FB_INTERLACED: u32 = 1 << 0;
+const DRM_MODE_FB_MODIFIERS: u32 = 1 << 1;
+
 /// Id is a 32-bit integer that represents an object in the driver.
 ///
 /// This information can change at any time, due to physical hardware
@@ -369,6 +379,9 @@ pub enum ConnectorType
     EDP = 14,
     VIRTUAL = 15,
     DSI = 16,
+    DPI = 17,
+    /// Writes the output of a CRTC to memory. See `Writeback`.
+    WRITEBACK = 18,
 }
 impl ConnectorType
 {
@@ -391,6 +404,8 @@ impl ConnectorType
             14 => ConnectorType::EDP,
             15 => ConnectorType::VIRTUAL,
             16 => ConnectorType::DSI,
+            17 => ConnectorType::DPI,
+            18 => ConnectorType::WRITEBACK,
             _ => ConnectorType::Unknown,
         }            
     }
@@ -507,6 +522,33 @@ impl ModeInfo
         str::from_utf8(&self.name[..len])
             .expect("mode name not valid utf8")
     }
+
+    /// Time between vblanks, worked out from the pixel clock and
+    /// totals. More precise than `vrefresh`, which is rounded. `None`
+    /// if the mode has no timings (or they make no sense).
+    pub fn frame_period(&self) -> Option<Duration>
+    {
+        let pixels = self.htotal as u64 * self.vtotal as u64;
+        if pixels == 0 || self.clock == 0 {
+            return None;
+        }
+        // clock is in kHz.
+        let mut ns = pixels * 1_000_000 / self.clock as u64;
+        if self.flags.contains(FLAG_INTERLACE) {
+            // A vblank per field.
+            ns /= 2;
+        }
+        if self.flags.contains(FLAG_DBLSCAN) {
+            ns *= 2;
+        }
+        if self.vscan > 1 {
+            ns *= self.vscan as u64;
+        }
+        if ns == 0 {
+            return None;
+        }
+        Some(Duration::from_nanos(ns))
+    }
 }
 
 impl From<ffi::modeinfo> for ModeInfo
@@ -868,7 +910,7 @@ pub struct Plane
     fb_id: u32,
     possible_crtcs: u32,
     gamma_size: u32,
-    formats: Vec<FourCC>
+    formats: Vec<FourCC>,
 }
 
 impl DrmIoctl for ffi::get_plane_res
@@ -891,10 +933,10 @@ impl Resource for Plane
             try!(dev.ioctl(&mut plane));
             let counts = plane;
 
-            let mut formats: Vec<FourCC> = Vec::new();
+            let mut formats: Vec<u32> = Vec::new();
             if plane.count_format_types > 0 {
-                formats.resize(plane.count_format_types as usize, FourCC::Unknown);
-                plane.format_type_ptr = from_ptr(formats.as_mut_ptr() as *mut u32);
+                formats.resize(plane.count_format_types as usize, 0);
+                plane.format_type_ptr = from_ptr(formats.as_mut_ptr());
                 try!(dev.ioctl(&mut plane));
                 if counts.count_format_types < plane.count_format_types {
                     continue;
@@ -906,7 +948,7 @@ impl Resource for Plane
                 fb_id: plane.fb_id,
                 possible_crtcs: plane.possible_crtcs,
                 gamma_size: plane.gamma_size,
-                formats: formats
+                formats: formats.into_iter().map(FourCC::from).collect(),
             });
         }
     }
@@ -927,6 +969,181 @@ impl Plane {
         try!(dev.ioctl(&mut plane_res));
         Ok(ids)
     }
+
+    pub fn id(&self) -> Id<Plane> { self.plane_id }
+
+    /// The CRTC this plane is currently attached to.
+    pub fn crtc_id(&self) -> Option<Id<Crtc>> {
+        unsafe { Id::from_u32(self.crtc_id) }
+    }
+
+    /// The frame buffer currently shown on this plane.
+    pub fn fb_id(&self) -> Option<Id<Fb>> {
+        unsafe { Id::from_u32(self.fb_id) }
+    }
+
+    /// Bitmask of the CRTC's this plane can be used with. Bit `n` is
+    /// the CRTC at index `n` of `Resources::crtcs`.
+    pub fn possible_crtcs(&self) -> u32 { self.possible_crtcs }
+
+    /// Number of entries in the plane's gamma table.
+    pub fn gamma_size(&self) -> u32 { self.gamma_size }
+
+    /// Pixel formats this plane can scan out.
+    pub fn formats(&self) -> &[FourCC] { self.formats.as_ref() }
+
+    /// Which modifiers are supported for each format. Fetched from the
+    /// device each time. See `FormatModifierSet::get`.
+    pub fn format_modifiers(&self, dev: &Device) -> io::Result<Option<FormatModifierSet>> {
+        FormatModifierSet::get(dev, self.plane_id)
+    }
+
+    /// Can this plane scan out a buffer with the given format and modifier?
+    ///
+    /// Without "IN_FORMATS" the plane is assumed to support every format
+    /// in `formats` with a `LINEAR` modifier only. `INVALID` (no
+    /// modifier, the driver picks the layout) only needs the format.
+    pub fn supports(&self, dev: &Device, format: FourCC, modifier: Modifier)
+                    -> io::Result<bool>
+    {
+        if modifier == Modifier::INVALID {
+            return Ok(self.formats.contains(&format));
+        }
+        Ok(match self.format_modifiers(dev)? {
+            Some(set) => set.supports(format, modifier),
+            None => modifier == Modifier::LINEAR && self.formats.contains(&format),
+        })
+    }
+}
+
+/// Formats and modifiers a plane can scan out.
+///
+/// Parsed from the `drm_format_modifier_blob` found in a plane's
+/// "IN_FORMATS" property.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatModifierSet {
+    formats: Vec<u32>,
+    // Each modifier and the indices into formats it can be used with.
+    modifiers: Vec<(Modifier, Vec<usize>)>,
+}
+
+impl FormatModifierSet {
+    /// Fetch the set for a plane. Returns `None` if the plane does not
+    /// have an "IN_FORMATS" property, or the driver doesn't report
+    /// `Capability::Addfb2Modifiers`.
+    pub fn get(dev: &Device, plane: Id<Plane>) -> io::Result<Option<FormatModifierSet>> {
+        if dev.capability(Capability::Addfb2Modifiers).unwrap_or(0) == 0 {
+            return Ok(None);
+        }
+        let blob_id = match dev.find_property(plane, "IN_FORMATS")? {
+            Some((_, value)) => value as u32,
+            None => return Ok(None),
+        };
+        let blob_id: Id<PropertyBlob> = match unsafe { Id::from_u32(blob_id) } {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+        let blob = dev.get(blob_id)?;
+        FormatModifierSet::parse(blob.data()).map(Some)
+    }
+
+    /// Parse the contents of an "IN_FORMATS" blob.
+    ///
+    /// ```c
+    /// struct drm_format_modifier_blob {
+    ///     __u32 version;
+    ///     __u32 flags;
+    ///     __u32 count_formats;
+    ///     __u32 formats_offset;
+    ///     __u32 count_modifiers;
+    ///     __u32 modifiers_offset;
+    /// };
+    /// struct drm_format_modifier {
+    ///     __u64 formats;
+    ///     __u32 offset;
+    ///     __u32 pad;
+    ///     __u64 modifier;
+    /// };
+    /// ```
+    pub fn parse(data: &[u8]) -> io::Result<FormatModifierSet> {
+        fn invalid(msg: &str) -> io::Error {
+            io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+        }
+        fn read_u32(data: &[u8], at: usize) -> io::Result<u32> {
+            at.checked_add(4).and_then(|end| data.get(at..end))
+                .map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+                .ok_or_else(|| invalid("IN_FORMATS blob truncated"))
+        }
+        fn read_u64(data: &[u8], at: usize) -> io::Result<u64> {
+            let lo = read_u32(data, at)? as u64;
+            // Can't overflow: the low half was in bounds.
+            let hi = read_u32(data, at + 4)? as u64;
+            Ok(if cfg!(target_endian = "little") { lo | (hi << 32) } else { (lo << 32) | hi })
+        }
+        // Where the `i`th entry of `size` bytes from `base` starts.
+        fn entry(base: usize, i: usize, size: usize) -> io::Result<usize> {
+            i.checked_mul(size).and_then(|off| base.checked_add(off))
+                .ok_or_else(|| invalid("IN_FORMATS blob truncated"))
+        }
+        const MODIFIER_SIZE: usize = 24;
+
+        let version = read_u32(data, 0)?;
+        if version != 1 {
+            return Err(invalid("Unknown IN_FORMATS blob version"));
+        }
+        let count_formats = read_u32(data, 8)? as usize;
+        let formats_offset = read_u32(data, 12)? as usize;
+        let count_modifiers = read_u32(data, 16)? as usize;
+        let modifiers_offset = read_u32(data, 20)? as usize;
+
+        let mut formats = Vec::with_capacity(count_formats.min(data.len() / 4));
+        for i in 0..count_formats {
+            formats.push(read_u32(data, entry(formats_offset, i, 4)?)?);
+        }
+
+        let mut modifiers = Vec::with_capacity(count_modifiers.min(data.len() / MODIFIER_SIZE));
+        for i in 0..count_modifiers {
+            let at = entry(modifiers_offset, i, MODIFIER_SIZE)?;
+            let mask = read_u64(data, at)?;
+            let offset = read_u32(data, at + 8)? as usize;
+            let modifier = Modifier(read_u64(data, at + 16)?);
+            let indices = (0..64)
+                .filter(|bit| mask & (1 << bit) != 0)
+                .filter_map(|bit| offset.checked_add(bit))
+                .filter(|&index| index < formats.len())
+                .collect();
+            modifiers.push((modifier, indices));
+        }
+
+        Ok(FormatModifierSet { formats, modifiers })
+    }
+
+    /// Every format in the set.
+    pub fn formats(&self) -> Vec<FourCC> {
+        self.formats.iter().map(|&f| FourCC::from(f)).collect()
+    }
+
+    /// Every modifier in the set.
+    pub fn modifiers(&self) -> Vec<Modifier> {
+        self.modifiers.iter().map(|&(m, _)| m).collect()
+    }
+
+    /// The modifiers that can be used with a format.
+    pub fn modifiers_for(&self, format: FourCC) -> Vec<Modifier> {
+        let code = u32::from(format);
+        self.modifiers.iter()
+            .filter(|(_, indices)| indices.iter().any(|&i| self.formats[i] == code))
+            .map(|&(m, _)| m)
+            .collect()
+    }
+
+    /// Can `format` be used with `modifier`?
+    pub fn supports(&self, format: FourCC, modifier: Modifier) -> bool {
+        let code = u32::from(format);
+        self.modifiers.iter()
+            .filter(|&&(m, _)| m == modifier)
+            .any(|(_, indices)| indices.iter().any(|&i| self.formats[i] == code))
+    }
 }
 
 /// Blob's are used to get EDID information out of a Property.
@@ -962,6 +1179,13 @@ impl Resource for PropertyBlob
     }
 }
 
+impl PropertyBlob
+{
+    pub fn id(&self) -> Id<PropertyBlob> { self.id }
+    /// The raw contents of the blob.
+    pub fn data(&self) -> &[u8] { self.data.as_ref() }
+}
+
 /// Fb is the DRM representation of frame buffer.
 ///
 /// Either crated by a DRM api, or imported from:
@@ -1055,19 +1279,32 @@ impl Fb
         })
     }
 
-    fn add_fb2(device: &Device,
-               width: u32, height: u32,
-               pixel_format: FourCC,
-               bo_handles: [u32; 4], pitches: [u32; 4],
-               offsets: [u32; 4], flags: u32) -> io::Result<Id<Fb>>
+    /// Add a frame buffer with ADDFB2. With a modifier, it's used for
+    /// every plane that has a handle.
+    pub(crate) fn add_fb2(device: &Device,
+                          width: u32, height: u32,
+                          pixel_format: FourCC,
+                          bo_handles: [u32; 4], pitches: [u32; 4],
+                          offsets: [u32; 4], modifier: Option<Modifier>,
+                          mut flags: u32) -> io::Result<Id<Fb>>
     {
+        let mut modifiers = [0; 4];
+        if let Some(modifier) = modifier {
+            flags |= DRM_MODE_FB_MODIFIERS;
+            for (m, &handle) in modifiers.iter_mut().zip(bo_handles.iter()) {
+                if handle != 0 {
+                    *m = modifier.0;
+                }
+            }
+        }
         let mut req = ffi::fb_cmd2 {
             width: width, height: height,
-            pixel_format: pixel_format as u32,
+            pixel_format: pixel_format.into(),
             flags: flags,
             handles: bo_handles,
             pitches: pitches,
             offsets: offsets,
+            modifier: modifiers,
             ..Default::default()
         };
 
@@ -1079,9 +1316,8 @@ impl Fb
     ///
     /// This will not happen automatically as the `Fb` object is not a
     /// real representation of the Frame buffer, but a struct
-    /// containing metadata about it.
-    ///
-    /// TODO: It might be a good idea to create a real RAII type for Fb.
+    /// containing metadata about it. Use `OwnedFb` for one that is
+    /// removed on drop.
     pub fn rm(dev: &Device, id: Id<Fb>) -> io::Result<()> {
         #[derive(Debug)]
         struct RmFb(Id<Fb>);
@@ -1096,240 +1332,169 @@ impl DrmIoctl for ffi::fb_cmd2 {
     fn request() -> c_ulong { DRM_IOCTL_MODE_ADDFB2 }
 }
 
-// /// Units that a dumb buffer is made of.
-// ///
-// /// (Not nessasarily a pixel. For example in Multiplane YUV, each
-// /// pixel is made up of 1 bytes in the Y plane and 1/{1,2,4} of a byte
-// /// in the U and V planes.
-// ///
-// /// # Warning #
-// ///
-// /// This value will be initialized automatically (as zero pages).
-// /// Drop is also not called when the DumbBuf is dropped.
-// unsafe trait BufferData: Sized {
-//     fn bpp() -> u32;
-// }
-// unsafe impl BufferData for  u8 { fn bpp() -> u32 {  8 } }
-// unsafe impl BufferData for u16 { fn bpp() -> u32 { 16 } }
-// unsafe impl BufferData for u32 { fn bpp() -> u32 { 32 } }
-
-
-pub struct DumbBufOptions<'a> {
-    device: &'a Device,
-    width: u32, height: u32,
-    bpp: u32, depth: u32,
-    format: FourCC, 
-    dumb_flags: DumbBufFlags,
-    addfb2_flags: u32,
-}
-
-impl<'a> DumbBufOptions<'a> {
-    pub fn new(device: &'a Device) -> DumbBufOptions<'a> {
-        DumbBufOptions {
-            device: device,
-            width: 0, height: 0,
-            bpp: 32, depth: 24,
-            format: FourCC::XRGB8888,
-            dumb_flags: DUNNO,
-            addfb2_flags: 0,
-        }
-    }
-    pub fn width(&mut self, width: u32) -> &mut DumbBufOptions<'a> {
-        self.width = width; self
-    }
-    pub fn height(&mut self, height: u32) -> &mut DumbBufOptions<'a> {
-        self.height = height; self
-    }
-    pub fn bpp(&mut self, bpp: u32) -> &mut DumbBufOptions<'a> {
-        self.bpp = bpp; self
-    }
-    pub fn depth(&mut self, depth: u32) -> &mut DumbBufOptions<'a> {
-        self.depth = depth; self
-    }
-    pub fn format(&mut self, format: FourCC) -> &mut DumbBufOptions<'a> {
-        self.format = format; self
-    }
-
-    pub fn create(&mut self) -> io::Result<DumbBuf> {
-        // TODO: dumb depth
-        // const DEPTH: u32 = 24;
-        let device = self.device.try_clone()?;
-        
-        let mut create_dumb =
-            ffi::create_dumb {
-                height: self.height,
-                width: self.width,
-                bpp: self.bpp,
-                flags: self.dumb_flags.bits(),
-                ..Default::default()
-            };
-        impl DrmIoctl for ffi::create_dumb {
-            fn request() -> c_ulong { DRM_IOCTL_MODE_CREATE_DUMB }
-        }
-        try!(device.ioctl(&mut create_dumb));
-
-        let handles = [create_dumb.handle, 0, 0, 0];
-        let pitches = [create_dumb.pitch, 0, 0, 0];
-        let offsets = [0; 4];
-        
-        let fb = try!(Fb::add_fb2(&device, 
-                                  self.width, self.height,
-                                  self.format,
-                                  handles, pitches, offsets, self.addfb2_flags));
-        
-        let mut map_dumb =
-            ffi::map_dumb {
-                handle: create_dumb.handle,
-                ..Default::default()
-            };
-        
-        impl DrmIoctl for ffi::map_dumb {
-            fn request() -> c_ulong { DRM_IOCTL_MODE_MAP_DUMB }
-        }
-        try!(device.ioctl(&mut map_dumb));
-
-        // Possible issue: if the size > usize::MAX then this could cause issues.
-        // If you need buffers that large you're going to have many issues anyway.
-        // (Eg, it won't map into your address space anyway.)
-        let map = try!(Mmap::open_with_offset(device.fd.get_ref(),
-                                              Protection::ReadWrite,
-                                              map_dumb.offset as usize,
-                                              create_dumb.size as usize));
-        
-        Ok(DumbBuf {
-            width: self.width, height: self.height,
-            bpp: self.bpp, flags: self.dumb_flags,
-            pitch: create_dumb.pitch, size: create_dumb.size,
-            handle: create_dumb.handle,
-            fb: try!(device.get(fb)),
-            map: map,
-            dev: device,
-        })
-    }
-}
-
-#[allow(dead_code)]
-pub struct DumbBuf
-{
-    handle: u32,
-    height: u32,
-    width: u32,
-    bpp: u32,
-    flags: DumbBufFlags,
-    pitch: u32,
-    size: u64,
+/// A frame buffer that is removed when dropped.
+pub struct OwnedFb {
     fb: Fb,
-    map: Mmap,
     dev: Device,
 }
 
-// TODO: Clean this up a bit.
-//
-// Currently we:
-//  * create the dumb buffer
-//  * get a device offset (Map)
-//  * mmap the buffer
-//
-// A user might want to do this sepeartly.
-
-impl DumbBuf {
-    /// Fixme: Probably should be unsafe.
-    pub fn create_with_depth(dev: &Device,
-                             width: u32, height: u32,
-                             bpp: u32, depth: u32)
-                             -> io::Result<DumbBuf>
-    {
-        DumbBufOptions::new(dev)
-            .width(width).height(height)
-            .bpp(bpp).depth(depth)
-            .create()
+impl OwnedFb {
+    /// Take ownership of a frame buffer, it will be removed on drop.
+    pub fn new(dev: &Device, fb: Fb) -> io::Result<OwnedFb> {
+        Ok(OwnedFb { fb, dev: dev.try_clone()? })
     }
 
-    /// Not sure what this used for other than creating/mapping the
-    /// frame buffer.
-    pub unsafe fn handle(&self) -> u32 { self.handle }
-
-    /// (width, height) in pixels
-    pub fn size(&self) -> (u32, u32) { (self.width, self.height) }
+    pub fn id(&self) -> Id<Fb> { self.fb.id() }
 
-    /// Size of buffer in bytes
-    pub fn bytes(&self) -> usize { self.size as usize }
-    
-    /// Distance between rows in bytes
-    pub fn pitch(&self) -> usize { self.pitch as usize }
-
-    /// Bits per pixel
-    pub fn bpp(&self) -> u32 { self.bpp }
-
-    /// Access the Fb object associated with this.
-    pub fn fb(&self) -> &Fb {
-        &self.fb
-    }
+    /// Access the Fb object.
+    pub fn fb(&self) -> &Fb { &self.fb }
 }
 
-impl AsMut<[u8]> for DumbBuf {
-    fn as_mut(&mut self) -> &mut [u8] {
-        unsafe {
-            let ptr = self.map.mut_ptr() as *mut _;
-            let size = (self.size as usize) / size_of::<u8>();
-            slice::from_raw_parts_mut(ptr, size)
-        }
+impl fmt::Debug for OwnedFb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OwnedFb").field(&self.fb).finish()
     }
 }
-impl AsMut<[u16]> for DumbBuf {
-    fn as_mut(&mut self) -> &mut [u16] {
-        unsafe {
-            let ptr = self.map.mut_ptr() as *mut _;
-            let size = (self.size as usize) / size_of::<u16>();
-            slice::from_raw_parts_mut(ptr, size)
-        }
+
+impl Drop for OwnedFb {
+    fn drop(&mut self) {
+        // Nothing sensible to do if this fails.
+        Fb::rm(&self.dev, self.fb.id()).ok();
     }
 }
-impl AsMut<[u32]> for DumbBuf {
-    fn as_mut(&mut self) -> &mut [u32] {
-        unsafe {
-            let ptr = self.map.mut_ptr() as *mut _;
-            let size = (self.size as usize) / size_of::<u32>();
-            slice::from_raw_parts_mut(ptr, size)
-        }
+
+bitflags! {
+    pub flags PageFlipFlags: u32
+    {
+        /// Send an `Event::PageFlip` when the flip completes.
+        const PAGE_FLIP_EVENT           = (1<<0),
+        /// Flip now, without waiting for vblank. (May tear.)
+        const PAGE_FLIP_ASYNC           = (1<<1),
+        /// Flip on the vblank with the given sequence number.
+        const PAGE_FLIP_TARGET_ABSOLUTE = (1<<2),
+        /// Flip on the vblank the given number of vblanks from now.
+        const PAGE_FLIP_TARGET_RELATIVE = (1<<3),
     }
 }
 
+/// How a flip was made. See `Master::page_flip_async`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum FlipMode {
+    /// Straight away, without waiting for vblank. May tear.
+    Async,
+    /// At the next vblank.
+    Vsync,
+}
 
-impl Drop for DumbBuf {
-    fn drop(&mut self) {
-        // self.map.take();
-        // We need to continue if this fails.
-        Fb::rm(&self.dev, self.fb.id()).ok();
+impl DrmIoctl for ffi::crtc_page_flip {
+    fn request() -> c_ulong { DRM_IOCTL_MODE_PAGE_FLIP }
+}
 
-        impl DrmIoctl for ffi::destroy_dumb {
-            fn request() -> c_ulong { DRM_IOCTL_MODE_DESTROY_DUMB }
-        }
-        let mut destroy = ffi::destroy_dumb { handle: self.handle };
-        self.dev.ioctl(&mut destroy).ok();
-    }
+/// The page flip ioctl, for callers that know the device is master
+/// without holding a `Master`.
+pub(crate) fn page_flip(dev: &Device, crtc_id: Id<Crtc>, fb_id: Id<Fb>, flags: PageFlipFlags,
+                        user_data: u64) -> io::Result<()>
+{
+    page_flip_target(dev, crtc_id, fb_id, flags, 0, user_data)
 }
 
-impl GemHandle for DumbBuf {
-    fn bo_handle(&self) -> u32 {
-        self.handle
+pub(crate) fn page_flip_target(dev: &Device, crtc_id: Id<Crtc>, fb_id: Id<Fb>,
+                               flags: PageFlipFlags, sequence: u32, user_data: u64)
+                               -> io::Result<()>
+{
+    // struct drm_mode_crtc_page_flip_target: the same, with the
+    // reserved field as the target.
+    let mut flip = ffi::crtc_page_flip {
+        crtc_id: crtc_id.as_u32(),
+        fb_id: fb_id.as_u32(),
+        flags: flags.bits(),
+        reserved: sequence,
+        user_data,
+    };
+    dev.ioctl(&mut flip)
+}
+
+impl<'a> Master<'a> {
+    /// Show a different frame buffer on a CRTC, at the next vblank.
+    ///
+    /// The CRTC must already have a mode set (see `set_crtc`), and the
+    /// frame buffer must match its size and format. With
+    /// `PAGE_FLIP_EVENT`, an `Event::PageFlip` carrying `user_data` is
+    /// sent when the flip happens.
+    ///
+    /// # Errors
+    ///
+    /// `EBUSY` (as `ErrorKind::Other`) if a flip is already pending on
+    /// the CRTC.
+    pub fn page_flip(&self,
+                     crtc_id: Id<Crtc>,
+                     fb_id: Id<Fb>,
+                     flags: PageFlipFlags,
+                     user_data: u64)
+                     -> io::Result<()>
+    {
+        page_flip(self, crtc_id, fb_id, flags, user_data)
     }
-    fn width(&self) -> u32 {
-        self.width
+
+    /// Page flip without waiting for vblank (`PAGE_FLIP_ASYNC`) if the
+    /// driver can, or else at the next vblank as `page_flip`. Returns
+    /// which it was.
+    ///
+    /// Async flips need `Capability::AsyncPageFlip`, and drivers can
+    /// refuse particular ones (changing the format, say), which also
+    /// falls back to a vsynced flip.
+    pub fn page_flip_async(&self,
+                           crtc_id: Id<Crtc>,
+                           fb_id: Id<Fb>,
+                           flags: PageFlipFlags,
+                           user_data: u64)
+                           -> io::Result<FlipMode>
+    {
+        let flags = flags - PAGE_FLIP_ASYNC;
+        if self.cached_capability(Capability::AsyncPageFlip) != 0 {
+            match self.page_flip(crtc_id, fb_id, flags | PAGE_FLIP_ASYNC, user_data) {
+                Ok(()) => return Ok(FlipMode::Async),
+                Err(ref err) if err.kind() == io::ErrorKind::InvalidInput => (),
+                Err(err) => return Err(err),
+            }
+        }
+        self.page_flip(crtc_id, fb_id, flags, user_data)?;
+        Ok(FlipMode::Vsync)
     }
-    fn height(&self) -> u32 {
-        self.height
+
+    /// Page flip on a particular vblank: `sequence` with
+    /// `PAGE_FLIP_TARGET_ABSOLUTE`, or `sequence` vblanks from now with
+    /// `PAGE_FLIP_TARGET_RELATIVE`. Needs `Capability::PageFlipTarget`.
+    ///
+    /// # Errors
+    ///
+    /// `EINVAL` (as `ErrorKind::InvalidInput`) if the target is more
+    /// than one vblank away: wait for the vblank before it first.
+    pub fn page_flip_target(&self,
+                            crtc_id: Id<Crtc>,
+                            fb_id: Id<Fb>,
+                            flags: PageFlipFlags,
+                            sequence: u32,
+                            user_data: u64)
+                            -> io::Result<()>
+    {
+        page_flip_target(self, crtc_id, fb_id, flags, sequence, user_data)
     }
-}
 
-/// Flags for DumbBuf creation.
-///
-/// TODO: find out what values these should be.
-///
-/// (DUNNO is Australian slang for "I don't know").
-bitflags! {
-    pub flags DumbBufFlags: u32 {
-        // TODO: Find out what the actually flags are.
-        const DUNNO = 1 << 0
+    /// Page flip, calling `callback` when the flip's event is
+    /// dispatched by `ctx`. `PAGE_FLIP_EVENT` is added to `flags`.
+    pub fn page_flip_with<'c, F>(&self,
+                                 ctx: &mut EventContext<'c>,
+                                 crtc_id: Id<Crtc>,
+                                 fb_id: Id<Fb>,
+                                 flags: PageFlipFlags,
+                                 callback: F)
+                                 -> io::Result<()>
+        where F: FnOnce(Completion) + 'c
+    {
+        let token = ctx.token(callback);
+        self.page_flip(crtc_id, fb_id, flags | PAGE_FLIP_EVENT, token).inspect_err(|_| {
+            ctx.cancel(token);
+        })
     }
 }
@@ -0,0 +1,125 @@
+//! Writeback connectors: capturing the output of a CRTC.
+//!
+//! A writeback connector is attached to a CRTC like any other
+//! connector, but instead of driving a display it writes what the CRTC
+//! composes into a frame buffer. Each atomic commit that sets its
+//! `WRITEBACK_FB_ID` captures one frame.
+//!
+//! They're only listed after setting `ClientCapability::Atomic` and
+//! `ClientCapability::WritebackConnectors`.
+//!
+//! ```ignore
+//! let writeback = Writeback::new(&dev, connector)?;
+//! let format = writeback.pick_format(&[FourCC::XRGB8888]).unwrap();
+//! let buf = DumbBufOptions::new(&dev).width(w).height(h).format(format).create()?;
+//! writeback.capture(&master, crtc, buf.fb().id(), Some(Duration::from_secs(1)))?;
+//! ```
+
+use std::io;
+use std::time::Duration;
+use fourcc::FourCC;
+use super::{Connector, ConnectorType, Crtc, Fb, Id, Property, PropertyBlob, Resource};
+use super::{AtomicRequest, ATOMIC_ALLOW_MODESET};
+use {Device, Master};
+
+/// A writeback connector, and the properties needed to use it.
+#[derive(Debug, Clone)]
+pub struct Writeback {
+    connector: Id<Connector>,
+    crtc_prop: Id<Property>,
+    fb_prop: Id<Property>,
+    formats: Vec<FourCC>,
+}
+
+fn property<T: Resource>(dev: &Device, obj: Id<T>, name: &str)
+                         -> io::Result<(Id<Property>, u64)>
+{
+    dev.find_property(obj, name)?.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound,
+                       format!("Writeback connector has no {} property", name))
+    })
+}
+
+impl Writeback {
+    /// Look up a writeback connector.
+    ///
+    /// # Errors
+    ///
+    /// `ErrorKind::InvalidInput` if it's not a writeback connector.
+    pub fn new(dev: &Device, connector: Id<Connector>) -> io::Result<Writeback> {
+        if dev.get(connector)?.connector_type() != ConnectorType::WRITEBACK {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "Not a writeback connector"));
+        }
+        let (crtc_prop, _) = property(dev, connector, "CRTC_ID")?;
+        let (fb_prop, _) = property(dev, connector, "WRITEBACK_FB_ID")?;
+        let (_, blob_id) = property(dev, connector, "WRITEBACK_PIXEL_FORMATS")?;
+        let formats = match unsafe { Id::<PropertyBlob>::from_u32(blob_id as u32) } {
+            Some(blob_id) => {
+                // An array of fourcc codes.
+                dev.get(blob_id)?.data().chunks_exact(4)
+                    .map(|code| u32::from_ne_bytes([code[0], code[1], code[2], code[3]]))
+                    .map(FourCC::from)
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+        Ok(Writeback { connector, crtc_prop, fb_prop, formats })
+    }
+
+    pub fn connector(&self) -> Id<Connector> { self.connector }
+
+    /// Formats frame buffers can be written in.
+    pub fn formats(&self) -> &[FourCC] { &self.formats }
+
+    /// The first of `preferred` that can be written, or failing that
+    /// the first format the connector supports.
+    pub fn pick_format(&self, preferred: &[FourCC]) -> Option<FourCC> {
+        preferred.iter().cloned()
+            .find(|format| self.formats.contains(format))
+            .or_else(|| self.formats.first().cloned())
+    }
+
+    /// Add a capture to an atomic request: route `crtc` to the
+    /// connector, write into `fb`, and ask for a fence that signals
+    /// when it's done. The fence is in `OutFences::writeback`.
+    ///
+    /// `fb` must be the size of the CRTC's mode, in one of `formats`.
+    /// The connector stays routed to `crtc` until `detach`ed.
+    pub fn attach(&self, dev: &Device, req: &mut AtomicRequest, crtc: Id<Crtc>, fb: Id<Fb>)
+                  -> io::Result<()>
+    {
+        req.add_property(self.connector, self.crtc_prop, crtc.as_u32() as u64);
+        req.add_property(self.connector, self.fb_prop, fb.as_u32() as u64);
+        req.writeback_out_fence(dev, self.connector)
+    }
+
+    /// Add unrouting the connector from its CRTC to an atomic request.
+    /// Needs `ATOMIC_ALLOW_MODESET`.
+    pub fn detach(&self, req: &mut AtomicRequest) {
+        req.add_property(self.connector, self.crtc_prop, 0);
+    }
+
+    /// Capture one frame of `crtc` into `fb`, waiting for it to be
+    /// written. A `timeout` of `None` waits forever.
+    ///
+    /// The connector is detached again afterwards. To capture every
+    /// frame, `attach` it in your own commits instead.
+    ///
+    /// # Errors
+    ///
+    /// `ErrorKind::TimedOut` if the frame wasn't written in time.
+    pub fn capture(&self, master: &Master, crtc: Id<Crtc>, fb: Id<Fb>,
+                   timeout: Option<Duration>) -> io::Result<()>
+    {
+        let mut req = AtomicRequest::new();
+        self.attach(master, &mut req, crtc, fb)?;
+        let fences = master.atomic_commit(&mut req, ATOMIC_ALLOW_MODESET, 0)?;
+        let written = fences.writeback.iter().try_for_each(|(_, fence)| fence.wait(timeout));
+
+        let mut req = AtomicRequest::new();
+        self.detach(&mut req);
+        master.atomic_commit(&mut req, ATOMIC_ALLOW_MODESET, 0)?;
+        written
+    }
+}
@@ -0,0 +1,414 @@
+//! Format modifiers.
+//!
+//! A modifier describes how the pixels of a buffer are laid out in
+//! memory, on top of the pixel format given by a `FourCC`. `LINEAR` is
+//! the plain row-major layout everyone knows. Everything else is
+//! vendor specific: tiling, compression, etc.
+//!
+//! The top 8 bits of a modifier name the vendor. The remaining 56 bits
+//! are defined by that vendor. (See `drm_fourcc.h` in the kernel for
+//! the details).
+
+use std::fmt;
+
+/// Vendors that define their own modifiers.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Vendor {
+    None,
+    Intel,
+    Amd,
+    Nvidia,
+    Samsung,
+    Qcom,
+    Vivante,
+    Broadcom,
+    Arm,
+    Allwinner,
+    Amlogic,
+    /// A vendor code unknown to this library.
+    Other(u8),
+}
+
+impl Vendor {
+    fn from_u8(v: u8) -> Vendor {
+        match v {
+            0x00 => Vendor::None,
+            0x01 => Vendor::Intel,
+            0x02 => Vendor::Amd,
+            0x03 => Vendor::Nvidia,
+            0x04 => Vendor::Samsung,
+            0x05 => Vendor::Qcom,
+            0x06 => Vendor::Vivante,
+            0x07 => Vendor::Broadcom,
+            0x08 => Vendor::Arm,
+            0x09 => Vendor::Allwinner,
+            0x0a => Vendor::Amlogic,
+            v => Vendor::Other(v),
+        }
+    }
+
+    fn as_u8(&self) -> u8 {
+        match *self {
+            Vendor::None => 0x00,
+            Vendor::Intel => 0x01,
+            Vendor::Amd => 0x02,
+            Vendor::Nvidia => 0x03,
+            Vendor::Samsung => 0x04,
+            Vendor::Qcom => 0x05,
+            Vendor::Vivante => 0x06,
+            Vendor::Broadcom => 0x07,
+            Vendor::Arm => 0x08,
+            Vendor::Allwinner => 0x09,
+            Vendor::Amlogic => 0x0a,
+            Vendor::Other(v) => v,
+        }
+    }
+}
+
+const fn fourcc_mod_code(vendor: u8, val: u64) -> u64 {
+    ((vendor as u64) << 56) | (val & 0x00ff_ffff_ffff_ffff)
+}
+
+// ARM modifiers have a 4 bit type in bits [55:52].
+const ARM_TYPE_AFBC: u64 = 0x00;
+const ARM_TYPE_MISC: u64 = 0x03;
+const ARM_TYPE_SHIFT: u64 = 52;
+
+const fn arm_code(type_: u64, val: u64) -> u64 {
+    fourcc_mod_code(0x08, (type_ << ARM_TYPE_SHIFT) | (val & 0x000f_ffff_ffff_ffff))
+}
+
+/// AFBC superblock size: 16x16 pixels.
+pub const AFBC_BLOCK_SIZE_16X16: u64 = 1;
+/// AFBC superblock size: 32x8 pixels.
+pub const AFBC_BLOCK_SIZE_32X8: u64 = 2;
+/// AFBC superblock size: 64x4 pixels.
+pub const AFBC_BLOCK_SIZE_64X4: u64 = 3;
+/// AFBC superblock size: 32x8 for luma, 64x4 for chroma.
+pub const AFBC_BLOCK_SIZE_32X8_64X4: u64 = 4;
+const AFBC_BLOCK_SIZE_MASK: u64 = 0xf;
+
+/// AFBC YUV-like transform.
+pub const AFBC_YTR: u64 = 1 << 4;
+/// AFBC block split.
+pub const AFBC_SPLIT: u64 = 1 << 5;
+/// AFBC sparse layout.
+pub const AFBC_SPARSE: u64 = 1 << 6;
+/// AFBC copy-block restrict.
+pub const AFBC_CBR: u64 = 1 << 7;
+/// AFBC tiled layout.
+pub const AFBC_TILED: u64 = 1 << 8;
+/// AFBC solid color blocks.
+pub const AFBC_SC: u64 = 1 << 9;
+/// AFBC double buffer.
+pub const AFBC_DB: u64 = 1 << 10;
+/// AFBC buffer content hints.
+pub const AFBC_BCH: u64 = 1 << 11;
+/// AFBC uncompressed storage mode.
+pub const AFBC_USM: u64 = 1 << 12;
+
+// In the order they are printed.
+const AFBC_FLAG_NAMES: &[(u64, &str)] = &[
+    (AFBC_SPLIT, "SPLIT"),
+    (AFBC_SPARSE, "SPARSE"),
+    (AFBC_YTR, "YTR"),
+    (AFBC_CBR, "CBR"),
+    (AFBC_TILED, "TILED"),
+    (AFBC_SC, "SC"),
+    (AFBC_DB, "DB"),
+    (AFBC_BCH, "BCH"),
+    (AFBC_USM, "USM"),
+];
+
+// AMD modifiers are a bunch of bitfields.
+const AMD_TILE_VERSION_SHIFT: u64 = 0;
+const AMD_TILE_VERSION_MASK: u64 = 0xff;
+const AMD_TILE_SHIFT: u64 = 8;
+const AMD_TILE_MASK: u64 = 0x1f;
+const AMD_DCC_SHIFT: u64 = 13;
+const AMD_DCC_RETILE_SHIFT: u64 = 14;
+const AMD_DCC_PIPE_ALIGN_SHIFT: u64 = 15;
+const AMD_DCC_INDEPENDENT_64B_SHIFT: u64 = 16;
+const AMD_DCC_INDEPENDENT_128B_SHIFT: u64 = 17;
+const AMD_DCC_MAX_COMPRESSED_BLOCK_SHIFT: u64 = 18;
+const AMD_DCC_CONSTANT_ENCODE_SHIFT: u64 = 20;
+
+// AMD tile versions.
+pub const AMD_TILE_VER_GFX9: u64 = 1;
+pub const AMD_TILE_VER_GFX10: u64 = 2;
+pub const AMD_TILE_VER_GFX10_RBPLUS: u64 = 3;
+pub const AMD_TILE_VER_GFX11: u64 = 4;
+
+// AMD swizzle modes.
+pub const AMD_TILE_GFX9_64K_S: u64 = 9;
+pub const AMD_TILE_GFX9_64K_D: u64 = 10;
+pub const AMD_TILE_GFX9_64K_S_X: u64 = 25;
+pub const AMD_TILE_GFX9_64K_D_X: u64 = 26;
+pub const AMD_TILE_GFX9_64K_R_X: u64 = 27;
+pub const AMD_TILE_GFX11_256K_R_X: u64 = 31;
+
+const BROADCOM_PARAM_SHIFT: u64 = 8;
+const BROADCOM_PARAM_MASK: u64 = 0x0000_ffff_ffff_ffff;
+
+/// A DRM format modifier.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct Modifier(pub u64);
+
+impl Modifier {
+    /// Plain row-major layout.
+    pub const LINEAR: Modifier = Modifier(0);
+    /// No modifier. Used where the layout is driver defined and not
+    /// known to userspace.
+    pub const INVALID: Modifier = Modifier(0x00ff_ffff_ffff_ffff);
+
+    pub const INTEL_X_TILED: Modifier = Modifier(fourcc_mod_code(0x01, 1));
+    pub const INTEL_Y_TILED: Modifier = Modifier(fourcc_mod_code(0x01, 2));
+    pub const INTEL_YF_TILED: Modifier = Modifier(fourcc_mod_code(0x01, 3));
+    pub const INTEL_Y_TILED_CCS: Modifier = Modifier(fourcc_mod_code(0x01, 4));
+    pub const INTEL_YF_TILED_CCS: Modifier = Modifier(fourcc_mod_code(0x01, 5));
+    pub const INTEL_Y_TILED_GEN12_RC_CCS: Modifier = Modifier(fourcc_mod_code(0x01, 6));
+    pub const INTEL_Y_TILED_GEN12_MC_CCS: Modifier = Modifier(fourcc_mod_code(0x01, 7));
+    pub const INTEL_Y_TILED_GEN12_RC_CCS_CC: Modifier = Modifier(fourcc_mod_code(0x01, 8));
+    pub const INTEL_4_TILED: Modifier = Modifier(fourcc_mod_code(0x01, 9));
+
+    pub const SAMSUNG_64_32_TILE: Modifier = Modifier(fourcc_mod_code(0x04, 1));
+    pub const SAMSUNG_16_16_TILE: Modifier = Modifier(fourcc_mod_code(0x04, 2));
+
+    pub const QCOM_COMPRESSED: Modifier = Modifier(fourcc_mod_code(0x05, 1));
+    pub const QCOM_TILED2: Modifier = Modifier(fourcc_mod_code(0x05, 2));
+    pub const QCOM_TILED3: Modifier = Modifier(fourcc_mod_code(0x05, 3));
+
+    pub const BROADCOM_VC4_T_TILED: Modifier = Modifier(fourcc_mod_code(0x07, 1));
+    pub const BROADCOM_SAND32: Modifier = Modifier(fourcc_mod_code(0x07, 2));
+    pub const BROADCOM_SAND64: Modifier = Modifier(fourcc_mod_code(0x07, 3));
+    pub const BROADCOM_SAND128: Modifier = Modifier(fourcc_mod_code(0x07, 4));
+    pub const BROADCOM_SAND256: Modifier = Modifier(fourcc_mod_code(0x07, 5));
+    pub const BROADCOM_UIF: Modifier = Modifier(fourcc_mod_code(0x07, 6));
+
+    pub const NVIDIA_TEGRA_TILED: Modifier = Modifier(fourcc_mod_code(0x03, 1));
+
+    pub const ARM_16X16_BLOCK_U_INTERLEAVED: Modifier = Modifier(arm_code(ARM_TYPE_MISC, 1));
+
+    pub const ALLWINNER_TILED: Modifier = Modifier(fourcc_mod_code(0x09, 1));
+
+    /// Build a modifier from a vendor and a vendor specific value.
+    pub fn new(vendor: Vendor, value: u64) -> Modifier {
+        Modifier(fourcc_mod_code(vendor.as_u8(), value))
+    }
+
+    /// An ARM AFBC modifier. `flags` is one of the `AFBC_BLOCK_SIZE_*`
+    /// values or'ed with any of the `AFBC_*` flags.
+    pub fn arm_afbc(flags: u64) -> Modifier {
+        Modifier(arm_code(ARM_TYPE_AFBC, flags))
+    }
+
+    /// A Broadcom SAND modifier with the given column height.
+    ///
+    /// `sand` should be one of the `BROADCOM_SAND*` constants.
+    pub fn broadcom_sand(sand: Modifier, column_height: u64) -> Modifier {
+        Modifier(sand.0 | ((column_height & BROADCOM_PARAM_MASK) << BROADCOM_PARAM_SHIFT))
+    }
+
+    /// An NVIDIA block-linear 2D modifier.
+    ///
+    /// * `c`: Compression type (3 bits).
+    /// * `s`: Sector layout (1 bit).
+    /// * `g`: Kind generation (2 bits).
+    /// * `k`: Page kind (8 bits).
+    /// * `h`: log2(height of a block in GOBs) (4 bits).
+    pub fn nvidia_block_linear_2d(c: u8, s: u8, g: u8, k: u8, h: u8) -> Modifier {
+        let val = 0x10
+            | (h as u64 & 0xf)
+            | ((k as u64) << 12)
+            | ((g as u64 & 0x3) << 20)
+            | ((s as u64 & 0x1) << 22)
+            | ((c as u64 & 0x7) << 23);
+        Modifier(fourcc_mod_code(0x03, val))
+    }
+
+    /// Who defined this modifier.
+    pub fn vendor(&self) -> Vendor {
+        Vendor::from_u8((self.0 >> 56) as u8)
+    }
+
+    /// The vendor specific part of the modifier.
+    pub fn value(&self) -> u64 {
+        self.0 & 0x00ff_ffff_ffff_ffff
+    }
+
+    /// True for `LINEAR`. (The only layout any device can be expected
+    /// to understand).
+    pub fn is_linear(&self) -> bool {
+        *self == Modifier::LINEAR
+    }
+
+    fn fmt_intel(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self.value() {
+            1 => "X_TILED",
+            2 => "Y_TILED",
+            3 => "Yf_TILED",
+            4 => "Y_TILED_CCS",
+            5 => "Yf_TILED_CCS",
+            6 => "Y_TILED_GEN12_RC_CCS",
+            7 => "Y_TILED_GEN12_MC_CCS",
+            8 => "Y_TILED_GEN12_RC_CCS_CC",
+            9 => "4_TILED",
+            v => return write!(f, "INTEL(0x{:x})", v),
+        };
+        write!(f, "I915_{}", name)
+    }
+
+    fn fmt_amd(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let v = self.value();
+        let version = (v >> AMD_TILE_VERSION_SHIFT) & AMD_TILE_VERSION_MASK;
+        let tile = (v >> AMD_TILE_SHIFT) & AMD_TILE_MASK;
+        let bit = |shift: u64| (v >> shift) & 1 != 0;
+
+        f.write_str("AMD(")?;
+        match version {
+            AMD_TILE_VER_GFX9 => f.write_str("GFX9")?,
+            AMD_TILE_VER_GFX10 => f.write_str("GFX10")?,
+            AMD_TILE_VER_GFX10_RBPLUS => f.write_str("GFX10_RBPLUS")?,
+            AMD_TILE_VER_GFX11 => f.write_str("GFX11")?,
+            v => write!(f, "VER{}", v)?,
+        }
+        match tile {
+            AMD_TILE_GFX9_64K_S => f.write_str(", 64K_S")?,
+            AMD_TILE_GFX9_64K_D => f.write_str(", 64K_D")?,
+            AMD_TILE_GFX9_64K_S_X => f.write_str(", 64K_S_X")?,
+            AMD_TILE_GFX9_64K_D_X => f.write_str(", 64K_D_X")?,
+            AMD_TILE_GFX9_64K_R_X => f.write_str(", 64K_R_X")?,
+            AMD_TILE_GFX11_256K_R_X => f.write_str(", 256K_R_X")?,
+            t => write!(f, ", TILE{}", t)?,
+        }
+        if bit(AMD_DCC_SHIFT) {
+            f.write_str(", DCC")?;
+            if bit(AMD_DCC_RETILE_SHIFT) {
+                f.write_str(", DCC_RETILE")?;
+            }
+            if bit(AMD_DCC_PIPE_ALIGN_SHIFT) {
+                f.write_str(", DCC_PIPE_ALIGN")?;
+            }
+            if bit(AMD_DCC_INDEPENDENT_64B_SHIFT) {
+                f.write_str(", DCC_I64B")?;
+            }
+            if bit(AMD_DCC_INDEPENDENT_128B_SHIFT) {
+                f.write_str(", DCC_I128B")?;
+            }
+            match (v >> AMD_DCC_MAX_COMPRESSED_BLOCK_SHIFT) & 0x3 {
+                0 => f.write_str(", DCC_MAX_64B")?,
+                1 => f.write_str(", DCC_MAX_128B")?,
+                2 => f.write_str(", DCC_MAX_256B")?,
+                _ => (),
+            }
+            if bit(AMD_DCC_CONSTANT_ENCODE_SHIFT) {
+                f.write_str(", DCC_CONSTANT_ENCODE")?;
+            }
+        }
+        f.write_str(")")
+    }
+
+    fn fmt_arm(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let v = self.value();
+        let type_ = v >> ARM_TYPE_SHIFT;
+        let v = v & 0x000f_ffff_ffff_ffff;
+        match type_ {
+            ARM_TYPE_AFBC => {
+                f.write_str("AFBC(")?;
+                match v & AFBC_BLOCK_SIZE_MASK {
+                    AFBC_BLOCK_SIZE_16X16 => f.write_str("16x16")?,
+                    AFBC_BLOCK_SIZE_32X8 => f.write_str("32x8")?,
+                    AFBC_BLOCK_SIZE_64X4 => f.write_str("64x4")?,
+                    AFBC_BLOCK_SIZE_32X8_64X4 => f.write_str("32x8_64x4")?,
+                    b => write!(f, "BLOCK{}", b)?,
+                }
+                for &(flag, name) in AFBC_FLAG_NAMES {
+                    if v & flag != 0 {
+                        write!(f, ", {}", name)?;
+                    }
+                }
+                f.write_str(")")
+            }
+            ARM_TYPE_MISC if v == 1 => f.write_str("ARM_16X16_BLOCK_U_INTERLEAVED"),
+            _ => write!(f, "ARM(0x{:x})", self.value()),
+        }
+    }
+
+    fn fmt_broadcom(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let v = self.value();
+        let param = (v >> BROADCOM_PARAM_SHIFT) & BROADCOM_PARAM_MASK;
+        let sand = match v & 0xff {
+            1 if param == 0 => return f.write_str("BROADCOM_VC4_T_TILED"),
+            6 if param == 0 => return f.write_str("BROADCOM_UIF"),
+            2 => 32,
+            3 => 64,
+            4 => 128,
+            5 => 256,
+            _ => return write!(f, "BROADCOM(0x{:x})", v),
+        };
+        write!(f, "BROADCOM_SAND{}(height={})", sand, param)
+    }
+
+    fn fmt_nvidia(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let v = self.value();
+        if v == 1 {
+            return f.write_str("NVIDIA_TEGRA_TILED");
+        }
+        if v & 0x10 == 0 {
+            return write!(f, "NVIDIA(0x{:x})", v);
+        }
+        write!(f, "NVIDIA_BLOCK_LINEAR_2D(h={}, k=0x{:x}, g={}, s={}, c={})",
+               v & 0xf,
+               (v >> 12) & 0xff,
+               (v >> 20) & 0x3,
+               (v >> 22) & 0x1,
+               (v >> 23) & 0x7)
+    }
+}
+
+impl From<u64> for Modifier {
+    fn from(v: u64) -> Modifier { Modifier(v) }
+}
+
+impl From<Modifier> for u64 {
+    fn from(m: Modifier) -> u64 { m.0 }
+}
+
+impl fmt::Display for Modifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Modifier::LINEAR => return f.write_str("LINEAR"),
+            Modifier::INVALID => return f.write_str("INVALID"),
+            _ => (),
+        }
+        match self.vendor() {
+            Vendor::Intel => self.fmt_intel(f),
+            Vendor::Amd => self.fmt_amd(f),
+            Vendor::Arm => self.fmt_arm(f),
+            Vendor::Broadcom => self.fmt_broadcom(f),
+            Vendor::Nvidia => self.fmt_nvidia(f),
+            Vendor::Samsung => match self.value() {
+                1 => f.write_str("SAMSUNG_64_32_TILE"),
+                2 => f.write_str("SAMSUNG_16_16_TILE"),
+                v => write!(f, "SAMSUNG(0x{:x})", v),
+            },
+            Vendor::Qcom => match self.value() {
+                1 => f.write_str("QCOM_COMPRESSED"),
+                2 => f.write_str("QCOM_TILED2"),
+                3 => f.write_str("QCOM_TILED3"),
+                v => write!(f, "QCOM(0x{:x})", v),
+            },
+            Vendor::Allwinner => match self.value() {
+                1 => f.write_str("ALLWINNER_TILED"),
+                v => write!(f, "ALLWINNER(0x{:x})", v),
+            },
+            vendor => write!(f, "{:?}(0x{:x})", vendor, self.value()),
+        }
+    }
+}
+
+impl fmt::Debug for Modifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Modifier({} = 0x{:016x})", self, self.0)
+    }
+}
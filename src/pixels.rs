@@ -0,0 +1,327 @@
+//! Typed, pitch aware access to pixels in memory.
+//!
+//! Buffers from the driver often have padding at the end of each row,
+//! so row `y` starts at `y * pitch` rather than `y * width`. `Pixels`
+//! hides that: it's a view of `width` x `height` pixels of a known
+//! format inside a padded buffer.
+//!
+//! ```ignore
+//! let mut pixels = buffer.pixels::<Xrgb8888>().unwrap();
+//! for (y, mut row) in pixels.rows_mut().enumerate() {
+//!     row.fill(Xrgb8888::from_rgba([y as u8, 0, 0xff, 0xff]));
+//! }
+//! ```
+
+use std::io;
+use std::marker::PhantomData;
+use std::fmt;
+use std::slice::ChunksMut;
+use convert::{Image, ImageMut};
+use fourcc::FourCC;
+
+/// A single plane pixel format with a fixed number of bytes per pixel.
+///
+/// Implemented by the marker types in this module. Pixels are stored
+/// as little endian integers, like DRM describes them.
+pub trait PixelFormat {
+    /// The DRM format.
+    const FOURCC: FourCC;
+    /// Bytes per pixel.
+    const BYTES: usize;
+    /// The integer a pixel is stored as.
+    type Pixel: Copy + fmt::Debug + PartialEq;
+
+    /// Read a pixel from the start of `bytes`.
+    fn read(bytes: &[u8]) -> Self::Pixel;
+    /// Write a pixel to the start of `bytes`.
+    fn write(bytes: &mut [u8], pixel: Self::Pixel);
+    /// Make a pixel from 8 bit red, green, blue and alpha.
+    fn from_rgba(rgba: [u8; 4]) -> Self::Pixel;
+    /// Split a pixel into 8 bit red, green, blue and alpha. Alpha is
+    /// 0xff for formats without it.
+    fn to_rgba(pixel: Self::Pixel) -> [u8; 4];
+}
+
+fn to_bits(v: u8, bits: u32) -> u64 {
+    (v as u64 * ((1 << bits) - 1) + 127) / 255
+}
+
+fn from_bits(v: u64, bits: u32) -> u8 {
+    if bits == 0 {
+        return 0;
+    }
+    let max = (1 << bits) - 1;
+    ((v & max) * 255 / max) as u8
+}
+
+macro_rules! pixel_formats {
+    ($($(#[$attr:meta])* $name:ident = $fourcc:ident, $pixel:ty, $bytes:expr,
+       [$($shift:expr, $bits:expr);*];)*) => {
+        $(
+            $(#[$attr])*
+            #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+            pub enum $name {}
+
+            impl PixelFormat for $name {
+                const FOURCC: FourCC = FourCC::$fourcc;
+                const BYTES: usize = $bytes;
+                type Pixel = $pixel;
+
+                fn read(bytes: &[u8]) -> $pixel {
+                    bytes[..$bytes].iter().rev().fold(0u64, |acc, &b| (acc << 8) | b as u64) as $pixel
+                }
+                fn write(bytes: &mut [u8], pixel: $pixel) {
+                    for (i, b) in bytes[..$bytes].iter_mut().enumerate() {
+                        *b = (pixel as u64 >> (i * 8)) as u8;
+                    }
+                }
+                fn from_rgba(rgba: [u8; 4]) -> $pixel {
+                    let fields: [(u32, u32); 4] = [$(($shift, $bits)),*];
+                    let pixel = fields.iter().zip(&rgba)
+                        .fold(0, |acc, (&(shift, bits), &v)| acc | to_bits(v, bits) << shift);
+                    pixel as $pixel
+                }
+                fn to_rgba(pixel: $pixel) -> [u8; 4] {
+                    let fields: [(u32, u32); 4] = [$(($shift, $bits)),*];
+                    let mut rgba = [0; 4];
+                    for (v, &(shift, bits)) in rgba.iter_mut().zip(&fields) {
+                        *v = from_bits(pixel as u64 >> shift, bits);
+                    }
+                    if fields[3].1 == 0 {
+                        rgba[3] = 0xff;
+                    }
+                    rgba
+                }
+            }
+        )*
+    }
+}
+
+// Channels are (shift, bits) of red, green, blue and alpha. Missing
+// channels have 0 bits.
+pixel_formats! {
+    /// 8 bit red.
+    R8 = R8, u8, 1, [0, 8; 0, 0; 0, 0; 0, 0];
+    /// [15:0] R:G:B 5:6:5
+    Rgb565 = RGB565, u16, 2, [11, 5; 5, 6; 0, 5; 0, 0];
+    /// [15:0] B:G:R 5:6:5
+    Bgr565 = BGR565, u16, 2, [0, 5; 5, 6; 11, 5; 0, 0];
+    /// [15:0] x:R:G:B 1:5:5:5
+    Xrgb1555 = XRGB1555, u16, 2, [10, 5; 5, 5; 0, 5; 0, 0];
+    /// [15:0] A:R:G:B 1:5:5:5
+    Argb1555 = ARGB1555, u16, 2, [10, 5; 5, 5; 0, 5; 15, 1];
+    /// [23:0] R:G:B
+    Rgb888 = RGB888, u32, 3, [16, 8; 8, 8; 0, 8; 0, 0];
+    /// [23:0] B:G:R
+    Bgr888 = BGR888, u32, 3, [0, 8; 8, 8; 16, 8; 0, 0];
+    /// [31:0] x:R:G:B 8:8:8:8
+    Xrgb8888 = XRGB8888, u32, 4, [16, 8; 8, 8; 0, 8; 0, 0];
+    /// [31:0] A:R:G:B 8:8:8:8
+    Argb8888 = ARGB8888, u32, 4, [16, 8; 8, 8; 0, 8; 24, 8];
+    /// [31:0] x:B:G:R 8:8:8:8
+    Xbgr8888 = XBGR8888, u32, 4, [0, 8; 8, 8; 16, 8; 0, 0];
+    /// [31:0] A:B:G:R 8:8:8:8
+    Abgr8888 = ABGR8888, u32, 4, [0, 8; 8, 8; 16, 8; 24, 8];
+    /// [31:0] x:R:G:B 2:10:10:10
+    Xrgb2101010 = XRGB2101010, u32, 4, [20, 10; 10, 10; 0, 10; 0, 0];
+    /// [31:0] A:R:G:B 2:10:10:10
+    Argb2101010 = ARGB2101010, u32, 4, [20, 10; 10, 10; 0, 10; 30, 2];
+    /// [31:0] x:B:G:R 2:10:10:10
+    Xbgr2101010 = XBGR2101010, u32, 4, [0, 10; 10, 10; 20, 10; 0, 0];
+}
+
+/// `width` x `height` pixels of format `F` in a buffer with `pitch`
+/// bytes between rows.
+pub struct Pixels<'a, F: PixelFormat> {
+    data: &'a mut [u8],
+    width: u32,
+    height: u32,
+    pitch: usize,
+    format: PhantomData<F>,
+}
+
+impl<'a, F: PixelFormat> fmt::Debug for Pixels<'a, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Pixels")
+            .field("format", &F::FOURCC)
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("pitch", &self.pitch)
+            .finish()
+    }
+}
+
+impl<'a, F: PixelFormat> Pixels<'a, F> {
+    /// View `data` as pixels.
+    ///
+    /// # Errors
+    ///
+    /// `ErrorKind::InvalidInput` if a row doesn't fit in `pitch`, `pitch`
+    /// is 0 with rows to fit, or `data` is too small.
+    pub fn new(data: &'a mut [u8], width: u32, height: u32, pitch: usize)
+               -> io::Result<Pixels<'a, F>> {
+        let row = width as usize * F::BYTES;
+        if pitch < row {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "Pitch too small for image width"));
+        }
+        if height > 0 && pitch == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "Pitch can't be 0"));
+        }
+        if height > 0 && data.len() < (height as usize - 1) * pitch + row {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "Buffer too small for image"));
+        }
+        Ok(Pixels { data, width, height, pitch, format: PhantomData })
+    }
+
+    pub fn width(&self) -> u32 { self.width }
+
+    pub fn height(&self) -> u32 { self.height }
+
+    /// Distance between rows in bytes
+    pub fn pitch(&self) -> usize { self.pitch }
+
+    fn offset(&self, x: u32, y: u32) -> Option<usize> {
+        if x < self.width && y < self.height {
+            Some(y as usize * self.pitch + x as usize * F::BYTES)
+        } else {
+            None
+        }
+    }
+
+    /// The pixel at (x, y), or `None` if it's outside the view.
+    pub fn get(&self, x: u32, y: u32) -> Option<F::Pixel> {
+        self.offset(x, y).map(|at| F::read(&self.data[at..]))
+    }
+
+    /// Set the pixel at (x, y).
+    ///
+    /// # Panics
+    ///
+    /// If (x, y) is outside the view.
+    pub fn put(&mut self, x: u32, y: u32, pixel: F::Pixel) {
+        let at = self.offset(x, y).expect("pixel out of bounds");
+        F::write(&mut self.data[at..], pixel);
+    }
+
+    /// Set every pixel.
+    pub fn fill(&mut self, pixel: F::Pixel) {
+        for mut row in self.rows_mut() {
+            row.fill(pixel);
+        }
+    }
+
+    /// Iterate over the rows, top to bottom. Rows don't include the
+    /// padding.
+    pub fn rows_mut(&mut self) -> RowsMut<'_, F> {
+        RowsMut {
+            // The pitch is only 0 when there are no rows.
+            rows: self.data.chunks_mut(self.pitch.max(1)),
+            remaining: self.height as usize,
+            width: self.width as usize,
+            format: PhantomData,
+        }
+    }
+
+    /// A view of the `width` x `height` rectangle at (x, y), or `None`
+    /// if it doesn't fit.
+    pub fn sub(&mut self, x: u32, y: u32, width: u32, height: u32) -> Option<Pixels<'_, F>> {
+        if x.checked_add(width)? > self.width || y.checked_add(height)? > self.height {
+            return None;
+        }
+        let start = y as usize * self.pitch + x as usize * F::BYTES;
+        let len = if height == 0 {
+            0
+        } else {
+            (height as usize - 1) * self.pitch + width as usize * F::BYTES
+        };
+        Some(Pixels {
+            data: &mut self.data[start..start + len],
+            width, height,
+            pitch: self.pitch,
+            format: PhantomData,
+        })
+    }
+
+    /// The pixels as an image for `convert`.
+    pub fn as_image(&self) -> Image<'_> {
+        Image::packed(F::FOURCC, self.width, self.height, self.data, self.pitch)
+            .expect("pixel formats are convertible")
+    }
+
+    /// The pixels as a writable image for `convert`.
+    pub fn as_image_mut(&mut self) -> ImageMut<'_> {
+        ImageMut::packed(F::FOURCC, self.width, self.height, self.data, self.pitch)
+            .expect("pixel formats are convertible")
+    }
+}
+
+/// Iterator over the rows of `Pixels`.
+pub struct RowsMut<'a, F: PixelFormat> {
+    rows: ChunksMut<'a, u8>,
+    remaining: usize,
+    width: usize,
+    format: PhantomData<F>,
+}
+
+impl<'a, F: PixelFormat> Iterator for RowsMut<'a, F> {
+    type Item = RowMut<'a, F>;
+
+    fn next(&mut self) -> Option<RowMut<'a, F>> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let data = match self.rows.next() {
+            Some(row) => &mut row[..self.width * F::BYTES],
+            // Rows 0 pixels wide can run past the end of the data.
+            None => &mut [],
+        };
+        Some(RowMut { data, format: PhantomData })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+/// A row of pixels, without padding.
+pub struct RowMut<'a, F: PixelFormat> {
+    data: &'a mut [u8],
+    format: PhantomData<F>,
+}
+
+impl<'a, F: PixelFormat> RowMut<'a, F> {
+    /// Width in pixels.
+    pub fn len(&self) -> usize { self.data.len() / F::BYTES }
+
+    pub fn is_empty(&self) -> bool { self.data.is_empty() }
+
+    /// The pixel at `x`, or `None` if it's past the end.
+    pub fn get(&self, x: usize) -> Option<F::Pixel> {
+        self.data.get(x * F::BYTES..(x + 1) * F::BYTES).map(F::read)
+    }
+
+    /// Set the pixel at `x`.
+    ///
+    /// # Panics
+    ///
+    /// If `x` is past the end of the row.
+    pub fn put(&mut self, x: usize, pixel: F::Pixel) {
+        F::write(&mut self.data[x * F::BYTES..(x + 1) * F::BYTES], pixel);
+    }
+
+    /// Set every pixel in the row.
+    pub fn fill(&mut self, pixel: F::Pixel) {
+        for chunk in self.data.chunks_mut(F::BYTES) {
+            F::write(chunk, pixel);
+        }
+    }
+
+    /// The raw bytes of the row.
+    pub fn bytes_mut(&mut self) -> &mut [u8] {
+        self.data
+    }
+}
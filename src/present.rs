@@ -0,0 +1,210 @@
+//! Showing frames at a chosen time.
+//!
+//! A `Presenter` flips a CRTC to a frame buffer on a particular vblank,
+//! or the vblank nearest a time, and reports when the frame was
+//! actually shown.
+//!
+//! The kernel only takes page flips for the next vblank (targeted
+//! flips can't be more than one ahead either), so for a later one the
+//! flip is made when the event for the vblank before it arrives. Pass
+//! every event to `handle_event`.
+//!
+//! ```ignore
+//! let mut presenter = Presenter::new(&master, crtc, 0)?;
+//! let at = DrmTimestamp::new(Clock::Monotonic, audio_pts);
+//! presenter.present(&master, fb, PresentTarget::Time(at))?;
+//! loop {
+//!     let event = master.read_event()?;
+//!     if let Some(presented) = presenter.handle_event(&master, &event)? {
+//!         println!("Shown at {:?}, on time: {}", presented.time, presented.on_time);
+//!         break;
+//!     }
+//! }
+//! ```
+
+use std::io;
+use event::{DrmTimestamp, Event};
+use frame_clock::FrameClock;
+use mode::{self, Crtc, Fb, Id, PAGE_FLIP_EVENT, PAGE_FLIP_TARGET_ABSOLUTE};
+use vblank::Target;
+use {Capability, Device, Master};
+
+/// When to show a frame.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum PresentTarget {
+    /// On the vblank with this sequence number.
+    Sequence(u64),
+    /// On the vblank nearest this time.
+    Time(DrmTimestamp),
+}
+
+/// A frame that's been shown. See `Presenter::handle_event`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Presented {
+    /// The vblank it was shown on.
+    pub seq: u64,
+    /// When it was shown.
+    pub time: DrmTimestamp,
+    /// The vblank it was meant to be shown on.
+    pub target_seq: u64,
+    /// The time it was meant to be shown, for `PresentTarget::Time`.
+    pub target_time: Option<DrmTimestamp>,
+    /// Was it shown on the target vblank? For `PresentTarget::Time`,
+    /// was it shown within half a frame of the time?
+    pub on_time: bool,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Pending {
+    fb: Id<Fb>,
+    target_seq: u64,
+    target_time: Option<DrmTimestamp>,
+    // The vblank to flip on: the target, or the next one if that's
+    // passed.
+    flip_seq: u64,
+    flipped: bool,
+}
+
+/// Shows frames on a CRTC at chosen vblanks. One at a time.
+#[derive(Debug)]
+pub struct Presenter {
+    crtc: Id<Crtc>,
+    user_data: u64,
+    targeted: bool,
+    clock: FrameClock,
+    pending: Option<Pending>,
+}
+
+impl Presenter {
+    /// A presenter for `crtc`, which must have a mode set. Its events
+    /// carry `user_data`.
+    pub fn new(dev: &Device, crtc: Id<Crtc>, user_data: u64) -> io::Result<Presenter> {
+        let mode = match dev.get(crtc)?.mode() {
+            Some(mode) => *mode,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "CRTC has no mode set")),
+        };
+        let clock = match FrameClock::from_mode(&mode) {
+            Some(clock) => clock.crtc(crtc),
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Mode has no timings")),
+        };
+        Ok(Presenter {
+            crtc,
+            user_data,
+            targeted: dev.capability(Capability::PageFlipTarget).unwrap_or(0) != 0,
+            clock,
+            pending: None,
+        })
+    }
+
+    pub fn crtc(&self) -> Id<Crtc> { self.crtc }
+
+    /// Are flips made with `PAGE_FLIP_TARGET_ABSOLUTE`? Only if the
+    /// driver supports it.
+    pub fn uses_target_flips(&self) -> bool { self.targeted }
+
+    /// The refresh timing, learned from the events handled.
+    pub fn frame_clock(&self) -> &FrameClock { &self.clock }
+
+    /// Is a frame waiting to be shown?
+    pub fn is_pending(&self) -> bool { self.pending.is_some() }
+
+    /// Show `fb` on the target vblank. Returns the vblank's sequence
+    /// number. If it's already passed (or the time has), the frame is
+    /// shown on the next one, and won't be `on_time`.
+    ///
+    /// # Errors
+    ///
+    /// `ErrorKind::WouldBlock` if a frame is already pending.
+    pub fn present(&mut self, master: &Master, fb: Id<Fb>, target: PresentTarget)
+                   -> io::Result<u64>
+    {
+        if self.pending.is_some() {
+            return Err(io::Error::new(io::ErrorKind::WouldBlock,
+                                      "A frame is already pending"));
+        }
+        let now = master.vblank_count(self.crtc)?;
+        self.clock.add_vblank(now.seq, now.time);
+        let (target_seq, target_time) = match target {
+            PresentTarget::Sequence(seq) => (seq, None),
+            PresentTarget::Time(time) => {
+                let (seq, _) = self.clock.vblank_at(time).expect("FrameClock has a vblank");
+                (seq, Some(time))
+            }
+        };
+        let mut pending = Pending {
+            fb,
+            target_seq,
+            target_time,
+            flip_seq: target_seq.max(now.seq + 1),
+            flipped: false,
+        };
+        if pending.flip_seq == now.seq + 1 {
+            self.flip(master, &pending)?;
+            pending.flipped = true;
+        } else {
+            let before = Target::Absolute((pending.flip_seq - 1) as u32);
+            master.request_vblank_event(self.crtc, before, false, self.user_data)?;
+        }
+        self.pending = Some(pending);
+        Ok(target_seq)
+    }
+
+    /// Handle an event from the device. Flips if it's the vblank before
+    /// the target, and returns the frame shown if it's the flip.
+    ///
+    /// # Errors
+    ///
+    /// If the flip fails. The frame is dropped.
+    pub fn handle_event(&mut self, master: &Master, event: &Event) -> io::Result<Option<Presented>> {
+        let mut pending = match self.pending {
+            Some(pending) => pending,
+            None => return Ok(None),
+        };
+        if event.user_data() != Some(self.user_data) || !self.clock.observe(event) {
+            return Ok(None);
+        }
+        match *event {
+            Event::VBlank { .. } if !pending.flipped => {
+                pending.flipped = true;
+                self.pending = Some(pending);
+                self.flip(master, &pending).inspect_err(|_| {
+                    self.pending = None;
+                })?;
+                Ok(None)
+            }
+            Event::PageFlip { seq, tv, .. } if pending.flipped => {
+                self.pending = None;
+                let on_time = match pending.target_time {
+                    // If the time had already passed, the target vblank
+                    // is just the next one, so check the time itself.
+                    Some(target) => {
+                        let off = tv.duration_since(target)
+                            .or_else(|| target.duration_since(tv))
+                            .unwrap_or_default();
+                        off <= self.clock.period() / 2
+                    }
+                    // The event's counter is only 32 bits.
+                    None => seq == pending.target_seq as u32,
+                };
+                Ok(Some(Presented {
+                    seq: seq as u64,
+                    time: tv,
+                    target_seq: pending.target_seq,
+                    target_time: pending.target_time,
+                    on_time,
+                }))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn flip(&self, dev: &Device, pending: &Pending) -> io::Result<()> {
+        if self.targeted {
+            mode::page_flip_target(dev, self.crtc, pending.fb,
+                                   PAGE_FLIP_EVENT | PAGE_FLIP_TARGET_ABSOLUTE,
+                                   pending.flip_seq as u32, self.user_data)
+        } else {
+            mode::page_flip(dev, self.crtc, pending.fb, PAGE_FLIP_EVENT, self.user_data)
+        }
+    }
+}
@@ -0,0 +1,243 @@
+//! PRIME: sharing buffers as dma-buf file descriptors.
+//!
+//! A GEM handle can be exported as a dma-buf fd, which can be passed
+//! to another process or device (a display GPU, a video decoder, ...)
+//! and imported there. Unlike flink names, only processes that are
+//! given the fd can use the buffer.
+//!
+//! A dma-buf can also be mapped with `DmaBuf::map`. CPU access to the
+//! mapping should be bracketed with `DmaBuf::sync_start` and
+//! `DmaBuf::sync_end` (or `DmaBuf::access`), so caches are kept
+//! coherent with the device.
+
+use std::{io, fmt};
+use std::fs::File;
+use std::os::raw::c_ulong;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use libc;
+use memmap::{Mmap, Protection};
+use ioctl_vals::*;
+use gem::GemObject;
+use mode::MapProtection;
+use {fd_ioctl, ffi, Capability, Device, DrmIoctl};
+
+bitflags! {
+    /// Flags for `Device::prime_export`.
+    pub flags PrimeFlags: u32 {
+        /// Close the fd on exec.
+        const PRIME_CLOEXEC = libc::O_CLOEXEC as u32,
+        /// Allow the fd to be mapped for writing.
+        const PRIME_RDWR    = libc::O_RDWR as u32,
+    }
+}
+
+bitflags! {
+    /// What PRIME operations a driver supports. See
+    /// `Device::prime_capabilities`.
+    pub flags PrimeCapabilities: u64 {
+        /// Buffers can be imported from dma-bufs.
+        const PRIME_CAP_IMPORT = 0x1,
+        /// Buffers can be exported as dma-bufs.
+        const PRIME_CAP_EXPORT = 0x2,
+    }
+}
+
+// Both directions use the same struct, so they need their own types.
+#[repr(C)]
+#[derive(Debug)]
+struct HandleToFd(ffi::prime_handle);
+impl DrmIoctl for HandleToFd {
+    fn request() -> c_ulong { DRM_IOCTL_PRIME_HANDLE_TO_FD }
+}
+
+#[repr(C)]
+#[derive(Debug)]
+struct FdToHandle(ffi::prime_handle);
+impl DrmIoctl for FdToHandle {
+    fn request() -> c_ulong { DRM_IOCTL_PRIME_FD_TO_HANDLE }
+}
+
+impl Device {
+    /// Decoded `Capability::Prime`.
+    pub fn prime_capabilities(&self) -> io::Result<PrimeCapabilities> {
+        let caps = self.capability(Capability::Prime)?;
+        Ok(PrimeCapabilities::from_bits_truncate(caps))
+    }
+
+    /// Export the buffer behind a GEM handle as a dma-buf.
+    ///
+    /// Usually called with `PRIME_CLOEXEC | PRIME_RDWR`.
+    pub fn prime_export(&self, handle: u32, flags: PrimeFlags) -> io::Result<OwnedFd> {
+        let mut arg = HandleToFd(ffi::prime_handle {
+            handle,
+            flags: flags.bits(),
+            fd: -1,
+        });
+        self.ioctl(&mut arg)?;
+        Ok(unsafe { OwnedFd::from_raw_fd(arg.0.fd) })
+    }
+
+    /// Import a dma-buf, getting a GEM handle for it.
+    ///
+    /// Importing the same buffer again gives the same handle. It's
+    /// reference counted, so each `GemObject` can be dropped
+    /// separately.
+    pub fn prime_import<F: AsRawFd>(&self, fd: &F) -> io::Result<GemObject> {
+        let mut arg = FdToHandle(ffi::prime_handle {
+            handle: 0,
+            flags: 0,
+            fd: fd.as_raw_fd(),
+        });
+        self.ioctl(&mut arg)?;
+        let size = dma_buf_size(fd.as_raw_fd()).ok();
+        GemObject::adopt(self, arg.0.handle, size)
+    }
+}
+
+/// dma-bufs report their size by seeking to the end.
+fn dma_buf_size(fd: RawFd) -> io::Result<u64> {
+    let size = unsafe { libc::lseek(fd, 0, libc::SEEK_END) };
+    if size < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(size as u64)
+    }
+}
+
+/// Which way CPU access goes, for `DmaBuf::sync_start`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum SyncAccess {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+const DMA_BUF_SYNC_READ: u64 = 1 << 0;
+const DMA_BUF_SYNC_WRITE: u64 = 1 << 1;
+const DMA_BUF_SYNC_END: u64 = 1 << 2;
+
+impl SyncAccess {
+    fn bits(&self) -> u64 {
+        match *self {
+            SyncAccess::Read => DMA_BUF_SYNC_READ,
+            SyncAccess::Write => DMA_BUF_SYNC_WRITE,
+            SyncAccess::ReadWrite => DMA_BUF_SYNC_READ | DMA_BUF_SYNC_WRITE,
+        }
+    }
+}
+
+/// A dma-buf file descriptor.
+pub struct DmaBuf {
+    fd: OwnedFd,
+}
+
+impl DmaBuf {
+    /// Wrap a dma-buf fd. (From `Device::prime_export`, or another
+    /// process or device.)
+    pub fn new(fd: OwnedFd) -> DmaBuf {
+        DmaBuf { fd }
+    }
+
+    /// Give back the fd.
+    pub fn into_fd(self) -> OwnedFd {
+        self.fd
+    }
+
+    /// Size of the buffer in bytes.
+    pub fn size(&self) -> io::Result<u64> {
+        dma_buf_size(self.fd.as_raw_fd())
+    }
+
+    /// Map the whole buffer into memory.
+    ///
+    /// Writable mappings need the fd to have been exported with
+    /// `PRIME_RDWR`. Not every exporter supports mapping.
+    pub fn map(&self, protection: MapProtection) -> io::Result<DmaBufMap> {
+        let size = self.size()?;
+        let (prot, writable) = match protection {
+            MapProtection::Read => (Protection::Read, false),
+            MapProtection::ReadWrite => (Protection::ReadWrite, true),
+        };
+        let file = File::from(self.fd.try_clone()?);
+        let map = Mmap::open_with_offset(&file, prot, 0, size as usize)?;
+        Ok(DmaBufMap { map, writable })
+    }
+
+    fn sync(&self, flags: u64) -> io::Result<()> {
+        let mut arg = flags;
+        fd_ioctl(self.fd.as_raw_fd(), DMA_BUF_IOCTL_SYNC, &mut arg)
+    }
+
+    /// Start CPU access to a mapping.
+    pub fn sync_start(&self, access: SyncAccess) -> io::Result<()> {
+        self.sync(access.bits())
+    }
+
+    /// End CPU access to a mapping. `access` must match `sync_start`.
+    pub fn sync_end(&self, access: SyncAccess) -> io::Result<()> {
+        self.sync(access.bits() | DMA_BUF_SYNC_END)
+    }
+
+    /// Start CPU access, ending it when the guard is dropped.
+    pub fn access(&self, access: SyncAccess) -> io::Result<SyncGuard<'_>> {
+        self.sync_start(access)?;
+        Ok(SyncGuard { buf: self, access })
+    }
+}
+
+impl AsRawFd for DmaBuf {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl fmt::Debug for DmaBuf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("DmaBuf").field(&self.fd.as_raw_fd()).finish()
+    }
+}
+
+/// Ends CPU access to a dma-buf when dropped. See `DmaBuf::access`.
+#[derive(Debug)]
+pub struct SyncGuard<'a> {
+    buf: &'a DmaBuf,
+    access: SyncAccess,
+}
+
+impl<'a> Drop for SyncGuard<'a> {
+    fn drop(&mut self) {
+        // Nothing sensible to do if this fails.
+        self.buf.sync_end(self.access).ok();
+    }
+}
+
+/// A dma-buf mapped into memory.
+pub struct DmaBufMap {
+    map: Mmap,
+    writable: bool,
+}
+
+impl DmaBufMap {
+    /// The whole mapping.
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { ::std::slice::from_raw_parts(self.map.ptr(), self.map.len()) }
+    }
+
+    /// The whole mapping, or `None` if it was mapped read only.
+    pub fn as_mut_slice(&mut self) -> Option<&mut [u8]> {
+        if self.writable {
+            Some(unsafe { ::std::slice::from_raw_parts_mut(self.map.mut_ptr(), self.map.len()) })
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Debug for DmaBufMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DmaBufMap")
+            .field("len", &self.map.len())
+            .field("writable", &self.writable)
+            .finish()
+    }
+}
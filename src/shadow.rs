@@ -0,0 +1,222 @@
+//! Shadow buffering for dumb buffers.
+//!
+//! Dumb buffer memory is often write-combined (or VRAM across a bus).
+//! Writing it in order is fast, but reading it back, as blending or
+//! read-modify-write does, is extremely slow. Drivers where this
+//! matters report `Capability::DumbPreferShadow`.
+//!
+//! A `ShadowBuf` keeps a copy of the buffer in system memory. Drawing
+//! goes to the copy, and `present` copies the rows that changed into
+//! the real buffer in one sequential pass.
+//!
+//! ```ignore
+//! let mut shadow = ShadowBuf::new(buf)?;
+//! draw::composite(&cursor, &mut shadow.image_mut_rows(y, h)?, x, 0, Alpha::Straight)?;
+//! shadow.present();
+//! ```
+
+use std::io;
+use std::cmp;
+use convert::{Image, ImageMut};
+use mode::{DumbBuf, split_planes_mut};
+use pixels::{PixelFormat, Pixels};
+use Capability;
+
+/// A `DumbBuf` that's drawn to through a copy in system memory, if the
+/// driver prefers it.
+pub struct ShadowBuf {
+    buf: DumbBuf,
+    // None when drawing straight into the buffer.
+    shadow: Option<Vec<u8>>,
+    // Range of rows that have changed since the last present.
+    damage: Option<(u32, u32)>,
+}
+
+impl ShadowBuf {
+    /// Wrap a buffer, shadowing it if the driver reports
+    /// `Capability::DumbPreferShadow`. Kernels that don't know it get
+    /// no shadow.
+    pub fn new(buf: DumbBuf) -> io::Result<ShadowBuf> {
+        let prefer = buf.buffer().device().cached_capability(Capability::DumbPreferShadow);
+        Ok(ShadowBuf::with_shadow(buf, prefer != 0))
+    }
+
+    /// Wrap a buffer, choosing whether to shadow it.
+    ///
+    /// The shadow starts zeroed (copying the buffer would mean reading
+    /// it), and all damaged, so the first `present` clears the buffer.
+    pub fn with_shadow(buf: DumbBuf, shadow: bool) -> ShadowBuf {
+        if !shadow {
+            return ShadowBuf { buf, shadow: None, damage: None };
+        }
+        let len = buf.buffer().bytes();
+        let mut shadow = ShadowBuf { buf, shadow: Some(vec![0; len]), damage: None };
+        shadow.damage_all();
+        shadow
+    }
+
+    /// Is drawing going to a copy in system memory?
+    pub fn is_shadowed(&self) -> bool {
+        self.shadow.is_some()
+    }
+
+    /// The real buffer. Use this to get the frame buffer to show.
+    pub fn dumb_buf(&self) -> &DumbBuf {
+        &self.buf
+    }
+
+    /// Present any outstanding damage and give back the buffer.
+    pub fn into_inner(mut self) -> DumbBuf {
+        self.present();
+        self.buf
+    }
+
+    /// Mark rows `y..y + height` as changed.
+    pub fn damage_rows(&mut self, y: u32, height: u32) {
+        let (_, buf_height) = self.buf.size();
+        let end = cmp::min(y.saturating_add(height), buf_height);
+        if y >= end {
+            return;
+        }
+        self.damage = Some(match self.damage {
+            Some((start, stop)) => (cmp::min(start, y), cmp::max(stop, end)),
+            None => (y, end),
+        });
+    }
+
+    /// Mark the whole buffer as changed.
+    pub fn damage_all(&mut self) {
+        let (_, height) = self.buf.size();
+        self.damage_rows(0, height);
+    }
+
+    /// The image being drawn to. Changes aren't tracked, see
+    /// `damage_rows`.
+    pub fn image(&self) -> io::Result<Image<'_>> {
+        match self.shadow {
+            Some(ref shadow) => {
+                let buffer = self.buf.buffer();
+                let planes = buffer.layout().iter()
+                    .map(|&(offset, pitch)| (&shadow[offset as usize..], pitch as usize))
+                    .collect();
+                let (width, height) = buffer.size();
+                Image::new(buffer.format(), width, height, planes)
+            }
+            None => self.buf.image(),
+        }
+    }
+
+    /// The whole image to draw to. Marks everything as damaged.
+    pub fn image_mut(&mut self) -> io::Result<ImageMut<'_>> {
+        let (_, height) = self.buf.size();
+        self.image_mut_rows(0, height)
+    }
+
+    /// Rows `y..y + height` of the image to draw to. Only these rows
+    /// are marked as damaged.
+    ///
+    /// # Errors
+    ///
+    /// `ErrorKind::InvalidInput` if the rows are outside the buffer,
+    /// or `y` splits subsampled rows.
+    pub fn image_mut_rows(&mut self, y: u32, height: u32) -> io::Result<ImageMut<'_>> {
+        self.damage_rows(y, height);
+        let (width, _) = self.buf.size();
+        let image = match self.shadow {
+            Some(ref mut shadow) => {
+                let buffer = self.buf.buffer();
+                let planes = split_planes_mut(shadow, buffer.layout());
+                let (width, height) = buffer.size();
+                ImageMut::new(buffer.format(), width, height, planes)?
+            }
+            None => self.buf.image_mut()?,
+        };
+        image.into_crop(0, y, width, height)
+    }
+
+    /// A pitch aware view of the pixels to draw to. Marks everything as
+    /// damaged.
+    ///
+    /// Returns `None` if `F` isn't the format of the buffer.
+    pub fn pixels<F: PixelFormat>(&mut self) -> Option<Pixels<'_, F>> {
+        self.damage_all();
+        let buffer = self.buf.buffer();
+        let (width, height) = buffer.size();
+        let pitch = buffer.pitch();
+        match self.shadow {
+            Some(ref mut shadow) if F::FOURCC == buffer.format() => {
+                Pixels::new(shadow, width, height, pitch).ok()
+            }
+            Some(_) => None,
+            None => self.buf.pixels(),
+        }
+    }
+
+    /// Copy changed rows to the real buffer.
+    ///
+    /// Does nothing when not shadowed, drawing already went to the
+    /// buffer.
+    pub fn present(&mut self) {
+        let (start, end) = match self.damage.take() {
+            Some(damage) => damage,
+            None => return,
+        };
+        let shadow = match self.shadow {
+            Some(ref shadow) => shadow,
+            None => return,
+        };
+        let buffer = self.buf.buffer();
+        let info = buffer.format().info().expect("dumb buffers have format info");
+        let (width, height) = buffer.size();
+        let layout = buffer.layout().to_vec();
+        let dst: &mut [u8] = self.buf.as_mut();
+        for (plane, &(offset, pitch)) in layout.iter().enumerate() {
+            let sub = if plane == 0 { 1 } else { info.vsub() };
+            let (_, bh) = info.block_size();
+            let rows = info.plane_height(plane, height).div_ceil(bh) as usize;
+            let first = (start / sub / bh) as usize;
+            let last = cmp::min(end.div_ceil(sub).div_ceil(bh) as usize, rows);
+            let row_bytes = info.min_pitch(plane, width) as usize;
+            let (offset, pitch) = (offset as usize, pitch as usize);
+            for row in first..last {
+                let at = offset + row * pitch;
+                copy_streaming(&mut dst[at..at + row_bytes], &shadow[at..at + row_bytes]);
+            }
+        }
+        store_fence();
+    }
+}
+
+/// Copy without pulling the destination into the cache.
+#[cfg(target_arch = "x86_64")]
+fn copy_streaming(dst: &mut [u8], src: &[u8]) {
+    use std::arch::x86_64::{__m128i, _mm_loadu_si128, _mm_stream_si128};
+
+    // Streaming stores need 16 byte aligned destinations.
+    let head = cmp::min(dst.as_ptr().align_offset(16), dst.len());
+    dst[..head].copy_from_slice(&src[..head]);
+    let blocks = (dst.len() - head) / 16;
+    for i in 0..blocks {
+        let at = head + i * 16;
+        // SSE2 is always there on x86_64, and the destination is aligned.
+        unsafe {
+            let value = _mm_loadu_si128(src[at..].as_ptr() as *const __m128i);
+            _mm_stream_si128(dst[at..].as_mut_ptr() as *mut __m128i, value);
+        }
+    }
+    let tail = head + blocks * 16;
+    dst[tail..].copy_from_slice(&src[tail..]);
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn copy_streaming(dst: &mut [u8], src: &[u8]) {
+    dst.copy_from_slice(src);
+}
+
+/// Make streaming stores visible before the buffer is used.
+fn store_fence() {
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        ::std::arch::x86_64::_mm_sfence();
+    }
+}
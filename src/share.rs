@@ -0,0 +1,247 @@
+//! Showing a buffer rendered on one device on another.
+//!
+//! On hybrid graphics and USB docks the GPU that renders isn't the one
+//! driving the display. `CrossDeviceFb` makes a buffer from the render
+//! device into a frame buffer on the display device.
+//!
+//! It first tries to import the buffer with PRIME, so the display
+//! device scans it out directly. If the display device can't use the
+//! format or modifier, or can't import it at all, the buffer is copied
+//! into a linear dumb buffer on the display device instead. Call
+//! `update` after each frame is rendered to refresh the copy.
+//!
+//! ```ignore
+//! let mut fb = CrossDeviceFb::from_dumb(&render_buf, &display)?;
+//! // Render into render_buf...
+//! fb.update()?;
+//! master.page_flip(crtc, fb.fb().id(), PAGE_FLIP_EVENT, 0)?;
+//! ```
+
+use std::{io, fmt};
+use std::os::unix::io::OwnedFd;
+use convert::{self, Image, ImageMut, Options};
+use fourcc::FourCC;
+use gem::GemObject;
+use modifier::Modifier;
+use mode::{DumbBuf, DumbBufOptions, DumbBuffer, Fb, MapProtection, OwnedFb, Plane};
+use prime::{DmaBuf, DmaBufMap, SyncAccess, PRIME_CLOEXEC};
+use {Capability, ClientCapability, Device};
+
+/// The layout of a buffer to share.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BufferLayout {
+    pub width: u32,
+    pub height: u32,
+    pub format: FourCC,
+    /// Use `Modifier::LINEAR` for plain buffers. `Modifier::INVALID`
+    /// means the driver chose the layout (often tiled): the buffer can
+    /// be imported, but not copied.
+    pub modifier: Modifier,
+    /// (offset, pitch) of each plane, in bytes.
+    pub planes: Vec<(u32, u32)>,
+}
+
+impl BufferLayout {
+    /// The layout of a dumb buffer.
+    pub fn of_dumb(buffer: &DumbBuffer) -> BufferLayout {
+        let (width, height) = buffer.size();
+        BufferLayout {
+            width, height,
+            format: buffer.format(),
+            modifier: Modifier::LINEAR,
+            planes: buffer.layout().to_vec(),
+        }
+    }
+
+    fn is_linear(&self) -> bool {
+        self.modifier == Modifier::LINEAR
+    }
+
+    /// The modifier to give the frame buffer, if any.
+    fn fb_modifier(&self) -> Option<Modifier> {
+        // Linear is what a frame buffer without a modifier means anyway,
+        // and not every driver takes modifiers. INVALID means no modifier.
+        if self.is_linear() || self.modifier == Modifier::INVALID {
+            None
+        } else {
+            Some(self.modifier)
+        }
+    }
+}
+
+enum Kind {
+    /// Scanned out straight from the source buffer.
+    Imported {
+        fb: OwnedFb,
+        // Keeps the imported handle open.
+        _gem: GemObject,
+    },
+    /// Copied into a buffer on the target.
+    Copied {
+        buf: DumbBuf,
+        dma_buf: DmaBuf,
+        map: DmaBufMap,
+        layout: BufferLayout,
+    },
+}
+
+/// A frame buffer on one device showing a buffer from another.
+pub struct CrossDeviceFb {
+    kind: Kind,
+}
+
+impl CrossDeviceFb {
+    /// Make `source`, a buffer on another device laid out as `layout`,
+    /// displayable on `target`.
+    ///
+    /// # Errors
+    ///
+    /// `ErrorKind::Unsupported` if zero-copy fails and the buffer
+    /// can't be copied either: it's tiled (or its layout is implicit),
+    /// or its format can't be converted to one the target supports.
+    ///
+    /// Zero-copy is only tried if `target` has
+    /// `ClientCapability::UniversalPlanes` turned on, so its primary
+    /// planes can be checked. Without it the buffer is copied.
+    pub fn new(source: &GemObject, layout: &BufferLayout, target: &Device)
+               -> io::Result<CrossDeviceFb>
+    {
+        let fd = source.prime_export(PRIME_CLOEXEC)?;
+        if supports_scanout(target, layout.format, layout.modifier) == Some(true) {
+            if let Ok(kind) = import(&fd, layout, target) {
+                return Ok(CrossDeviceFb { kind });
+            }
+        }
+        let kind = copy(DmaBuf::new(fd), layout, target)?;
+        let mut fb = CrossDeviceFb { kind };
+        fb.update()?;
+        Ok(fb)
+    }
+
+    /// Make a dumb buffer displayable on `target`.
+    pub fn from_dumb(buffer: &DumbBuffer, target: &Device) -> io::Result<CrossDeviceFb> {
+        CrossDeviceFb::new(&buffer.gem_object()?, &BufferLayout::of_dumb(buffer), target)
+    }
+
+    /// The frame buffer on the target device.
+    pub fn fb(&self) -> &Fb {
+        match self.kind {
+            Kind::Imported { ref fb, .. } => fb.fb(),
+            Kind::Copied { ref buf, .. } => buf.fb(),
+        }
+    }
+
+    /// Is the source buffer scanned out directly? If not, it's copied
+    /// by `update`.
+    pub fn is_zero_copy(&self) -> bool {
+        match self.kind {
+            Kind::Imported { .. } => true,
+            Kind::Copied { .. } => false,
+        }
+    }
+
+    /// Copy the source buffer to the target again, after rendering to
+    /// it. Does nothing when zero-copy.
+    pub fn update(&mut self) -> io::Result<()> {
+        let (buf, dma_buf, map, layout) = match self.kind {
+            Kind::Imported { .. } => return Ok(()),
+            Kind::Copied { ref mut buf, ref dma_buf, ref map, ref layout } => {
+                (buf, dma_buf, map, layout)
+            }
+        };
+        let _access = dma_buf.access(SyncAccess::Read)?;
+        let data = map.as_slice();
+        let mut planes = Vec::with_capacity(layout.planes.len());
+        for &(offset, pitch) in &layout.planes {
+            let plane = data.get(offset as usize..).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "Plane is outside the buffer")
+            })?;
+            planes.push((plane, pitch as usize));
+        }
+        let src = Image::new(layout.format, layout.width, layout.height, planes)?;
+        let mut dst: ImageMut = buf.image_mut()?;
+        convert::convert(&src, &mut dst, &Options::default())
+    }
+}
+
+impl fmt::Debug for CrossDeviceFb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CrossDeviceFb")
+            .field("fb", self.fb())
+            .field("zero_copy", &self.is_zero_copy())
+            .finish()
+    }
+}
+
+/// Could some plane of `dev` scan out this format and modifier? `None`
+/// if we can't tell: the planes couldn't be read, or without
+/// `ClientCapability::UniversalPlanes` (which isn't ours to turn on)
+/// only overlay planes are listed.
+fn supports_scanout(dev: &Device, format: FourCC, modifier: Modifier) -> Option<bool> {
+    // With INVALID the driver picks the layout, so no modifiers needed.
+    if modifier != Modifier::INVALID && modifier != Modifier::LINEAR
+        && dev.cached_capability(Capability::Addfb2Modifiers) == 0 {
+        return Some(false);
+    }
+    if !dev.has_client_capability(ClientCapability::UniversalPlanes) {
+        return None;
+    }
+    for id in Plane::get_ids(dev).ok()? {
+        if dev.get(id).ok()?.supports(dev, format, modifier).ok()? {
+            return Some(true);
+        }
+    }
+    Some(false)
+}
+
+fn import(fd: &OwnedFd, layout: &BufferLayout, target: &Device)
+          -> io::Result<Kind>
+{
+    let gem = target.prime_import(fd)?;
+    let mut handles = [0; 4];
+    let mut pitches = [0; 4];
+    let mut offsets = [0; 4];
+    for (plane, &(offset, pitch)) in layout.planes.iter().enumerate().take(4) {
+        handles[plane] = gem.handle();
+        pitches[plane] = pitch;
+        offsets[plane] = offset;
+    }
+    let id = Fb::add_fb2(target, layout.width, layout.height, layout.format,
+                         handles, pitches, offsets, layout.fb_modifier(), 0)?;
+    // Don't leak the frame buffer if we fail to get it.
+    let fb = target.get(id).inspect_err(|_| {
+        Fb::rm(target, id).ok();
+    })?;
+    Ok(Kind::Imported { fb: OwnedFb::new(target, fb)?, _gem: gem })
+}
+
+fn copy(dma_buf: DmaBuf, layout: &BufferLayout, target: &Device) -> io::Result<Kind> {
+    let unsupported = |msg| io::Error::new(io::ErrorKind::Unsupported, msg);
+    if layout.modifier == Modifier::INVALID {
+        return Err(unsupported("Buffers with an implicit layout can't be copied"));
+    }
+    if !layout.is_linear() {
+        return Err(unsupported("Tiled buffers can't be copied"));
+    }
+    if !convert::is_supported(layout.format) {
+        return Err(unsupported("Buffer format can't be copied"));
+    }
+    // Keep the format if the target can take it, otherwise convert.
+    let mut buf = None;
+    for &format in &[layout.format, FourCC::XRGB8888] {
+        // If we can't tell, try it.
+        if supports_scanout(target, format, Modifier::LINEAR) != Some(false) {
+            let created = DumbBufOptions::new(target)
+                .width(layout.width).height(layout.height)
+                .format(format)
+                .create();
+            if let Ok(created) = created {
+                buf = Some(created);
+                break;
+            }
+        }
+    }
+    let buf = buf.ok_or_else(|| unsupported("No format the target can show"))?;
+    let map = dma_buf.map(MapProtection::Read)?;
+    Ok(Kind::Copied { buf, dma_buf, map, layout: layout.clone() })
+}
@@ -0,0 +1,229 @@
+//! A chain of dumb buffers presented with page flips.
+//!
+//! The usual loop is:
+//!
+//! ```ignore
+//! let mut chain = Swapchain::new(&dev, crtc_id, width, height, FourCC::XRGB8888, 2)?;
+//! let first = chain.next_buffer().unwrap().index();
+//! chain.set_mode(&master, first, &[connector_id], &mode)?;
+//!
+//! loop {
+//!     if let Some(mut back) = chain.next_buffer() {
+//!         // back.age() says how old the contents are.
+//!         draw(&mut back);
+//!         let index = back.index();
+//!         chain.queue(&master, index)?;
+//!     }
+//!     let event = master.read_event()?;
+//!     chain.handle_event(&event);
+//! }
+//! ```
+
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use fourcc::FourCC;
+use mode::{Connector, Crtc, DumbBuf, DumbBufOptions, Id, ModeInfo, PAGE_FLIP_EVENT};
+use {Device, Event, Master};
+
+// Tells the events of different swapchains apart.
+static NEXT_CHAIN: AtomicUsize = AtomicUsize::new(1);
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum State {
+    /// Can be drawn to.
+    Free,
+    /// Handed out by `next_buffer`.
+    Acquired,
+    /// Waiting for a page flip.
+    Queued,
+    /// On screen.
+    Front,
+}
+
+struct Slot {
+    buf: DumbBuf,
+    state: State,
+    // Frame number this buffer was last queued as. 0 if never.
+    frame: u64,
+}
+
+/// A set of buffers shown in turn on a CRTC.
+pub struct Swapchain {
+    crtc: Id<Crtc>,
+    chain: u64,
+    slots: Vec<Slot>,
+    frames: u64,
+}
+
+impl Swapchain {
+    /// Create `count` buffers for a CRTC. Use 2 for double buffering
+    /// and 3 for triple buffering.
+    pub fn new(dev: &Device, crtc: Id<Crtc>, width: u32, height: u32,
+               format: FourCC, count: usize)
+               -> io::Result<Swapchain>
+    {
+        let mut slots = Vec::with_capacity(count);
+        for _ in 0..count {
+            let buf = DumbBufOptions::new(dev)
+                .width(width).height(height)
+                .format(format)
+                .create()?;
+            slots.push(Slot { buf, state: State::Free, frame: 0 });
+        }
+        Ok(Swapchain {
+            crtc,
+            chain: NEXT_CHAIN.fetch_add(1, Ordering::Relaxed) as u64,
+            slots,
+            frames: 0,
+        })
+    }
+
+    /// The CRTC buffers are shown on.
+    pub fn crtc(&self) -> Id<Crtc> { self.crtc }
+
+    /// Number of buffers.
+    pub fn len(&self) -> usize { self.slots.len() }
+
+    pub fn is_empty(&self) -> bool { self.slots.is_empty() }
+
+    /// A buffer, by index.
+    pub fn buffer(&self, index: usize) -> &DumbBuf {
+        &self.slots[index].buf
+    }
+
+    /// Is a page flip waiting to happen?
+    pub fn is_flip_pending(&self) -> bool {
+        self.slots.iter().any(|slot| slot.state == State::Queued)
+    }
+
+    /// Get a buffer to draw the next frame in, or `None` if they're all
+    /// in use. (Wait for a page flip event and try again.)
+    ///
+    /// The buffer stays acquired until it's queued.
+    pub fn next_buffer(&mut self) -> Option<BackBuffer<'_>> {
+        // The least recently shown buffer.
+        let index = (0..self.slots.len())
+            .filter(|&i| self.slots[i].state == State::Free)
+            .min_by_key(|&i| self.slots[i].frame)?;
+        let frames = self.frames;
+        let slot = &mut self.slots[index];
+        slot.state = State::Acquired;
+        let age = if slot.frame == 0 { 0 } else { (frames - slot.frame + 1) as u32 };
+        Some(BackBuffer { index, age, buf: &mut slot.buf })
+    }
+
+    /// Give an acquired buffer back without showing it.
+    pub fn release(&mut self, index: usize) {
+        if self.slots[index].state == State::Acquired {
+            self.slots[index].state = State::Free;
+        }
+    }
+
+    fn tag(&self, index: usize) -> u64 {
+        (self.chain << 32) | index as u64
+    }
+
+    fn check_acquired(&self, index: usize) -> io::Result<()> {
+        if self.slots.get(index).map(|slot| slot.state) == Some(State::Acquired) {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::InvalidInput, "Buffer was not acquired"))
+        }
+    }
+
+    /// Set the mode of the CRTC, showing an acquired buffer straight
+    /// away. Needed before the first `queue`.
+    pub fn set_mode(&mut self, master: &Master, index: usize,
+                    connectors: &[Id<Connector>], mode: &ModeInfo)
+                    -> io::Result<()>
+    {
+        self.check_acquired(index)?;
+        let fb = self.slots[index].buf.fb().id();
+        master.set_crtc(self.crtc, Some(fb), 0, 0, connectors, Some(mode))?;
+        self.frames += 1;
+        let frames = self.frames;
+        for (i, slot) in self.slots.iter_mut().enumerate() {
+            if i == index {
+                slot.state = State::Front;
+                slot.frame = frames;
+            } else if slot.state != State::Acquired {
+                slot.state = State::Free;
+            }
+        }
+        Ok(())
+    }
+
+    /// Queue an acquired buffer to be shown at the next vblank.
+    ///
+    /// # Errors
+    ///
+    /// `ErrorKind::WouldBlock` if a flip is already pending. Only one
+    /// can be pending at a time, wait for its event.
+    pub fn queue(&mut self, master: &Master, index: usize) -> io::Result<()> {
+        self.check_acquired(index)?;
+        if self.is_flip_pending() {
+            return Err(io::Error::new(io::ErrorKind::WouldBlock,
+                                      "A page flip is already pending"));
+        }
+        let fb = self.slots[index].buf.fb().id();
+        master.page_flip(self.crtc, fb, PAGE_FLIP_EVENT, self.tag(index))?;
+        self.frames += 1;
+        self.slots[index].state = State::Queued;
+        self.slots[index].frame = self.frames;
+        Ok(())
+    }
+
+    /// Handle an event from `Device::read_event`.
+    ///
+    /// Returns `true` if it was the page flip of one of this chain's
+    /// buffers: that buffer is now on screen, and the one it replaced
+    /// is free.
+    pub fn handle_event(&mut self, event: &Event) -> bool {
+        let user = match *event {
+            Event::PageFlip { user, .. } => user,
+            _ => return false,
+        };
+        let index = (user & 0xffff_ffff) as usize;
+        if user >> 32 != self.chain || index >= self.slots.len()
+            || self.slots[index].state != State::Queued {
+            return false;
+        }
+        for slot in self.slots.iter_mut() {
+            if slot.state == State::Front {
+                slot.state = State::Free;
+            }
+        }
+        self.slots[index].state = State::Front;
+        true
+    }
+}
+
+/// A buffer to draw the next frame in. Derefs to the `DumbBuf`.
+pub struct BackBuffer<'a> {
+    index: usize,
+    age: u32,
+    buf: &'a mut DumbBuf,
+}
+
+impl<'a> BackBuffer<'a> {
+    /// Index of the buffer in the chain. Pass it to `queue`.
+    pub fn index(&self) -> usize { self.index }
+
+    /// How many frames old the contents are.
+    ///
+    /// 0 means the contents are undefined (never shown) and everything
+    /// needs drawing. 1 means they're the frame on screen, 2 the frame
+    /// before that, and so on: redraw everything that changed in the
+    /// last `age` frames.
+    pub fn age(&self) -> u32 { self.age }
+}
+
+impl<'a> Deref for BackBuffer<'a> {
+    type Target = DumbBuf;
+    fn deref(&self) -> &DumbBuf { self.buf }
+}
+
+impl<'a> DerefMut for BackBuffer<'a> {
+    fn deref_mut(&mut self) -> &mut DumbBuf { self.buf }
+}
@@ -0,0 +1,231 @@
+//! sync_file fds: fences shared between drivers and processes.
+//!
+//! A sync_file holds one or more fences, and becomes readable (in the
+//! `poll` sense) once they've all signaled. They come out of atomic
+//! commits as out-fences (`AtomicRequest::out_fence`), go into them as
+//! in-fences, and can be converted to and from sync objects.
+
+use std::{io, fmt, str};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::time::Duration;
+use libc;
+use ioctl_vals::*;
+use fd_ioctl;
+
+/// struct sync_merge_data
+#[repr(C)]
+struct MergeData {
+    name: [u8; 32],
+    fd2: i32,
+    fence: i32,
+    flags: u32,
+    pad: u32,
+}
+
+/// struct sync_file_info
+#[repr(C)]
+struct FileInfo {
+    name: [u8; 32],
+    status: i32,
+    flags: u32,
+    num_fences: u32,
+    pad: u32,
+    sync_fence_info: u64,
+}
+
+/// struct sync_fence_info
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct FenceInfoRaw {
+    obj_name: [u8; 32],
+    driver_name: [u8; 32],
+    status: i32,
+    flags: u32,
+    timestamp_ns: u64,
+}
+
+/// State of a fence.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum FenceStatus {
+    /// Not signaled yet.
+    Active,
+    Signaled,
+    /// Signaled with an error. (A negative errno.)
+    Error(i32),
+}
+
+impl FenceStatus {
+    fn from_raw(status: i32) -> FenceStatus {
+        match status {
+            0 => FenceStatus::Active,
+            1 => FenceStatus::Signaled,
+            err => FenceStatus::Error(err),
+        }
+    }
+}
+
+/// A fence in a sync_file. See `SyncFile::info`.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct FenceInfo {
+    /// Name of the timeline the fence is on.
+    pub obj_name: String,
+    /// Name of the driver that will signal it.
+    pub driver_name: String,
+    pub status: FenceStatus,
+    /// When it signaled, on CLOCK_MONOTONIC. `None` if it hasn't.
+    pub timestamp: Option<Duration>,
+}
+
+/// Information about a sync_file. See `SyncFile::info`.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct SyncFileInfo {
+    pub name: String,
+    /// Combined status of all the fences.
+    pub status: FenceStatus,
+    pub fences: Vec<FenceInfo>,
+}
+
+fn c_str(bytes: &[u8]) -> String {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len]).into_owned()
+}
+
+/// A sync_file fd.
+pub struct SyncFile {
+    fd: OwnedFd,
+}
+
+impl SyncFile {
+    /// Wrap a sync_file fd.
+    pub fn new(fd: OwnedFd) -> SyncFile {
+        SyncFile { fd }
+    }
+
+    /// Give back the fd.
+    pub fn into_fd(self) -> OwnedFd {
+        self.fd
+    }
+
+    /// Have all the fences signaled? Doesn't block.
+    pub fn poll(&self) -> io::Result<bool> {
+        self.poll_timeout(0)
+    }
+
+    /// Wait for all the fences to signal. A `timeout` of `None` waits
+    /// forever.
+    ///
+    /// # Errors
+    ///
+    /// `ErrorKind::TimedOut` if the timeout passed first.
+    pub fn wait(&self, timeout: Option<Duration>) -> io::Result<()> {
+        let ms = match timeout {
+            // Round up, so short waits don't become polls.
+            Some(timeout) => {
+                let ms = timeout.as_nanos().div_ceil(1_000_000);
+                if ms > libc::c_int::MAX as u128 { -1 } else { ms as libc::c_int }
+            }
+            None => -1,
+        };
+        if self.poll_timeout(ms)? {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::TimedOut, "Timed out waiting for fence"))
+        }
+    }
+
+    fn poll_timeout(&self, ms: libc::c_int) -> io::Result<bool> {
+        let mut pollfd = libc::pollfd { fd: self.fd.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+        loop {
+            let ret = unsafe { libc::poll(&mut pollfd, 1, ms) };
+            if ret < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(err);
+            }
+            if pollfd.revents & (libc::POLLERR | libc::POLLNVAL) != 0 {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "Not a sync_file"));
+            }
+            return Ok(ret > 0);
+        }
+    }
+
+    /// A new sync_file with the fences of both, which signals once all
+    /// of them have. `name` is truncated to 31 bytes.
+    pub fn merge(&self, other: &SyncFile, name: &str) -> io::Result<SyncFile> {
+        let mut data = MergeData {
+            name: [0; 32],
+            fd2: other.fd.as_raw_fd(),
+            fence: -1,
+            flags: 0,
+            pad: 0,
+        };
+        let len = name.len().min(31);
+        data.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+        fd_ioctl(self.fd.as_raw_fd(), SYNC_IOC_MERGE, &mut data)?;
+        Ok(SyncFile { fd: unsafe { OwnedFd::from_raw_fd(data.fence) } })
+    }
+
+    /// Name, status and fences of the sync_file.
+    pub fn info(&self) -> io::Result<SyncFileInfo> {
+        let mut info = FileInfo {
+            name: [0; 32],
+            status: 0,
+            flags: 0,
+            num_fences: 0,
+            pad: 0,
+            sync_fence_info: 0,
+        };
+        // The first call only fills in the number of fences.
+        fd_ioctl(self.fd.as_raw_fd(), SYNC_IOC_FILE_INFO, &mut info)?;
+        let blank = FenceInfoRaw {
+            obj_name: [0; 32],
+            driver_name: [0; 32],
+            status: 0,
+            flags: 0,
+            timestamp_ns: 0,
+        };
+        let mut fences = vec![blank; info.num_fences as usize];
+        if !fences.is_empty() {
+            info.sync_fence_info = fences.as_mut_ptr() as u64;
+            fd_ioctl(self.fd.as_raw_fd(), SYNC_IOC_FILE_INFO, &mut info)?;
+            fences.truncate(info.num_fences as usize);
+        }
+        Ok(SyncFileInfo {
+            name: c_str(&info.name),
+            status: FenceStatus::from_raw(info.status),
+            fences: fences.iter().map(|fence| {
+                let status = FenceStatus::from_raw(fence.status);
+                FenceInfo {
+                    obj_name: c_str(&fence.obj_name),
+                    driver_name: c_str(&fence.driver_name),
+                    status,
+                    timestamp: if status == FenceStatus::Active {
+                        None
+                    } else {
+                        Some(Duration::from_nanos(fence.timestamp_ns))
+                    },
+                }
+            }).collect(),
+        })
+    }
+}
+
+impl AsRawFd for SyncFile {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl From<OwnedFd> for SyncFile {
+    fn from(fd: OwnedFd) -> SyncFile {
+        SyncFile::new(fd)
+    }
+}
+
+impl fmt::Debug for SyncFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("SyncFile").field(&self.fd.as_raw_fd()).finish()
+    }
+}
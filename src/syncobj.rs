@@ -0,0 +1,405 @@
+//! DRM sync objects, for explicit synchronisation.
+//!
+//! A sync object holds a fence: something that signals once the GPU is
+//! done with some work. Binary sync objects hold a single fence, which
+//! can be replaced or reset. Timeline sync objects
+//! (`Capability::SyncObjTimeline`) hold a sequence of points, each
+//! signaled in order.
+//!
+//! Sync objects can be shared with other processes as fds, or
+//! converted to and from sync_file fds, which is how fences are passed
+//! around elsewhere (atomic commits, Vulkan, ...).
+//!
+//! Check `Capability::SyncObj` before using any of this.
+
+use std::{io, fmt};
+use std::convert::TryFrom;
+use std::os::raw::c_ulong;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::time::Duration;
+use libc;
+use ioctl_vals::*;
+use event::Clock;
+use {ffi, Device, DrmIoctl};
+
+const DRM_SYNCOBJ_CREATE_SIGNALED: u32 = 1 << 0;
+
+const DRM_SYNCOBJ_FD_TO_HANDLE_FLAGS_IMPORT_SYNC_FILE: u32 = 1 << 0;
+const DRM_SYNCOBJ_FD_TO_HANDLE_FLAGS_TIMELINE: u32 = 1 << 1;
+const DRM_SYNCOBJ_HANDLE_TO_FD_FLAGS_EXPORT_SYNC_FILE: u32 = 1 << 0;
+const DRM_SYNCOBJ_HANDLE_TO_FD_FLAGS_TIMELINE: u32 = 1 << 1;
+
+const DRM_SYNCOBJ_WAIT_FLAGS_WAIT_ALL: u32 = 1 << 0;
+
+const DRM_SYNCOBJ_QUERY_FLAGS_LAST_SUBMITTED: u32 = 1 << 0;
+
+bitflags! {
+    /// Flags for waiting on sync objects.
+    pub flags SyncObjWaitFlags: u32 {
+        /// Wait for a fence to be added, rather than failing with
+        /// `EINVAL` if there isn't one yet.
+        const SYNCOBJ_WAIT_FOR_SUBMIT = (1<<1),
+        /// Only wait for a fence to be added, not for it to signal.
+        const SYNCOBJ_WAIT_AVAILABLE  = (1<<2),
+    }
+}
+
+/// Wait for all of the sync objects, or just the first.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum WaitMode {
+    All,
+    First,
+}
+
+impl DrmIoctl for ffi::syncobj_create {
+    fn request() -> c_ulong { DRM_IOCTL_SYNCOBJ_CREATE }
+}
+
+impl DrmIoctl for ffi::syncobj_destroy {
+    fn request() -> c_ulong { DRM_IOCTL_SYNCOBJ_DESTROY }
+}
+
+impl DrmIoctl for ffi::syncobj_wait {
+    fn request() -> c_ulong { DRM_IOCTL_SYNCOBJ_WAIT }
+}
+
+impl DrmIoctl for ffi::syncobj_timeline_wait {
+    fn request() -> c_ulong { DRM_IOCTL_SYNCOBJ_TIMELINE_WAIT }
+}
+
+impl DrmIoctl for ffi::syncobj_transfer {
+    fn request() -> c_ulong { DRM_IOCTL_SYNCOBJ_TRANSFER }
+}
+
+// These structs are each used by two ioctls, so need their own types.
+#[repr(C)]
+#[derive(Debug)]
+struct HandleToFd(ffi::syncobj_handle);
+impl DrmIoctl for HandleToFd {
+    fn request() -> c_ulong { DRM_IOCTL_SYNCOBJ_HANDLE_TO_FD }
+}
+
+#[repr(C)]
+#[derive(Debug)]
+struct FdToHandle(ffi::syncobj_handle);
+impl DrmIoctl for FdToHandle {
+    fn request() -> c_ulong { DRM_IOCTL_SYNCOBJ_FD_TO_HANDLE }
+}
+
+#[repr(C)]
+#[derive(Debug)]
+struct Reset(ffi::syncobj_array);
+impl DrmIoctl for Reset {
+    fn request() -> c_ulong { DRM_IOCTL_SYNCOBJ_RESET }
+}
+
+#[repr(C)]
+#[derive(Debug)]
+struct Signal(ffi::syncobj_array);
+impl DrmIoctl for Signal {
+    fn request() -> c_ulong { DRM_IOCTL_SYNCOBJ_SIGNAL }
+}
+
+#[repr(C)]
+#[derive(Debug)]
+struct Query(ffi::syncobj_timeline_array);
+impl DrmIoctl for Query {
+    fn request() -> c_ulong { DRM_IOCTL_SYNCOBJ_QUERY }
+}
+
+#[repr(C)]
+#[derive(Debug)]
+struct TimelineSignal(ffi::syncobj_timeline_array);
+impl DrmIoctl for TimelineSignal {
+    fn request() -> c_ulong { DRM_IOCTL_SYNCOBJ_TIMELINE_SIGNAL }
+}
+
+/// A sync object. Destroyed on drop.
+pub struct SyncObj {
+    handle: u32,
+    dev: Device,
+}
+
+impl SyncObj {
+    /// Create a sync object. If `signaled`, it starts out holding an
+    /// already signaled fence, otherwise it's empty.
+    pub fn create(dev: &Device, signaled: bool) -> io::Result<SyncObj> {
+        let dev = dev.try_clone()?;
+        let mut create = ffi::syncobj_create {
+            flags: if signaled { DRM_SYNCOBJ_CREATE_SIGNALED } else { 0 },
+            ..Default::default()
+        };
+        dev.ioctl(&mut create)?;
+        Ok(SyncObj { handle: create.handle, dev })
+    }
+
+    /// Import a sync object shared with `export`.
+    pub fn import<F: AsRawFd>(dev: &Device, fd: &F) -> io::Result<SyncObj> {
+        let dev = dev.try_clone()?;
+        let mut import = FdToHandle(ffi::syncobj_handle {
+            fd: fd.as_raw_fd(),
+            ..Default::default()
+        });
+        dev.ioctl(&mut import)?;
+        Ok(SyncObj { handle: import.0.handle, dev })
+    }
+
+    /// The handle. It's only valid as long as this object is alive.
+    pub fn handle(&self) -> u32 { self.handle }
+
+    /// Share the sync object itself as an fd, to be imported with
+    /// `import`.
+    pub fn export(&self) -> io::Result<OwnedFd> {
+        let mut export = HandleToFd(ffi::syncobj_handle {
+            handle: self.handle,
+            fd: -1,
+            ..Default::default()
+        });
+        self.dev.ioctl(&mut export)?;
+        Ok(unsafe { OwnedFd::from_raw_fd(export.0.fd) })
+    }
+
+    /// Export the fence at `point` as a sync_file. Use point 0 for
+    /// binary sync objects.
+    pub fn export_sync_file(&self, point: u64) -> io::Result<OwnedFd> {
+        let mut flags = DRM_SYNCOBJ_HANDLE_TO_FD_FLAGS_EXPORT_SYNC_FILE;
+        if point != 0 {
+            flags |= DRM_SYNCOBJ_HANDLE_TO_FD_FLAGS_TIMELINE;
+        }
+        let mut export = HandleToFd(ffi::syncobj_handle {
+            handle: self.handle,
+            flags,
+            fd: -1,
+            point,
+            ..Default::default()
+        });
+        self.dev.ioctl(&mut export)?;
+        Ok(unsafe { OwnedFd::from_raw_fd(export.0.fd) })
+    }
+
+    /// Replace the fence at `point` with the one in a sync_file. Use
+    /// point 0 for binary sync objects.
+    pub fn import_sync_file<F: AsRawFd>(&self, fd: &F, point: u64) -> io::Result<()> {
+        let mut flags = DRM_SYNCOBJ_FD_TO_HANDLE_FLAGS_IMPORT_SYNC_FILE;
+        if point != 0 {
+            flags |= DRM_SYNCOBJ_FD_TO_HANDLE_FLAGS_TIMELINE;
+        }
+        let mut import = FdToHandle(ffi::syncobj_handle {
+            handle: self.handle,
+            flags,
+            fd: fd.as_raw_fd(),
+            point,
+            ..Default::default()
+        });
+        self.dev.ioctl(&mut import)
+    }
+
+    /// Wait for the fence to signal. See `wait`.
+    pub fn wait(&self, flags: SyncObjWaitFlags, timeout: Option<Duration>) -> io::Result<()> {
+        wait(&[self], WaitMode::All, flags, timeout).map(|_| ())
+    }
+
+    /// Wait for a timeline point to signal. See `wait_points`.
+    pub fn wait_point(&self, point: u64, flags: SyncObjWaitFlags, timeout: Option<Duration>)
+                      -> io::Result<()>
+    {
+        wait_points(&[(self, point)], WaitMode::All, flags, timeout).map(|_| ())
+    }
+
+    /// Remove the fence, making the sync object unsignaled.
+    pub fn reset(&self) -> io::Result<()> {
+        reset(&[self])
+    }
+
+    /// Replace the fence with a signaled one.
+    pub fn signal(&self) -> io::Result<()> {
+        signal(&[self])
+    }
+
+    /// Signal a timeline point.
+    pub fn signal_point(&self, point: u64) -> io::Result<()> {
+        signal_points(&[(self, point)])
+    }
+
+    /// The last signaled point of a timeline.
+    pub fn query(&self) -> io::Result<u64> {
+        Ok(query(&[self], false)?[0])
+    }
+
+    /// The last point of a timeline that has had work submitted, signaled
+    /// or not.
+    pub fn query_last_submitted(&self) -> io::Result<u64> {
+        Ok(query(&[self], true)?[0])
+    }
+
+    /// Copy the fence at `src_point` of this sync object to `dst_point`
+    /// of `dst`. Point 0 means the fence of a binary sync object.
+    ///
+    /// Useful for converting between binary and timeline sync objects.
+    pub fn transfer(&self, src_point: u64, dst: &SyncObj, dst_point: u64) -> io::Result<()> {
+        let mut transfer = ffi::syncobj_transfer {
+            src_handle: self.handle,
+            dst_handle: dst.handle,
+            src_point,
+            dst_point,
+            ..Default::default()
+        };
+        self.dev.ioctl(&mut transfer)
+    }
+}
+
+impl fmt::Debug for SyncObj {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("SyncObj").field(&self.handle).finish()
+    }
+}
+
+impl Drop for SyncObj {
+    fn drop(&mut self) {
+        // Nothing sensible to do if this fails.
+        let mut destroy = ffi::syncobj_destroy { handle: self.handle, ..Default::default() };
+        self.dev.ioctl(&mut destroy).ok();
+    }
+}
+
+/// The device of a non-empty set of sync objects.
+///
+/// The sync objects must all belong to the same device.
+fn device<'a>(syncobjs: &[&'a SyncObj]) -> io::Result<&'a Device> {
+    match syncobjs.first() {
+        Some(syncobj) => Ok(&syncobj.dev),
+        None => Err(io::Error::new(io::ErrorKind::InvalidInput, "No sync objects")),
+    }
+}
+
+fn handles(syncobjs: &[&SyncObj]) -> Vec<u32> {
+    syncobjs.iter().map(|syncobj| syncobj.handle).collect()
+}
+
+/// The absolute CLOCK_MONOTONIC deadline the kernel wants.
+fn deadline(timeout: Option<Duration>) -> i64 {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return i64::MAX,
+    };
+    let deadline = Clock::Monotonic.now().checked_add(timeout).map_or(u128::MAX, |d| d.as_nanos());
+    i64::try_from(deadline).unwrap_or(i64::MAX)
+}
+
+fn wait_result(result: io::Result<()>, first_signaled: u32) -> io::Result<usize> {
+    match result {
+        Ok(()) => Ok(first_signaled as usize),
+        Err(ref e) if e.raw_os_error() == Some(libc::ETIME) => {
+            Err(io::Error::new(io::ErrorKind::TimedOut, "Timed out waiting for sync objects"))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Wait for sync objects to signal.
+///
+/// With `WaitMode::First`, returns the index of the first one that
+/// signaled. A `timeout` of `None` waits forever, and `Some(0)` polls.
+///
+/// # Errors
+///
+/// `ErrorKind::TimedOut` if the timeout passed. `EINVAL` if a sync
+/// object has no fence, unless waiting with `SYNCOBJ_WAIT_FOR_SUBMIT`.
+pub fn wait(syncobjs: &[&SyncObj], mode: WaitMode, flags: SyncObjWaitFlags,
+            timeout: Option<Duration>) -> io::Result<usize>
+{
+    let dev = device(syncobjs)?;
+    let handles = handles(syncobjs);
+    let mut flags = flags.bits();
+    if mode == WaitMode::All {
+        flags |= DRM_SYNCOBJ_WAIT_FLAGS_WAIT_ALL;
+    }
+    let mut wait = ffi::syncobj_wait {
+        handles: handles.as_ptr() as u64,
+        timeout_nsec: deadline(timeout),
+        count_handles: handles.len() as u32,
+        flags,
+        ..Default::default()
+    };
+    let result = dev.ioctl(&mut wait);
+    wait_result(result, wait.first_signaled)
+}
+
+/// Wait for timeline points to signal. See `wait`.
+pub fn wait_points(points: &[(&SyncObj, u64)], mode: WaitMode, flags: SyncObjWaitFlags,
+                   timeout: Option<Duration>) -> io::Result<usize>
+{
+    let syncobjs: Vec<_> = points.iter().map(|&(syncobj, _)| syncobj).collect();
+    let dev = device(&syncobjs)?;
+    let handles = handles(&syncobjs);
+    let values: Vec<u64> = points.iter().map(|&(_, point)| point).collect();
+    let mut flags = flags.bits();
+    if mode == WaitMode::All {
+        flags |= DRM_SYNCOBJ_WAIT_FLAGS_WAIT_ALL;
+    }
+    let mut wait = ffi::syncobj_timeline_wait {
+        handles: handles.as_ptr() as u64,
+        points: values.as_ptr() as u64,
+        timeout_nsec: deadline(timeout),
+        count_handles: handles.len() as u32,
+        flags,
+        ..Default::default()
+    };
+    let result = dev.ioctl(&mut wait);
+    wait_result(result, wait.first_signaled)
+}
+
+/// Reset several sync objects. See `SyncObj::reset`.
+pub fn reset(syncobjs: &[&SyncObj]) -> io::Result<()> {
+    let dev = device(syncobjs)?;
+    let handles = handles(syncobjs);
+    let mut reset = Reset(ffi::syncobj_array {
+        handles: handles.as_ptr() as u64,
+        count_handles: handles.len() as u32,
+        ..Default::default()
+    });
+    dev.ioctl(&mut reset)
+}
+
+/// Signal several sync objects. See `SyncObj::signal`.
+pub fn signal(syncobjs: &[&SyncObj]) -> io::Result<()> {
+    let dev = device(syncobjs)?;
+    let handles = handles(syncobjs);
+    let mut signal = Signal(ffi::syncobj_array {
+        handles: handles.as_ptr() as u64,
+        count_handles: handles.len() as u32,
+        ..Default::default()
+    });
+    dev.ioctl(&mut signal)
+}
+
+/// Signal several timeline points.
+pub fn signal_points(points: &[(&SyncObj, u64)]) -> io::Result<()> {
+    let syncobjs: Vec<_> = points.iter().map(|&(syncobj, _)| syncobj).collect();
+    let dev = device(&syncobjs)?;
+    let handles = handles(&syncobjs);
+    let values: Vec<u64> = points.iter().map(|&(_, point)| point).collect();
+    let mut signal = TimelineSignal(ffi::syncobj_timeline_array {
+        handles: handles.as_ptr() as u64,
+        points: values.as_ptr() as u64,
+        count_handles: handles.len() as u32,
+        ..Default::default()
+    });
+    dev.ioctl(&mut signal)
+}
+
+/// The last signaled point of several timelines, or with
+/// `last_submitted` the last point with work submitted.
+pub fn query(syncobjs: &[&SyncObj], last_submitted: bool) -> io::Result<Vec<u64>> {
+    let dev = device(syncobjs)?;
+    let handles = handles(syncobjs);
+    let mut values = vec![0u64; handles.len()];
+    let mut query = Query(ffi::syncobj_timeline_array {
+        handles: handles.as_ptr() as u64,
+        points: values.as_mut_ptr() as u64,
+        count_handles: handles.len() as u32,
+        flags: if last_submitted { DRM_SYNCOBJ_QUERY_FLAGS_LAST_SUBMITTED } else { 0 },
+    });
+    dev.ioctl(&mut query)?;
+    Ok(values)
+}
@@ -1,95 +1,76 @@
+//! Reading events with tokio.
+//!
+//! `AsyncDevice` is a non-blocking `Device` registered with the tokio
+//! reactor. It's a `Stream` of events, and can wait for particular
+//! page flips and vblanks:
+//!
+//! ```ignore
+//! let mut dev = Device::first_card()?.event_stream()?;
+//! dev.set_master()?;
+//! let frame = dev.page_flip(crtc, fb, PageFlipFlags::empty()).await?;
+//! let next = dev.next_vblank(crtc).await?;
+//! ```
 
-// Async stuff
-
-use {Device, Event};
-use mio::{self, Evented};
-use mio::unix::EventedFd;
-use tokio_core::reactor::{PollEvented, Handle};
-use futures::{Poll, Future, Async};
+use Device;
+use async_device::{self, Readiness};
+use event::Event;
+use tokio_rt::io::Interest;
+use tokio_rt::io::unix::AsyncFd;
 
 use std::io::{self, ErrorKind};
-use std::mem::replace;
-use std::os::unix::io::AsRawFd;
-
-#[cfg(feature = "tokio")]
-impl Evented for Device {
-    fn register(&self,
-                poll: &mio::Poll,
-                token: mio::Token,
-                interest: mio::Ready,
-                opts: mio::PollOpt)
-                -> io::Result<()>
-    {
-        poll.register(&EventedFd(&self.as_raw_fd()), token, interest, opts)
-    }
-    fn reregister(&self,
-                  poll: &mio::Poll,
-                  token: mio::Token,
-                  interest: mio::Ready,
-                  opts: mio::PollOpt)
-                  -> io::Result<()>
-    {
-        poll.reregister(&EventedFd(&self.as_raw_fd()), token, interest, opts)   
-    }
-    fn deregister(&self, poll: &mio::Poll) -> io::Result<()>
-    {
-        poll.deregister(&EventedFd(&self.as_raw_fd()))
-    }
-}
-
-/// Async version
-// pub struct Device {
-    
-// }
+use std::task::{Context, Poll};
 
-pub enum EventFuture {
-    Waiting { 
-        io: PollEvented<Device>,
-    },
-    Empty,
-}
+/// A device whose events are read asynchronously. See
+/// `Device::event_stream`.
+pub type AsyncDevice = async_device::AsyncDevice<AsyncFd<Device>>;
+/// Future for `AsyncDevice::next_event`.
+pub type NextEvent<'a> = async_device::NextEvent<'a, AsyncFd<Device>>;
+/// Future for `AsyncDevice::page_flip` and `next_vblank`.
+pub type WaitCompletion<'a> = async_device::WaitCompletion<'a, AsyncFd<Device>>;
 
-impl EventFuture {
-    pub fn new(dev: Device, handle: &Handle) -> io::Result<EventFuture> {
-        let mut dev = dev;
+impl AsyncDevice {
+    /// Make `dev` non-blocking and register it with the current tokio
+    /// runtime.
+    ///
+    /// # Panics
+    ///
+    /// Outside of a tokio runtime.
+    pub fn new(mut dev: Device) -> io::Result<AsyncDevice> {
         dev.set_nonblocking(true)?;
-        Ok(EventFuture::Waiting { io: PollEvented::new(dev,handle)?  })
+        // Safe: the device owns its fd, so it stays open until the
+        // AsyncFd is done with it.
+        let io = unsafe { AsyncFd::register_with_interest(dev, Interest::READABLE)? };
+        Ok(AsyncDevice::with_io(io))
     }
-    
+
     pub fn get_mut(&mut self) -> &mut Device {
-        match *self {
-            EventFuture::Waiting { ref mut io } => io.get_mut(),
-            _ => panic!("")
-        }
+        self.io.get_mut()
+    }
+
+    /// Give back the device. It's left non-blocking, and still master
+    /// if it was. Events not read yet are lost.
+    pub fn into_inner(self) -> Device {
+        self.io.into_inner()
     }
 }
 
-#[cfg(feature = "tokio")]
-impl Future for EventFuture {
-    type Item = (EventFuture, Event);
-    type Error = io::Error;
+impl Readiness for AsyncFd<Device> {
+    fn get_ref(&self) -> &Device {
+        AsyncFd::get_ref(self)
+    }
 
-    fn poll(&mut self) -> Poll<(EventFuture, Event), io::Error> {
-        let ev = match *self {
-            EventFuture::Waiting { ref mut io } => {
-                if let Async::NotReady = io.poll_read() {
-                    return Ok(Async::NotReady)
-                }
-                match io.get_mut().read_event() {
-                    Ok(ev) => ev,
-                    Err(ref err) if err.kind() == ErrorKind::WouldBlock => {
-                        io.need_read();
-                        return Ok(Async::NotReady);
-                    }
-                    Err(err) => return Err(err)
-                }
+    fn poll_read_events(&mut self, cx: &mut Context) -> Poll<io::Result<Vec<Event>>> {
+        loop {
+            let mut guard = match self.poll_read_ready_mut(cx) {
+                Poll::Ready(guard) => guard?,
+                Poll::Pending => return Poll::Pending,
+            };
+            match guard.try_io(|io| io.get_mut().read_events()) {
+                Ok(Err(ref err)) if err.kind() == ErrorKind::Interrupted => (),
+                Ok(result) => return Poll::Ready(result),
+                // Not actually readable.
+                Err(_) => (),
             }
-            EventFuture::Empty => panic!("Polled on EventFuture after it's done"),
-        };
-
-        match replace(self, EventFuture::Empty) {
-            dev@EventFuture::Waiting { .. } => Ok((dev, ev).into()),
-            _ => panic!("")
         }
     }
 }
@@ -0,0 +1,207 @@
+//! Waiting for vblanks.
+//!
+//! The vblank ioctl addresses CRTCs by pipe: their index in
+//! `Resources::crtcs`, not their id. The functions here take an
+//! `Id<Crtc>` and look the pipe up. (`Device::request_vblank` still
+//! takes the index.)
+//!
+//! ```ignore
+//! let now = dev.vblank_count(crtc)?;
+//! // Block until 10 vblanks later.
+//! let later = dev.wait_vblank(crtc, Target::Absolute(now.seq as u32 + 10), false)?;
+//! ```
+//!
+//! Kernels since 4.15 also have a CRTC sequence API, which takes the
+//! id, has a 64-bit counter that won't wrap, and times everything on
+//! the monotonic clock. It's event only: `Device::queue_sequence` sends
+//! an `Event::CrtcSequence`.
+
+use std::io;
+use std::os::raw::c_ulong;
+use std::time::Duration;
+use event::{Clock, Completion, DrmTimestamp};
+use ioctl_vals::*;
+use mode::{Crtc, Id};
+use {consts, ffi, Capability, Device, DrmIoctl};
+
+/// Which vblank to wait for.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Target {
+    /// This many vblanks from now. 0 is the current one, so doesn't
+    /// wait.
+    Relative(u32),
+    /// The vblank with this sequence number. If it's passed, the wait
+    /// ends at once (or see `next_on_miss`).
+    Absolute(u32),
+}
+
+impl DrmIoctl for ffi::wait_vblank {
+    fn request() -> c_ulong { DRM_IOCTL_WAIT_VBLANK }
+}
+
+impl DrmIoctl for ffi::crtc_get_sequence {
+    fn request() -> c_ulong { DRM_IOCTL_CRTC_GET_SEQUENCE }
+}
+
+impl DrmIoctl for ffi::crtc_queue_sequence {
+    fn request() -> c_ulong { DRM_IOCTL_CRTC_QUEUE_SEQUENCE }
+}
+
+const DRM_CRTC_SEQUENCE_RELATIVE: u32 = 0x1;
+const DRM_CRTC_SEQUENCE_NEXT_ON_MISS: u32 = 0x2;
+
+/// The 64-bit vblank counter of a CRTC. See `Device::crtc_sequence`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Sequence {
+    pub seq: u64,
+    /// When the vblank `seq` happened. Always on the monotonic clock.
+    pub time: DrmTimestamp,
+    /// Is the CRTC on? If not, the counter isn't moving.
+    pub active: bool,
+}
+
+impl Device {
+    /// The pipe of a CRTC: its index in `Resources::crtcs`. The CRTCs
+    /// are only fetched the first time.
+    ///
+    /// # Errors
+    ///
+    /// `ErrorKind::NotFound` if there's no such CRTC.
+    pub fn crtc_pipe(&self, crtc: Id<Crtc>) -> io::Result<u32> {
+        let crtcs = match self.shared.crtcs.get() {
+            Some(crtcs) => crtcs,
+            None => {
+                let crtcs = self.get_resources()?.crtcs().to_vec();
+                self.shared.crtcs.get_or_init(|| crtcs)
+            }
+        };
+        match crtcs.iter().position(|&c| c == crtc) {
+            Some(pipe) => Ok(pipe as u32),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, "No such CRTC")),
+        }
+    }
+
+    /// Block until a vblank on `crtc`, returning its sequence number
+    /// and time.
+    ///
+    /// With `next_on_miss`, an `Absolute` target that has already
+    /// passed waits for the next vblank instead.
+    pub fn wait_vblank(&self, crtc: Id<Crtc>, target: Target, next_on_miss: bool)
+                       -> io::Result<Completion>
+    {
+        let pipe = self.crtc_pipe(crtc)?;
+        let reply = self.vblank_ioctl(pipe, target, next_on_miss, None)?;
+        let time = Duration::new(reply.tval_sec as u64, (reply.tval_usec as u32).saturating_mul(1000));
+        Ok(Completion {
+            seq: reply.sequence as u64,
+            time: DrmTimestamp::new(self.event_clock(), time),
+            crtc_id: Some(crtc.as_u32()),
+        })
+    }
+
+    /// The current vblank count of `crtc`, and when it happened.
+    /// Doesn't block.
+    pub fn vblank_count(&self, crtc: Id<Crtc>) -> io::Result<Completion> {
+        self.wait_vblank(crtc, Target::Relative(0), false)
+    }
+
+    /// Ask for an `Event::VBlank` carrying `user_data` at a vblank on
+    /// `crtc`. Returns the sequence number the event will be for.
+    pub fn request_vblank_event(&self, crtc: Id<Crtc>, target: Target, next_on_miss: bool,
+                                user_data: u64) -> io::Result<u32>
+    {
+        let pipe = self.crtc_pipe(crtc)?;
+        let reply = self.vblank_ioctl(pipe, target, next_on_miss, Some(user_data))?;
+        Ok(reply.sequence)
+    }
+
+    /// The 64-bit vblank counter of `crtc`, and when it last changed.
+    /// Doesn't block.
+    pub fn crtc_sequence(&self, crtc: Id<Crtc>) -> io::Result<Sequence> {
+        let mut arg = ffi::crtc_get_sequence { crtc_id: crtc.as_u32(), ..Default::default() };
+        self.ioctl(&mut arg)?;
+        Ok(Sequence {
+            seq: arg.sequence,
+            time: DrmTimestamp::new(Clock::Monotonic, Duration::from_nanos(arg.sequence_ns.max(0) as u64)),
+            active: arg.active != 0,
+        })
+    }
+
+    /// Ask for an `Event::CrtcSequence` carrying `user_data` when the
+    /// counter of `crtc` reaches `target`, or `target` vblanks from
+    /// now if `relative`. Returns the sequence number the event will
+    /// be for.
+    ///
+    /// With `next_on_miss`, a target that has already passed is moved
+    /// to the next vblank instead of sending the event at once.
+    pub fn queue_sequence(&self, crtc: Id<Crtc>, target: u64, relative: bool,
+                          next_on_miss: bool, user_data: u64) -> io::Result<u64>
+    {
+        let mut flags = 0;
+        if relative {
+            flags |= DRM_CRTC_SEQUENCE_RELATIVE;
+        }
+        if next_on_miss {
+            flags |= DRM_CRTC_SEQUENCE_NEXT_ON_MISS;
+        }
+        let mut arg = ffi::crtc_queue_sequence {
+            crtc_id: crtc.as_u32(),
+            flags,
+            sequence: target,
+            user_data,
+        };
+        self.ioctl(&mut arg)?;
+        Ok(arg.sequence)
+    }
+
+    /// The wait vblank ioctl. With `event`, returns at once and sends
+    /// an event with the user data.
+    pub(crate) fn vblank_ioctl(&self, pipe: u32, target: Target, next_on_miss: bool,
+                               event: Option<u64>) -> io::Result<ffi::wait_vblank_reply>
+    {
+        // Originally there were at most 2 CRTC's and so a "Secondary"
+        // flag was sufficient to switch between them. Later ones need
+        // the high CRTC bits.
+        let mut type_ = match pipe {
+            0 => 0,
+            1 => consts::DRM_VBLANK_SECONDARY,
+            _ => {
+                if pipe > consts::DRM_VBLANK_HIGH_CRTC_MASK >> 1
+                    || self.cached_capability(Capability::VblankHighCrtc) == 0 {
+                    return Err(io::Error::new(io::ErrorKind::Unsupported,
+                                              "Can't wait for vblanks on this CRTC"));
+                }
+                (pipe << 1) & consts::DRM_VBLANK_HIGH_CRTC_MASK
+            }
+        };
+        let sequence = match target {
+            Target::Relative(n) => {
+                type_ |= consts::DRM_VBLANK_RELATIVE;
+                n
+            }
+            Target::Absolute(seq) => {
+                type_ |= consts::DRM_VBLANK_ABSOLUTE;
+                seq
+            }
+        };
+        if next_on_miss {
+            type_ |= consts::DRM_VBLANK_NEXTONMISS;
+        }
+        if event.is_some() {
+            type_ |= consts::DRM_VBLANK_EVENT;
+        }
+
+        let mut arg = ffi::wait_vblank::default();
+        unsafe {
+            *arg.request() = ffi::wait_vblank_request {
+                type_,
+                sequence,
+                signal: event.unwrap_or(0) as c_ulong,
+            };
+        }
+        // If interrupted, the kernel has rewritten a relative wait as an
+        // absolute one, so retrying doesn't wait longer.
+        self.ioctl(&mut arg)?;
+        Ok(unsafe { *arg.reply() })
+    }
+}
@@ -0,0 +1,55 @@
+extern crate drm;
+
+use drm::{Capability, ClientCapability, Device, Event};
+use drm::mode::{AtomicFlags, AtomicRequest, FlipMode, PAGE_FLIP_EVENT};
+
+#[test]
+fn async_page_flip() {
+    let mut dev = Device::first_card().expect("Failed to open card");
+    let res = dev.get_resources().expect("Failed to get resources");
+    let crtc = res.crtcs().iter().map(|&id| dev.get(id).unwrap())
+        .find(|crtc| crtc.mode().is_some() && crtc.fb_id().is_some());
+    let crtc = match crtc {
+        Some(crtc) => crtc,
+        None => return,
+    };
+    let supported = dev.capability(Capability::AsyncPageFlip).unwrap_or(0) != 0;
+
+    let mut master = dev.set_master().expect("Failed to become master");
+    let mode = master.page_flip_async(crtc.id(), crtc.fb_id().unwrap(), PAGE_FLIP_EVENT, 5)
+        .expect("Failed to flip");
+    if !supported {
+        assert_eq!(mode, FlipMode::Vsync);
+    }
+    match master.read_event().expect("Failed to read event") {
+        Event::PageFlip { user: 5, .. } => {}
+        ev => panic!("Expected page flip, got {:?}", ev),
+    }
+}
+
+#[test]
+fn atomic_async_needs_fb_id_only() {
+    let mut dev = Device::first_card().expect("Failed to open card");
+    assert!(!dev.has_client_capability(ClientCapability::Atomic));
+    if dev.set_client_capability(ClientCapability::Atomic, true).is_err() {
+        return;
+    }
+    assert!(dev.has_client_capability(ClientCapability::Atomic));
+    assert!(dev.has_client_capability(ClientCapability::UniversalPlanes));
+
+    let res = dev.get_resources().expect("Failed to get resources");
+    let crtc = match res.crtcs().iter().map(|&id| dev.get(id).unwrap())
+        .find(|crtc| crtc.mode().is_some()) {
+        Some(crtc) => crtc,
+        None => return,
+    };
+    let (active, value) = dev.find_property(crtc.id(), "ACTIVE").unwrap().unwrap();
+
+    let master = dev.set_master().expect("Failed to become master");
+    // Setting ACTIVE (even to what it is) isn't allowed async.
+    let mut req = AtomicRequest::new();
+    req.add_property(crtc.id(), active, value);
+    let (_, mode) = master.atomic_commit_async(&mut req, AtomicFlags::empty(), 0)
+        .expect("Failed to commit");
+    assert_eq!(mode, FlipMode::Vsync);
+}
@@ -0,0 +1,129 @@
+
+extern crate drm;
+use drm::convert::{self, Image, ImageMut, Options, ColorRange};
+use drm::fourcc::FourCC;
+
+const W: u32 = 6;
+const H: u32 = 4;
+
+fn argb_pattern() -> Vec<u8> {
+    let mut data = Vec::new();
+    for y in 0..H {
+        for x in 0..W {
+            let pixel: u32 = 0xff00_0000 | (x * 40) << 16 | (y * 60) << 8 | 0x80;
+            data.extend_from_slice(&pixel.to_le_bytes());
+        }
+    }
+    data
+}
+
+fn close(a: &[u8], b: &[u8], tolerance: u8) -> bool {
+    a.iter().zip(b).all(|(&a, &b)| (a as i16 - b as i16).abs() <= tolerance as i16)
+}
+
+#[test]
+fn argb_to_rgb565() {
+    let src = [0xff, 0x00, 0xff, 0xff, 0x00, 0xff, 0x00, 0xff];
+    let src = Image::packed(FourCC::ARGB8888, 2, 1, &src, 8).unwrap();
+    let mut out = [0u8; 4];
+    {
+        let mut dst = ImageMut::packed(FourCC::RGB565, 2, 1, &mut out, 4).unwrap();
+        let opts = Options { dither: false, ..Options::default() };
+        convert::convert(&src, &mut dst, &opts).unwrap();
+    }
+    assert_eq!(out, [0x1f, 0xf8, 0xe0, 0x07]);
+}
+
+#[test]
+fn padded_pitch() {
+    let src = argb_pattern();
+    let src = Image::packed(FourCC::ARGB8888, W, H, &src, W as usize * 4).unwrap();
+    // BGR888 with 2 bytes of padding on every row.
+    let pitch = W as usize * 3 + 2;
+    let mut out = vec![0xaa; pitch * H as usize];
+    {
+        let mut dst = ImageMut::packed(FourCC::BGR888, W, H, &mut out, pitch).unwrap();
+        convert::convert(&src, &mut dst, &Options::default()).unwrap();
+    }
+    // [23:0] B:G:R little endian
+    assert_eq!(&out[pitch + 3..pitch + 6], &[40, 60, 0x80]);
+    assert_eq!(&out[W as usize * 3..pitch], &[0xaa, 0xaa]);
+}
+
+#[test]
+fn nv12_round_trip() {
+    let data = argb_pattern();
+    let src = Image::packed(FourCC::ARGB8888, W, H, &data, W as usize * 4).unwrap();
+    let mut y = vec![0; (W * H) as usize];
+    let mut uv = vec![0; (W * H / 2) as usize];
+    for range in &[ColorRange::Limited, ColorRange::Full] {
+        let opts = Options { range: *range, dither: false, ..Options::default() };
+        {
+            let mut dst = ImageMut::new(FourCC::NV12, W, H,
+                                        vec![(&mut y[..], W as usize), (&mut uv[..], W as usize)])
+                .unwrap();
+            convert::convert(&src, &mut dst, &opts).unwrap();
+        }
+        let nv12 = Image::new(FourCC::NV12, W, H, vec![(&y[..], W as usize), (&uv[..], W as usize)])
+            .unwrap();
+        let mut back = vec![0; data.len()];
+        {
+            let mut dst = ImageMut::packed(FourCC::ARGB8888, W, H, &mut back, W as usize * 4)
+                .unwrap();
+            convert::convert(&nv12, &mut dst, &opts).unwrap();
+        }
+        // Chroma is shared by 2x2 blocks, so neighbours bleed a little.
+        assert!(close(&data, &back, 40));
+    }
+}
+
+#[test]
+fn gray_through_yuyv() {
+    let gray = [0x80u8; 4 * 4];
+    let src = Image::packed(FourCC::XRGB8888, 4, 1, &gray, 16).unwrap();
+    let mut yuyv = [0u8; 8];
+    {
+        let mut dst = ImageMut::packed(FourCC::YUYV, 4, 1, &mut yuyv, 8).unwrap();
+        convert::convert(&src, &mut dst, &Options::default()).unwrap();
+    }
+    // Y0 Cb Y1 Cr, no chroma for gray.
+    assert!(close(&yuyv, &[126, 128, 126, 128, 126, 128, 126, 128], 1));
+}
+
+#[test]
+fn dithered_reduction_keeps_average() {
+    // 10 bit value between two 8 bit steps. (128.38)
+    let v: u32 = 515;
+    let pixel = v << 20 | v << 10 | v;
+    let mut data = Vec::new();
+    for _ in 0..16 {
+        data.extend_from_slice(&pixel.to_le_bytes());
+    }
+    let src = Image::packed(FourCC::XRGB2101010, 4, 4, &data, 16).unwrap();
+    let mut out = [0u8; 64];
+    {
+        let mut dst = ImageMut::packed(FourCC::XRGB8888, 4, 4, &mut out, 16).unwrap();
+        convert::convert(&src, &mut dst, &Options::default()).unwrap();
+    }
+    let blue: Vec<u32> = out.chunks(4).map(|p| p[0] as u32).collect();
+    assert!(blue.iter().any(|&b| b == 128));
+    assert!(blue.iter().any(|&b| b == 129));
+    let sum: u32 = blue.iter().sum();
+    // 16 * 128.38 = 2054
+    assert!((2051..=2057).contains(&sum), "sum {}", sum);
+}
+
+#[test]
+fn rejects_bad_images() {
+    let data = [0u8; 16];
+    assert!(Image::packed(FourCC::ARGB8888, 4, 2, &data, 16).is_err());
+    assert!(Image::packed(FourCC::ARGB8888, 4, 1, &data, 8).is_err());
+    assert!(Image::packed(FourCC::C8, 4, 1, &data, 4).is_err());
+    assert!(Image::packed(FourCC::NV12, 2, 2, &data, 2).is_err());
+    assert!(!convert::is_supported(FourCC::ARGB16161616F));
+
+    let src = Image::packed(FourCC::ARGB8888, 2, 1, &data, 8).unwrap();
+    let mut out = [0u8; 16];
+    let mut dst = ImageMut::packed(FourCC::ARGB8888, 4, 1, &mut out, 16).unwrap();
+    assert!(convert::convert(&src, &mut dst, &Options::default()).is_err());
+}
@@ -0,0 +1,107 @@
+
+extern crate drm;
+use drm::convert::{Image, ImageMut};
+use drm::draw::{self, Alpha, Color, Filter, Rect};
+use drm::fourcc::FourCC;
+
+fn pixel(data: &[u8], pitch: usize, x: usize, y: usize) -> u32 {
+    let at = y * pitch + x * 4;
+    u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+#[test]
+fn fill_is_clipped() {
+    let mut data = vec![0u8; 20 * 3];
+    {
+        let mut img = ImageMut::packed(FourCC::XRGB8888, 4, 3, &mut data, 20).unwrap();
+        draw::fill_rect(&mut img, Rect::new(-1, 1, 3, 10), Color::rgb8(0xff, 0, 0)).unwrap();
+        draw::fill_rect(&mut img, Rect::new(10, 10, 3, 3), Color::rgb8(0, 0xff, 0)).unwrap();
+    }
+    assert_eq!(pixel(&data, 20, 0, 0), 0);
+    assert_eq!(pixel(&data, 20, 0, 1) & 0xffffff, 0xff0000);
+    assert_eq!(pixel(&data, 20, 1, 2) & 0xffffff, 0xff0000);
+    assert_eq!(pixel(&data, 20, 2, 1), 0);
+    // Padding is untouched.
+    assert_eq!(&data[16..20], &[0; 4]);
+}
+
+#[test]
+fn blit_converts() {
+    let src = [0x1f, 0xf8, 0xe0, 0x07]; // RGB565 magenta, green
+    let src = Image::packed(FourCC::RGB565, 2, 1, &src, 4).unwrap();
+    let mut data = vec![0u8; 12 * 2];
+    {
+        let mut dst = ImageMut::packed(FourCC::XRGB8888, 3, 2, &mut data, 12).unwrap();
+        draw::blit(&src, &mut dst, 2, 1).unwrap();
+    }
+    assert_eq!(pixel(&data, 12, 2, 1) & 0xffffff, 0xff00ff);
+    assert_eq!(pixel(&data, 12, 1, 1), 0);
+}
+
+#[test]
+fn composite_over() {
+    let src = [0x00, 0x00, 0xff, 0x80]; // ARGB8888 half transparent red
+    let src = Image::packed(FourCC::ARGB8888, 1, 1, &src, 4).unwrap();
+    let mut data = [0xff, 0x00, 0x00, 0xff]; // opaque blue
+    {
+        let mut dst = ImageMut::packed(FourCC::ARGB8888, 1, 1, &mut data, 4).unwrap();
+        draw::composite(&src, &mut dst, 0, 0, Alpha::Straight).unwrap();
+    }
+    assert_eq!(data[3], 0xff);
+    assert!((data[2] as i32 - 0x80).abs() <= 1);
+    assert!((data[0] as i32 - 0x7f).abs() <= 1);
+
+    let src = [0x00, 0x00, 0x80, 0x80]; // the same, premultiplied
+    let src = Image::packed(FourCC::ARGB8888, 1, 1, &src, 4).unwrap();
+    let mut data = [0xff, 0x00, 0x00, 0xff];
+    {
+        let mut dst = ImageMut::packed(FourCC::ARGB8888, 1, 1, &mut data, 4).unwrap();
+        draw::composite(&src, &mut dst, 0, 0, Alpha::Premultiplied).unwrap();
+    }
+    assert_eq!(data[3], 0xff);
+    assert!((data[2] as i32 - 0x80).abs() <= 1);
+    assert!((data[0] as i32 - 0x7f).abs() <= 1);
+}
+
+#[test]
+fn scale_up() {
+    let src = [0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0x00];
+    let src = Image::packed(FourCC::XRGB8888, 2, 1, &src, 8).unwrap();
+    let mut data = vec![0u8; 16];
+    {
+        let mut dst = ImageMut::packed(FourCC::XRGB8888, 4, 1, &mut data, 16).unwrap();
+        draw::scale(&src, &mut dst, Rect::new(0, 0, 4, 1), Filter::Nearest).unwrap();
+    }
+    let blue: Vec<u8> = data.chunks(4).map(|p| p[0]).collect();
+    assert_eq!(blue, vec![0, 0, 0xff, 0xff]);
+    {
+        let mut dst = ImageMut::packed(FourCC::XRGB8888, 4, 1, &mut data, 16).unwrap();
+        draw::scale(&src, &mut dst, Rect::new(0, 0, 4, 1), Filter::Bilinear).unwrap();
+    }
+    let blue: Vec<u8> = data.chunks(4).map(|p| p[0]).collect();
+    assert_eq!(blue[0], 0);
+    assert!(blue[1] > 0x30 && blue[1] < 0x50);
+    assert!(blue[2] > 0xb0 && blue[2] < 0xd0);
+    assert_eq!(blue[3], 0xff);
+}
+
+#[test]
+fn flips() {
+    let mut data: Vec<u8> = (0..6 * 2).collect(); // 3x2 RGB565
+    {
+        let mut img = ImageMut::packed(FourCC::RGB565, 3, 2, &mut data, 6).unwrap();
+        draw::flip_horizontal(&mut img).unwrap();
+    }
+    assert_eq!(&data[..6], &[4, 5, 2, 3, 0, 1]);
+    {
+        let mut img = ImageMut::packed(FourCC::RGB565, 3, 2, &mut data, 6).unwrap();
+        draw::flip_vertical(&mut img).unwrap();
+    }
+    assert_eq!(&data[..6], &[10, 11, 8, 9, 6, 7]);
+    assert_eq!(&data[6..], &[4, 5, 2, 3, 0, 1]);
+
+    let mut nv12 = [0u8; 6];
+    let (y, uv) = nv12.split_at_mut(4);
+    let mut img = ImageMut::new(FourCC::NV12, 2, 2, vec![(y, 2), (uv, 2)]).unwrap();
+    assert!(draw::flip_vertical(&mut img).is_err());
+}
@@ -0,0 +1,67 @@
+extern crate drm;
+use drm::Device;
+use drm::mode::*;
+use drm::fourcc::*;
+
+/// Map a buffer and get the length and pitch of each plane's slice.
+fn plane_slices(buffer: &DumbBuffer) -> Vec<(usize, usize)> {
+    let mut map = buffer.map(MapProtection::ReadWrite).expect("Failed to map");
+    let len = map.as_slice().len();
+    assert!(len >= buffer.bytes());
+    map.planes_mut().expect("Mapped read only").iter()
+        .map(|&(ref data, pitch)| (data.len(), pitch))
+        .collect()
+}
+
+#[test]
+fn single_plane_layout() {
+    let dev = Device::first_card().expect("Failed to open card");
+    let buffer = DumbBuffer::create(&dev, 64, 48, FourCC::XRGB8888)
+        .expect("Failed to create dumb buffer");
+    let pitch = buffer.pitch() as u32;
+    assert!(pitch >= 64 * 4);
+    assert_eq!(buffer.offsets(), vec![0]);
+    assert_eq!(buffer.pitches(), vec![pitch]);
+    assert!(buffer.bytes() >= 48 * pitch as usize);
+
+    let slices = plane_slices(&buffer);
+    assert_eq!(slices.len(), 1);
+    assert!(slices[0].0 >= 48 * pitch as usize);
+    assert_eq!(slices[0].1, pitch as usize);
+}
+
+#[test]
+fn nv12_layout() {
+    let dev = Device::first_card().expect("Failed to open card");
+    let buffer = DumbBuffer::create(&dev, 64, 48, FourCC::NV12)
+        .expect("Failed to create dumb buffer");
+    let pitch = buffer.pitch() as u32;
+    assert!(pitch >= 64);
+    // The Cb:Cr plane has half the rows, of the same width in bytes.
+    assert_eq!(buffer.offsets(), vec![0, 48 * pitch]);
+    assert_eq!(buffer.pitches(), vec![pitch, pitch]);
+    assert!(buffer.bytes() >= 72 * pitch as usize);
+
+    let slices = plane_slices(&buffer);
+    assert_eq!(slices, vec![(48 * pitch as usize, pitch as usize),
+                            (slices[1].0, pitch as usize)]);
+    assert!(slices[1].0 >= 24 * pitch as usize);
+}
+
+#[test]
+fn yuv420_layout() {
+    let dev = Device::first_card().expect("Failed to open card");
+    let buffer = DumbBuffer::create(&dev, 64, 48, FourCC::YUV420)
+        .expect("Failed to create dumb buffer");
+    let pitch = buffer.pitch() as u32;
+    // Cb and Cr have half the rows, each half as wide.
+    let chroma = pitch / 2;
+    assert_eq!(buffer.offsets(), vec![0, 48 * pitch, 48 * pitch + 24 * chroma]);
+    assert_eq!(buffer.pitches(), vec![pitch, chroma, chroma]);
+    assert!(buffer.bytes() >= 72 * pitch as usize);
+
+    let slices = plane_slices(&buffer);
+    assert_eq!(slices[0], (48 * pitch as usize, pitch as usize));
+    assert_eq!(slices[1], (24 * chroma as usize, chroma as usize));
+    assert!(slices[2].0 >= 24 * chroma as usize);
+}
@@ -0,0 +1,167 @@
+
+extern crate drm;
+use std::io::ErrorKind;
+use drm::Event;
+use std::time::Duration;
+use drm::event::{parse_events, Clock, DrmTimestamp, EventContext};
+
+fn header(type_: u32, length: u32) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(&type_.to_ne_bytes());
+    data.extend_from_slice(&length.to_ne_bytes());
+    data
+}
+
+fn vblank(type_: u32, user: u64, seq: u32, crtc_id: u32) -> Vec<u8> {
+    let mut data = header(type_, 32);
+    data.extend_from_slice(&user.to_ne_bytes());
+    data.extend_from_slice(&1u32.to_ne_bytes());
+    data.extend_from_slice(&500u32.to_ne_bytes());
+    data.extend_from_slice(&seq.to_ne_bytes());
+    data.extend_from_slice(&crtc_id.to_ne_bytes());
+    data
+}
+
+#[test]
+fn parses_every_event() {
+    let mut buf = vblank(1, 7, 100, 0);
+    buf.extend(vblank(2, 8, 101, 42));
+    let mut sequence = header(3, 32);
+    sequence.extend_from_slice(&9u64.to_ne_bytes());
+    sequence.extend_from_slice(&1234i64.to_ne_bytes());
+    sequence.extend_from_slice(&102u64.to_ne_bytes());
+    buf.extend(sequence);
+
+    let events: Vec<_> = parse_events(&buf, Clock::Monotonic).map(Result::unwrap).collect();
+    assert_eq!(events.len(), 3);
+    match events[0] {
+        Event::VBlank { seq: 100, user: 7, crtc_id: 0, .. } => {}
+        ref ev => panic!("Expected vblank, got {:?}", ev),
+    }
+    match events[1] {
+        Event::PageFlip { seq: 101, user: 8, crtc_id: 42, .. } => {}
+        ref ev => panic!("Expected page flip, got {:?}", ev),
+    }
+    assert_eq!(events[1].crtc_id(), Some(42));
+    match events[0] {
+        Event::VBlank { tv, .. } => {
+            assert_eq!(tv, DrmTimestamp::new(Clock::Monotonic, Duration::new(1, 500_000)));
+        }
+        _ => unreachable!(),
+    }
+    let time = DrmTimestamp::new(Clock::Monotonic, Duration::from_nanos(1234));
+    assert_eq!(events[2], Event::CrtcSequence { seq: 102, time, user: 9 });
+}
+
+#[test]
+fn keeps_unknown_events() {
+    let mut buf = header(0x8000_0001, 12);
+    buf.extend_from_slice(&[1, 2, 3, 4]);
+    buf.extend(vblank(1, 0, 5, 0));
+
+    let events: Vec<_> = parse_events(&buf, Clock::Monotonic).map(Result::unwrap).collect();
+    assert_eq!(events[0], Event::Unknown { type_: 0x8000_0001, data: vec![1, 2, 3, 4] });
+    assert_eq!(events.len(), 2);
+}
+
+#[test]
+fn malformed_events_are_errors() {
+    // Too short for a vblank, but the framing is fine.
+    let mut buf = header(1, 12);
+    buf.extend_from_slice(&[0; 4]);
+    buf.extend(vblank(2, 0, 5, 0));
+    let mut events = parse_events(&buf, Clock::Monotonic);
+    assert_eq!(events.next().unwrap().unwrap_err().kind(), ErrorKind::InvalidData);
+    assert!(events.next().unwrap().is_ok());
+    assert!(events.next().is_none());
+
+    // Bad lengths stop parsing.
+    for &length in &[0, 4, 1000] {
+        let mut buf = header(1, length);
+        buf.extend(vblank(1, 0, 5, 0));
+        let mut events = parse_events(&buf, Clock::Monotonic);
+        assert!(events.next().unwrap().is_err());
+        assert!(events.next().is_none());
+        assert_eq!(events.consumed(), buf.len());
+    }
+
+    // A truncated header.
+    let mut events = parse_events(&[1, 0, 0], Clock::Monotonic);
+    assert!(events.next().unwrap().is_err());
+    assert!(events.next().is_none());
+}
+
+#[test]
+fn timestamps() {
+    let now = Clock::Monotonic.now();
+    let future = DrmTimestamp::new(Clock::Monotonic, now + Duration::from_secs(10));
+    let past = DrmTimestamp::new(Clock::Monotonic, now - Duration::from_millis(1));
+    assert!(future.time_until() > Duration::from_secs(9));
+    assert_eq!(future.elapsed(), Duration::from_secs(0));
+    assert_eq!(past.time_until(), Duration::from_secs(0));
+    assert!(past < future);
+    assert_eq!(future.duration_since(past), Some(Duration::from_secs(10) + Duration::from_millis(1)));
+    assert_eq!(past.duration_since(future), None);
+
+    // The same moment on both clocks.
+    let real = DrmTimestamp::new(Clock::Realtime, Clock::Realtime.now());
+    let mono = DrmTimestamp::new(Clock::Monotonic, Clock::Monotonic.now());
+    assert_eq!(real.partial_cmp(&mono), None);
+    let diff = real.monotonic().max(mono.monotonic()) - real.monotonic().min(mono.monotonic());
+    assert!(diff < Duration::from_secs(1));
+    let system = mono.to_system_time();
+    let diff = system.duration_since(real.to_system_time())
+        .unwrap_or_else(|e| e.duration());
+    assert!(diff < Duration::from_secs(1));
+}
+
+fn page_flip(user: u64, crtc_id: u32) -> Event {
+    let tv = DrmTimestamp::new(Clock::Monotonic, Duration::from_secs(1));
+    Event::PageFlip { seq: 5, tv, user, crtc_id }
+}
+
+#[test]
+fn dispatches_to_callbacks() {
+    let crtc = unsafe { drm::mode::Id::<drm::mode::Crtc>::from_u32(42).unwrap() };
+    let (mut flipped, mut other, mut vblanks) = (Vec::new(), Vec::new(), 0);
+    let mut tokened = None;
+    {
+        let mut ctx = EventContext::new();
+        ctx.on_page_flip(crtc, |frame, user| flipped.push((frame.crtc_id, user)));
+        ctx.on_any_page_flip(|frame, user| other.push((frame.crtc_id, user)));
+        ctx.on_vblank(|_, _| vblanks += 1);
+        let token = ctx.token(|frame| tokened = Some(frame.seq));
+        assert_eq!(ctx.pending(), 1);
+
+        assert!(ctx.dispatch(&page_flip(1, 42)));
+        assert!(ctx.dispatch(&page_flip(2, 43)));
+        assert!(ctx.dispatch(&page_flip(3, 0)));
+        assert!(ctx.dispatch(&page_flip(token, 42)));
+        assert_eq!(ctx.pending(), 0);
+        // Tokens are only used once.
+        assert!(ctx.dispatch(&page_flip(token, 42)));
+        let tv = DrmTimestamp::new(Clock::Monotonic, Duration::from_secs(1));
+        assert!(ctx.dispatch(&Event::VBlank { seq: 1, tv, user: 0, crtc_id: 0 }));
+        assert!(!ctx.dispatch(&Event::CrtcSequence { seq: 1, time: tv, user: 0 }));
+        assert!(!ctx.dispatch(&Event::Unknown { type_: 99, data: Vec::new() }));
+
+        let token = ctx.token(|_| panic!("Cancelled token called"));
+        assert!(ctx.cancel(token));
+        assert!(!ctx.dispatch(&Event::CrtcSequence { seq: 1, time: tv, user: token }));
+    }
+    assert_eq!(tokened, Some(5));
+    assert_eq!(vblanks, 1);
+    assert_eq!(flipped.len(), 2);
+    assert_eq!(flipped[0], (Some(42), 1));
+    assert_eq!(other, vec![(Some(43), 2), (None, 3)]);
+}
+
+#[test]
+fn tokens_fit_vblank_user_data() {
+    let mut ctx = EventContext::new();
+    let token = ctx.token(|_| ());
+    // Vblank requests only carry a C unsigned long.
+    assert_eq!(token as std::os::raw::c_ulong as u64, token);
+    let tv = DrmTimestamp::new(Clock::Monotonic, Duration::from_secs(1));
+    assert!(ctx.dispatch(&Event::VBlank { seq: 1, tv, user: token, crtc_id: 0 }));
+}
@@ -0,0 +1,96 @@
+#![cfg(any(feature = "calloop", feature = "async-io", feature = "mio"))]
+extern crate drm;
+extern crate libc;
+#[cfg(feature = "calloop")]
+extern crate calloop;
+#[cfg(feature = "async-io")]
+extern crate async_io;
+#[cfg(feature = "mio")]
+extern crate mio;
+
+use drm::{Device, Event};
+use std::fs::File;
+use std::io::Write;
+use std::os::unix::io::FromRawFd;
+
+fn page_flip(user: u64, seq: u32) -> Vec<u8> {
+    let mut data = Vec::new();
+    for word in &[2u32, 32] {
+        data.extend_from_slice(&word.to_ne_bytes());
+    }
+    data.extend_from_slice(&user.to_ne_bytes());
+    for word in &[1u32, 0, seq, 0] {
+        data.extend_from_slice(&word.to_ne_bytes());
+    }
+    data
+}
+
+/// A "device" reading from a pipe, and the other end.
+fn pipe_device() -> (Device, File) {
+    let mut fds = [0; 2];
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+    unsafe { (Device::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) }
+}
+
+fn users(events: &[Event]) -> Vec<u64> {
+    events.iter().filter_map(Event::user_data).collect()
+}
+
+#[cfg(feature = "calloop")]
+#[test]
+fn calloop_source() {
+    use drm::calloop::DeviceSource;
+    use std::time::Duration;
+
+    let (dev, mut input) = pipe_device();
+    let mut event_loop = calloop::EventLoop::<Vec<Event>>::try_new().unwrap();
+    let source = DeviceSource::new(&dev).unwrap();
+    event_loop.handle().insert_source(source, |event, _, events| events.push(event)).unwrap();
+
+    input.write_all(&page_flip(1, 10)).unwrap();
+    input.write_all(&page_flip(2, 11)).unwrap();
+    let mut events = Vec::new();
+    event_loop.dispatch(Some(Duration::from_secs(1)), &mut events).unwrap();
+    assert_eq!(users(&events), vec![1, 2]);
+}
+
+#[cfg(feature = "async-io")]
+#[test]
+fn async_io_device() {
+    use drm::async_io::AsyncDevice;
+
+    let (dev, mut input) = pipe_device();
+    let mut dev = AsyncDevice::new(dev).unwrap();
+    input.write_all(&page_flip(1, 10)).unwrap();
+    input.write_all(&page_flip(2, 11)).unwrap();
+    let first = async_io::block_on(dev.next_event()).unwrap();
+    let second = async_io::block_on(dev.next_event()).unwrap();
+    assert_eq!(users(&[first, second]), vec![1, 2]);
+    // Not a DRM device, so the ioctl fails rather than waiting forever.
+    let crtc = unsafe { drm::mode::Id::from_u32(1).unwrap() };
+    assert!(async_io::block_on(dev.next_vblank(crtc)).is_err());
+}
+
+#[cfg(feature = "mio")]
+#[test]
+fn mio_source() {
+    use mio::{Events, Interest, Poll, Token};
+    use std::io::ErrorKind;
+    use std::time::Duration;
+
+    let (mut dev, mut input) = pipe_device();
+    dev.set_nonblocking(true).unwrap();
+    let mut poll = Poll::new().unwrap();
+    poll.registry().register(&mut dev, Token(7), Interest::READABLE).unwrap();
+
+    input.write_all(&page_flip(1, 10)).unwrap();
+    let mut events = Events::with_capacity(4);
+    poll.poll(&mut events, Some(Duration::from_secs(1))).unwrap();
+    assert_eq!(events.iter().map(|event| event.token()).collect::<Vec<_>>(), vec![Token(7)]);
+    assert_eq!(dev.read_event().unwrap().user_data(), Some(1));
+    match dev.read_event() {
+        Err(ref err) if err.kind() == ErrorKind::WouldBlock => {}
+        ev => panic!("Expected WouldBlock, got {:?}", ev),
+    }
+    poll.registry().deregister(&mut dev).unwrap();
+}
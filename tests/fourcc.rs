@@ -0,0 +1,54 @@
+
+extern crate drm;
+use drm::fourcc::FourCC;
+
+#[test]
+fn round_trip_codes() {
+    let code: u32 = FourCC::XRGB8888.into();
+    assert_eq!(code, 0x3432_5258);
+    assert_eq!(FourCC::from(code), FourCC::XRGB8888);
+
+    // Unknown codes must survive the trip.
+    let unknown = u32::from_le_bytes(*b"ZZ99");
+    assert_eq!(FourCC::from(unknown), FourCC::Other(unknown));
+    assert_eq!(u32::from(FourCC::from(unknown)), unknown);
+}
+
+#[test]
+fn display_and_parse() {
+    assert_eq!(FourCC::NV12.to_string(), "NV12");
+    assert_eq!(FourCC::C8.to_string(), "C8  ");
+    assert_eq!("XR24".parse::<FourCC>(), Ok(FourCC::XRGB8888));
+    assert_eq!("C8".parse::<FourCC>(), Ok(FourCC::C8));
+    assert_eq!("ZZ99".parse::<FourCC>().unwrap().to_string(), "ZZ99");
+    assert!("TOOLONG".parse::<FourCC>().is_err());
+    assert_eq!(FourCC::Other(0x0000_0001).to_string(), "0x00000001");
+}
+
+#[test]
+fn format_info() {
+    let nv12 = FourCC::NV12.info().unwrap();
+    assert_eq!(nv12.num_planes(), 2);
+    assert!(nv12.is_yuv());
+    assert_eq!(nv12.plane_width(1, 1921), 961);
+    assert_eq!(nv12.min_pitch(1, 1920), 1920);
+    assert_eq!(nv12.plane_size(1, 1080, 1920), 1920 * 540);
+
+    let yuyv = FourCC::YUYV.info().unwrap();
+    assert_eq!(yuyv.bits_per_pixel(0), 16);
+    assert_eq!(yuyv.min_pitch(0, 3), 8);
+
+    let argb = FourCC::ARGB8888.info().unwrap();
+    assert!(argb.has_alpha());
+    assert_eq!(argb.depth(), 32);
+    assert_eq!(argb.bits_per_pixel(0), 32);
+
+    assert_eq!(FourCC::Other(0).info(), None);
+}
+
+#[test]
+fn as_u32() {
+    assert_eq!(FourCC::XRGB8888.as_u32(), 0x3432_5258);
+    assert_eq!(FourCC::Other(7).as_u32(), 7);
+    assert_eq!(FourCC::XRGB16161616.info().unwrap().depth(), 0);
+}
@@ -0,0 +1,174 @@
+extern crate drm;
+
+use std::time::Duration;
+use drm::Event;
+use drm::event::{Clock, DrmTimestamp};
+use drm::frame_clock::FrameClock;
+
+const NOMINAL: u64 = 16_666_667;
+
+fn at(ns: u64) -> DrmTimestamp {
+    DrmTimestamp::new(Clock::Monotonic, Duration::from_nanos(ns))
+}
+
+fn vblank(seq: u32, ns: u64, crtc_id: u32) -> Event {
+    Event::VBlank { seq, tv: at(ns), user: 0, crtc_id }
+}
+
+/// Up to ±200us of jitter, the same every run.
+fn jitter(i: u64) -> i64 {
+    ((i * 7919) % 401) as i64 - 200
+}
+
+fn nanos(duration: Duration) -> i64 {
+    duration.as_nanos() as i64
+}
+
+#[test]
+fn converges_on_real_period() {
+    // Nominally 60Hz, really a bit slower.
+    let real = 16_700_000;
+    let mut clock = FrameClock::new(Duration::from_nanos(NOMINAL));
+    for i in 0..200 {
+        let ns = 1_000_000_000 + i * real;
+        let ns = (ns as i64 + jitter(i) * 1000) as u64;
+        assert!(clock.observe(&vblank(i as u32, ns, 0)));
+    }
+    assert_eq!(clock.samples(), 199);
+    assert!((nanos(clock.period()) - real as i64).abs() < 50_000, "{:?}", clock.period());
+    assert_eq!(clock.nominal_period(), Duration::from_nanos(NOMINAL));
+    assert!((clock.refresh_rate() - 59.88).abs() < 0.2);
+}
+
+#[test]
+fn allows_for_missed_vblanks() {
+    let mut clock = FrameClock::new(Duration::from_nanos(NOMINAL));
+    // Only every third vblank seen.
+    for i in 0..50 {
+        clock.add_vblank(i * 3, at(i * 3 * 16_000_000));
+    }
+    assert!((nanos(clock.period()) - 16_000_000).abs() < 20_000, "{:?}", clock.period());
+    assert_eq!(clock.dropped_frames(), 0);
+}
+
+#[test]
+fn ignores_outliers_then_adapts() {
+    let mut clock = FrameClock::new(Duration::from_nanos(NOMINAL));
+    let mut ns = 0;
+    for seq in 0..10 {
+        clock.add_vblank(seq, at(ns));
+        ns += NOMINAL;
+    }
+    // One very late timestamp doesn't move the estimate...
+    let before = clock.period();
+    clock.add_vblank(10, at(ns + NOMINAL));
+    assert_eq!(clock.period(), before);
+
+    // ...but a new refresh rate does, eventually.
+    let mut ns = ns + NOMINAL;
+    for seq in 11..40 {
+        ns += 8_333_333;
+        clock.add_vblank(seq, at(ns));
+    }
+    assert!((nanos(clock.period()) - 8_333_333).abs() < 100_000, "{:?}", clock.period());
+}
+
+#[test]
+fn ignores_repeated_timestamps() {
+    let mut clock = FrameClock::new(Duration::from_nanos(NOMINAL));
+    // A disabled CRTC's counter moves on, but its timestamp doesn't.
+    for seq in 0..20 {
+        clock.add_vblank(seq, at(1_000_000_000));
+    }
+    assert_eq!(clock.period(), Duration::from_nanos(NOMINAL));
+    assert!(clock.refresh_rate().is_finite());
+    assert_eq!(clock.vblank_at(at(2_000_000_000)).map(|(seq, _)| seq), Some(19 + 60));
+    assert_eq!(clock.predict_after(at(2_000_000_000), 1).len(), 1);
+}
+
+#[test]
+fn counts_dropped_frames() {
+    let mut clock = FrameClock::new(Duration::from_nanos(NOMINAL));
+    let flip = |seq: u32| Event::PageFlip { seq, tv: at(seq as u64 * NOMINAL), user: 0, crtc_id: 0 };
+    for &seq in &[100, 101, 102, 105, 106, 108] {
+        clock.observe(&flip(seq));
+    }
+    assert_eq!(clock.dropped_frames(), 3);
+    // Timing isn't thrown by the gaps.
+    assert_eq!(clock.period(), Duration::from_nanos(NOMINAL));
+}
+
+#[test]
+fn wraps_32_bit_counter() {
+    let mut clock = FrameClock::new(Duration::from_nanos(NOMINAL));
+    clock.add_flip(u32::MAX as u64 - 1, at(0));
+    clock.add_flip(u32::MAX as u64, at(NOMINAL));
+    clock.add_flip(1, at(3 * NOMINAL));
+    assert_eq!(clock.dropped_frames(), 1);
+    assert_eq!(clock.samples(), 2);
+    assert_eq!(clock.period(), Duration::from_nanos(NOMINAL));
+}
+
+#[test]
+fn filters_by_crtc() {
+    let crtc = unsafe { drm::mode::Id::from_u32(42).unwrap() };
+    let mut clock = FrameClock::new(Duration::from_nanos(NOMINAL)).crtc(crtc);
+    assert!(clock.observe(&vblank(1, 0, 42)));
+    assert!(!clock.observe(&vblank(2, NOMINAL, 43)));
+    // Old kernels don't say.
+    assert!(clock.observe(&vblank(2, NOMINAL, 0)));
+    assert!(!clock.observe(&Event::Unknown { type_: 99, data: Vec::new() }));
+    assert_eq!(clock.last_vblank(), Some((2, at(NOMINAL))));
+}
+
+#[test]
+fn predicts_vblanks() {
+    let mut clock = FrameClock::new(Duration::from_nanos(NOMINAL));
+    assert_eq!(clock.predict(3), vec![]);
+    assert_eq!(clock.next_vblank(), None);
+    clock.add_vblank(10, at(1_000_000_000));
+
+    assert_eq!(clock.predict(2), vec![
+        (11, at(1_000_000_000 + NOMINAL)),
+        (12, at(1_000_000_000 + 2 * NOMINAL)),
+    ]);
+    assert_eq!(clock.next_vblank(), Some((11, at(1_000_000_000 + NOMINAL))));
+    // Half way through vblank 13's frame.
+    let now = at(1_000_000_000 + 3 * NOMINAL + NOMINAL / 2);
+    assert_eq!(clock.predict_after(now, 1), vec![(14, at(1_000_000_000 + 4 * NOMINAL))]);
+    // Before the last vblank.
+    assert_eq!(clock.predict_after(at(0), 1), clock.predict(1));
+}
+
+#[test]
+fn latency_stats() {
+    let mut clock = FrameClock::new(Duration::from_nanos(NOMINAL));
+    assert_eq!(clock.latency(), None);
+    for (i, &latency) in [4_000_000u64, 8_000_000, 6_000_000].iter().enumerate() {
+        let flip = (i as u64 + 1) * NOMINAL;
+        clock.flip_submitted(at(flip - latency));
+        clock.add_flip(i as u64 + 1, at(flip));
+    }
+    // Not submitted with flip_submitted, so not measured.
+    clock.add_flip(4, at(4 * NOMINAL));
+
+    let stats = clock.latency().unwrap();
+    assert_eq!(stats.count, 3);
+    assert_eq!(stats.min, Duration::from_millis(4));
+    assert_eq!(stats.max, Duration::from_millis(8));
+    assert_eq!(stats.mean, Duration::from_millis(6));
+    assert_eq!(stats.last, Duration::from_millis(6));
+}
+
+#[test]
+fn nearest_vblank() {
+    let mut clock = FrameClock::new(Duration::from_nanos(NOMINAL));
+    assert_eq!(clock.vblank_at(at(0)), None);
+    clock.add_vblank(10, at(1_000_000_000));
+    let vblank = |k: u64| Some((10 + k, at(1_000_000_000 + k * NOMINAL)));
+    assert_eq!(clock.vblank_at(at(1_000_000_000 + 3 * NOMINAL + NOMINAL / 3)), vblank(3));
+    assert_eq!(clock.vblank_at(at(1_000_000_000 + 3 * NOMINAL + 2 * NOMINAL / 3)), vblank(4));
+    // Never the last one seen, or earlier.
+    assert_eq!(clock.vblank_at(at(1_000_000_000)), vblank(1));
+    assert_eq!(clock.vblank_at(at(0)), vblank(1));
+}
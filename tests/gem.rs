@@ -0,0 +1,29 @@
+
+extern crate drm;
+use drm::Device;
+use drm::fourcc::FourCC;
+use drm::gem::GemObject;
+use drm::mode::{DumbBuffer, MapProtection};
+
+#[test]
+fn open_by_name_shares_handle() {
+    let dev = Device::first_card().expect("Failed to open card");
+    let buffer = DumbBuffer::create(&dev, 64, 64, FourCC::XRGB8888)
+        .expect("Failed to create dumb buffer");
+    let name = buffer.flink().expect("Failed to flink");
+
+    let first = GemObject::open(&dev, name).expect("Failed to open by name");
+    let second = GemObject::open(&dev, name).expect("Failed to open by name");
+    assert_eq!(first.handle(), second.handle());
+    // The name was given out by this device, so it's the buffer's own
+    // handle.
+    let own = buffer.gem_object().expect("Failed to get GEM object");
+    assert_eq!(first.handle(), own.handle());
+    assert_eq!(first.size(), Some(buffer.bytes() as u64));
+
+    // The handle is still owned by the buffer.
+    drop(first);
+    drop(second);
+    drop(own);
+    buffer.map(MapProtection::Read).expect("Handle was closed");
+}
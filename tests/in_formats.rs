@@ -0,0 +1,55 @@
+
+extern crate drm;
+use drm::mode::FormatModifierSet;
+use drm::modifier::Modifier;
+use drm::fourcc::FourCC;
+
+fn push_u32(v: &mut Vec<u8>, x: u32) { v.extend_from_slice(&x.to_ne_bytes()) }
+fn push_u64(v: &mut Vec<u8>, x: u64) { v.extend_from_slice(&x.to_ne_bytes()) }
+
+#[test]
+fn parse_in_formats() {
+    let formats: Vec<u32> = vec![FourCC::XRGB8888.into(), FourCC::ARGB8888.into(), FourCC::NV12.into()];
+    let mut blob = Vec::new();
+    push_u32(&mut blob, 1); // version
+    push_u32(&mut blob, 0); // flags
+    push_u32(&mut blob, formats.len() as u32);
+    push_u32(&mut blob, 24);
+    push_u32(&mut blob, 2);
+    push_u32(&mut blob, 40);
+    for &f in formats.iter() { push_u32(&mut blob, f); }
+    push_u32(&mut blob, 0); // padding to 8 bytes
+    // LINEAR: every format.
+    push_u64(&mut blob, 0b111); push_u32(&mut blob, 0); push_u32(&mut blob, 0);
+    push_u64(&mut blob, Modifier::LINEAR.0);
+    // X tiled: only the RGB formats.
+    push_u64(&mut blob, 0b011); push_u32(&mut blob, 0); push_u32(&mut blob, 0);
+    push_u64(&mut blob, Modifier::INTEL_X_TILED.0);
+
+    let set = FormatModifierSet::parse(&blob).unwrap();
+    assert!(set.supports(FourCC::NV12, Modifier::LINEAR));
+    assert!(set.supports(FourCC::XRGB8888, Modifier::INTEL_X_TILED));
+    assert!(!set.supports(FourCC::NV12, Modifier::INTEL_X_TILED));
+    assert_eq!(set.modifiers_for(FourCC::NV12), vec![Modifier::LINEAR]);
+
+    assert!(FormatModifierSet::parse(&blob[..50]).is_err());
+}
+
+#[test]
+fn parse_huge_offsets() {
+    let mut blob = Vec::new();
+    push_u32(&mut blob, 1); // version
+    push_u32(&mut blob, 0); // flags
+    push_u32(&mut blob, u32::max_value());
+    push_u32(&mut blob, u32::max_value());
+    push_u32(&mut blob, 0);
+    push_u32(&mut blob, 0);
+    assert!(FormatModifierSet::parse(&blob).is_err());
+
+    let mut blob = blob[..8].to_vec();
+    push_u32(&mut blob, 0);
+    push_u32(&mut blob, 0);
+    push_u32(&mut blob, u32::max_value());
+    push_u32(&mut blob, u32::max_value());
+    assert!(FormatModifierSet::parse(&blob).is_err());
+}
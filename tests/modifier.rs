@@ -0,0 +1,23 @@
+
+extern crate drm;
+use drm::modifier::*;
+
+#[test]
+fn modifier_names() {
+    assert_eq!(Modifier::LINEAR.to_string(), "LINEAR");
+    assert_eq!(Modifier::INVALID.to_string(), "INVALID");
+    assert_eq!(Modifier::INTEL_Y_TILED.to_string(), "I915_Y_TILED");
+    assert_eq!(Modifier::INTEL_Y_TILED.vendor(), Vendor::Intel);
+
+    let afbc = Modifier::arm_afbc(AFBC_BLOCK_SIZE_16X16 | AFBC_YTR | AFBC_SPARSE);
+    assert_eq!(afbc.0, 0x0800_0000_0000_0051);
+    assert_eq!(afbc.to_string(), "AFBC(16x16, SPARSE, YTR)");
+
+    let sand = Modifier::broadcom_sand(Modifier::BROADCOM_SAND128, 96);
+    assert_eq!(sand.to_string(), "BROADCOM_SAND128(height=96)");
+
+    let nv = Modifier::nvidia_block_linear_2d(0, 1, 2, 0xfe, 4);
+    assert_eq!(nv.to_string(), "NVIDIA_BLOCK_LINEAR_2D(h=4, k=0xfe, g=2, s=1, c=0)");
+
+    assert_eq!(Modifier(0xff00_0000_0000_0001).vendor(), Vendor::Other(0xff));
+}
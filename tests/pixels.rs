@@ -0,0 +1,78 @@
+
+extern crate drm;
+use drm::pixels::{PixelFormat, Pixels, Xrgb8888, Rgb565, Bgr888};
+
+#[test]
+fn padded_rows() {
+    // 3x2 XRGB8888 with 4 bytes of padding per row.
+    let mut data = vec![0xaa; 16 * 2];
+    {
+        let mut pixels = Pixels::<Xrgb8888>::new(&mut data, 3, 2, 16).unwrap();
+        for (y, mut row) in pixels.rows_mut().enumerate() {
+            assert_eq!(row.len(), 3);
+            row.fill(y as u32 + 1);
+        }
+        pixels.put(2, 1, 0x00ff_00ff);
+        assert_eq!(pixels.get(2, 1), Some(0x00ff_00ff));
+        assert_eq!(pixels.get(3, 1), None);
+        assert_eq!(pixels.get(0, 2), None);
+    }
+    assert_eq!(&data[12..16], &[0xaa; 4]);
+    assert_eq!(&data[16..20], &[2, 0, 0, 0]);
+    assert_eq!(&data[24..28], &[0xff, 0, 0xff, 0]);
+    assert_eq!(&data[28..32], &[0xaa; 4]);
+}
+
+#[test]
+fn sub_rectangle() {
+    let mut data = vec![0u8; 8 * 4];
+    {
+        let mut pixels = Pixels::<Rgb565>::new(&mut data, 4, 4, 8).unwrap();
+        assert!(pixels.sub(3, 3, 2, 1).is_none());
+        pixels.sub(1, 2, 2, 2).unwrap().fill(0xffff);
+    }
+    let set: Vec<_> = data.chunks(2).enumerate()
+        .filter(|&(_, p)| p == [0xff, 0xff])
+        .map(|(i, _)| (i % 4, i / 4))
+        .collect();
+    assert_eq!(set, vec![(1, 2), (2, 2), (1, 3), (2, 3)]);
+}
+
+#[test]
+fn rgba_helpers() {
+    assert_eq!(Xrgb8888::from_rgba([0x11, 0x22, 0x33, 0x44]), 0x0011_2233);
+    assert_eq!(Xrgb8888::to_rgba(0x0011_2233), [0x11, 0x22, 0x33, 0xff]);
+    assert_eq!(Rgb565::from_rgba([0xff, 0, 0xff, 0xff]), 0xf81f);
+    assert_eq!(Rgb565::to_rgba(0x07e0), [0, 0xff, 0, 0xff]);
+
+    let mut data = [0u8; 3];
+    Bgr888::write(&mut data, Bgr888::from_rgba([1, 2, 3, 0]));
+    assert_eq!(data, [1, 2, 3]);
+}
+
+#[test]
+fn too_small() {
+    let mut data = vec![0u8; 20];
+    assert!(Pixels::<Xrgb8888>::new(&mut data, 3, 2, 8).is_err());
+    assert!(Pixels::<Xrgb8888>::new(&mut data, 3, 2, 12).is_err());
+    assert!(Pixels::<Xrgb8888>::new(&mut data, 2, 2, 12).is_ok());
+}
+
+#[test]
+fn zero_pitch() {
+    let mut data = vec![0u8; 20];
+    assert!(Pixels::<Xrgb8888>::new(&mut data, 0, 2, 0).is_err());
+    let mut empty = Pixels::<Xrgb8888>::new(&mut data, 0, 0, 0).expect("Failed to make empty view");
+    assert_eq!(empty.rows_mut().count(), 0);
+    empty.fill(0x12345678);
+}
+
+#[test]
+fn zero_width_rows() {
+    let mut data = vec![0u8; 4 * 8 * 3];
+    let mut pixels = Pixels::<Xrgb8888>::new(&mut data, 4, 3, 8 * 4).unwrap();
+    let mut sub = pixels.sub(1, 0, 0, 3).expect("Failed to make sub view");
+    assert_eq!(sub.height(), 3);
+    assert_eq!(sub.rows_mut().count(), 3);
+    assert!(sub.rows_mut().all(|row| row.len() == 0));
+}
@@ -0,0 +1,66 @@
+extern crate drm;
+
+use std::time::Duration;
+use drm::Device;
+use drm::event::DrmTimestamp;
+use drm::present::{PresentTarget, Presenter};
+
+#[test]
+fn present_on_vblank() {
+    let mut dev = Device::first_card().expect("Failed to open card");
+    let res = dev.get_resources().expect("Failed to get resources");
+    let crtc = res.crtcs().iter().map(|&id| dev.get(id).unwrap())
+        .find(|crtc| crtc.mode().is_some() && crtc.fb_id().is_some());
+    let crtc = match crtc {
+        Some(crtc) => crtc,
+        None => return,
+    };
+    let mut presenter = Presenter::new(&dev, crtc.id(), 0x5eed).expect("Failed to make presenter");
+
+    let mut master = dev.set_master().expect("Failed to become master");
+    let now = master.vblank_count(crtc.id()).expect("Failed to get vblank count");
+    // Show the frame buffer that's already up, a few vblanks from now.
+    let target = presenter.present(&master, crtc.fb_id().unwrap(), PresentTarget::Sequence(now.seq + 3))
+        .expect("Failed to present");
+    assert_eq!(target, now.seq + 3);
+    assert!(presenter.is_pending());
+
+    let presented = loop {
+        let event = master.read_event().expect("Failed to read event");
+        if let Some(presented) = presenter.handle_event(&master, &event).expect("Failed to flip") {
+            break presented;
+        }
+    };
+    assert!(presented.seq >= target);
+    assert_eq!(presented.on_time, presented.seq == target);
+    assert!(!presenter.is_pending());
+}
+
+#[test]
+fn present_late_time() {
+    let mut dev = Device::first_card().expect("Failed to open card");
+    let res = dev.get_resources().expect("Failed to get resources");
+    let crtc = res.crtcs().iter().map(|&id| dev.get(id).unwrap())
+        .find(|crtc| crtc.mode().is_some() && crtc.fb_id().is_some());
+    let crtc = match crtc {
+        Some(crtc) => crtc,
+        None => return,
+    };
+    let mut presenter = Presenter::new(&dev, crtc.id(), 0x1a7e).expect("Failed to make presenter");
+
+    let mut master = dev.set_master().expect("Failed to become master");
+    let now = master.vblank_count(crtc.id()).expect("Failed to get vblank count");
+    // A second before the last vblank: long gone.
+    let late = DrmTimestamp::new(now.time.clock(), now.time.as_duration() - Duration::from_secs(1));
+    presenter.present(&master, crtc.fb_id().unwrap(), PresentTarget::Time(late))
+        .expect("Failed to present");
+
+    let presented = loop {
+        let event = master.read_event().expect("Failed to read event");
+        if let Some(presented) = presenter.handle_event(&master, &event).expect("Failed to flip") {
+            break presented;
+        }
+    };
+    assert_eq!(presented.target_time, Some(late));
+    assert!(!presented.on_time);
+}
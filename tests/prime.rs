@@ -0,0 +1,24 @@
+
+extern crate drm;
+use drm::Device;
+use drm::fourcc::FourCC;
+use drm::mode::{DumbBuffer, MapProtection};
+use drm::prime::{DmaBuf, SyncAccess, PRIME_CLOEXEC, PRIME_RDWR};
+
+#[test]
+fn export_import_round_trip() {
+    let dev = Device::first_card().expect("Failed to open card");
+    let buffer = DumbBuffer::create(&dev, 64, 64, FourCC::XRGB8888)
+        .expect("Failed to create dumb buffer");
+    let gem = buffer.gem_object().expect("Failed to get GEM object");
+    let fd = gem.prime_export(PRIME_CLOEXEC | PRIME_RDWR).expect("Failed to export");
+
+    let imported = dev.prime_import(&fd).expect("Failed to import");
+    assert_eq!(imported.handle(), gem.handle());
+    assert!(imported.size().unwrap() >= buffer.bytes() as u64);
+
+    let dma_buf = DmaBuf::new(fd);
+    let map = dma_buf.map(MapProtection::Read).expect("Failed to map dma-buf");
+    let _access = dma_buf.access(SyncAccess::Read).expect("Failed to sync");
+    assert!(map.as_slice().len() >= buffer.bytes());
+}
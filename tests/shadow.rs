@@ -0,0 +1,31 @@
+
+extern crate drm;
+use drm::Device;
+use drm::draw::{self, Color, Rect};
+use drm::fourcc::FourCC;
+use drm::mode::DumbBufOptions;
+use drm::shadow::ShadowBuf;
+
+#[test]
+fn present_damaged_rows() {
+    let dev = Device::first_card().expect("Failed to open card");
+    let buf = DumbBufOptions::new(&dev)
+        .width(64).height(64)
+        .format(FourCC::XRGB8888)
+        .create()
+        .expect("Failed to create dumbbuf");
+    let pitch = buf.pitch();
+
+    let mut shadow = ShadowBuf::with_shadow(buf, true);
+    // Clears the buffer.
+    shadow.present();
+    {
+        let mut rows = shadow.image_mut_rows(8, 4).unwrap();
+        draw::fill_rect(&mut rows, Rect::new(0, 0, 64, 4), Color::rgb8(0xff, 0, 0)).unwrap();
+    }
+    // into_inner presents, copying only rows 8..12.
+    let mut buf = shadow.into_inner();
+    let data: &mut [u8] = buf.as_mut();
+    assert_eq!(&data[8 * pitch..8 * pitch + 4], &[0, 0, 0xff, 0]);
+    assert_eq!(&data[12 * pitch..12 * pitch + 4], &[0, 0, 0, 0]);
+}
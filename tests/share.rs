@@ -0,0 +1,21 @@
+
+extern crate drm;
+use drm::{ClientCapability, Device};
+use drm::fourcc::FourCC;
+use drm::mode::DumbBuffer;
+use drm::share::CrossDeviceFb;
+
+#[test]
+fn share_with_same_device() {
+    let dev = Device::first_card().expect("Failed to open card");
+    let buffer = DumbBuffer::create(&dev, 64, 64, FourCC::XRGB8888)
+        .expect("Failed to create dumb buffer");
+    let target = Device::first_card().expect("Failed to open card");
+    target.set_client_capability(ClientCapability::UniversalPlanes, true)
+        .expect("Failed to set universal planes");
+
+    let mut fb = CrossDeviceFb::from_dumb(&buffer, &target).expect("Failed to share");
+    // A device can always import its own buffers.
+    assert!(fb.is_zero_copy());
+    fb.update().expect("Failed to update");
+}
@@ -0,0 +1,100 @@
+extern crate drm;
+
+use std::time::Duration;
+use drm::{Device, Event};
+use drm::event::{Clock, DrmTimestamp};
+use drm::fourcc::FourCC;
+use drm::swapchain::Swapchain;
+
+fn page_flip(user: u64) -> Event {
+    Event::PageFlip {
+        seq: 1,
+        tv: DrmTimestamp::new(Clock::Monotonic, Duration::from_secs(1)),
+        user,
+        crtc_id: 0,
+    }
+}
+
+#[test]
+fn acquire_and_release() {
+    let dev = Device::first_card().expect("Failed to open card");
+    let res = dev.get_resources().expect("Failed to get resources");
+    let crtc = res.crtcs()[0];
+    let mut chain = Swapchain::new(&dev, crtc, 64, 64, FourCC::XRGB8888, 2)
+        .expect("Failed to make swapchain");
+    assert_eq!(chain.len(), 2);
+
+    let (first, age) = {
+        let back = chain.next_buffer().expect("No free buffer");
+        (back.index(), back.age())
+    };
+    // Never shown.
+    assert_eq!(age, 0);
+    let second = chain.next_buffer().expect("No free buffer").index();
+    assert!(first != second);
+    assert!(chain.next_buffer().is_none());
+
+    chain.release(first);
+    assert_eq!(chain.next_buffer().map(|back| back.index()), Some(first));
+    assert!(!chain.is_flip_pending());
+
+    // Nothing is queued, so no flip is ours.
+    for &user in &[0, first as u64, (1 << 32) | first as u64] {
+        assert!(!chain.handle_event(&page_flip(user)));
+    }
+}
+
+#[test]
+fn flip_and_age() {
+    let mut dev = Device::first_card().expect("Failed to open card");
+    let res = dev.get_resources().expect("Failed to get resources");
+    let connector = res.connectors().iter()
+        .filter_map(|&id| dev.get(id).ok())
+        .find(|conn| conn.encoder_id().is_some());
+    let connector = match connector {
+        Some(connector) => connector,
+        None => return,
+    };
+    let encoder = dev.get(connector.encoder_id().unwrap()).expect("Failed to get encoder");
+    let crtc = match encoder.crtc_id() {
+        Some(crtc) => dev.get(crtc).expect("Failed to get CRTC"),
+        None => return,
+    };
+    let mode = match crtc.mode() {
+        Some(mode) => *mode,
+        None => return,
+    };
+    let (width, height) = (mode.hdisplay as u32, mode.vdisplay as u32);
+
+    let mut master = dev.set_master().expect("Failed to become master");
+    let mut chain = Swapchain::new(&master, crtc.id(), width, height, FourCC::XRGB8888, 2)
+        .expect("Failed to make swapchain");
+    let front = chain.next_buffer().unwrap().index();
+    chain.set_mode(&master, front, &[connector.id()], &mode).expect("Failed to set mode");
+
+    let back = chain.next_buffer().expect("No free buffer").index();
+    assert!(back != front);
+    // The front buffer is on screen, so can't be drawn to.
+    assert!(chain.next_buffer().is_none());
+    chain.queue(&master, back).expect("Failed to queue");
+    assert!(chain.is_flip_pending());
+    assert!(chain.next_buffer().is_none());
+
+    let event = master.read_event().expect("Failed to read event");
+    assert!(chain.handle_event(&event));
+    assert!(!chain.is_flip_pending());
+    // Only handled once.
+    assert!(!chain.handle_event(&event));
+
+    // The old front buffer is free again, holding the frame before last.
+    let (index, age) = {
+        let next = chain.next_buffer().expect("Old front buffer not freed");
+        (next.index(), next.age())
+    };
+    assert_eq!(index, front);
+    assert_eq!(age, 2);
+
+    // Put back what was on screen.
+    master.set_crtc(crtc.id(), crtc.fb_id(), 0, 0, &[connector.id()], Some(&mode))
+        .expect("Failed to restore CRTC");
+}
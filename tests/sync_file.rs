@@ -0,0 +1,22 @@
+
+extern crate drm;
+use std::time::Duration;
+use drm::Device;
+use drm::sync_file::{FenceStatus, SyncFile};
+use drm::syncobj::SyncObj;
+
+#[test]
+fn signaled_sync_file() {
+    let dev = Device::first_card().expect("Failed to open card");
+    let syncobj = SyncObj::create(&dev, true).expect("Failed to create syncobj");
+    let fence = SyncFile::new(syncobj.export_sync_file(0).expect("Failed to export"));
+
+    assert!(fence.poll().expect("Failed to poll"));
+    fence.wait(Some(Duration::from_millis(10))).expect("Failed to wait");
+
+    let merged = fence.merge(&fence, "merged").expect("Failed to merge");
+    let info = merged.info().expect("Failed to get info");
+    assert_eq!(info.name, "merged");
+    assert_eq!(info.status, FenceStatus::Signaled);
+    assert!(info.fences.iter().all(|f| f.timestamp.is_some()));
+}
@@ -0,0 +1,24 @@
+
+extern crate drm;
+use std::time::Duration;
+use drm::Device;
+use drm::syncobj::{self, SyncObj, WaitMode, SyncObjWaitFlags, SYNCOBJ_WAIT_FOR_SUBMIT};
+
+#[test]
+fn signal_and_wait() {
+    let dev = Device::first_card().expect("Failed to open card");
+    let signaled = SyncObj::create(&dev, true).expect("Failed to create syncobj");
+    let pending = SyncObj::create(&dev, false).expect("Failed to create syncobj");
+
+    signaled.wait(SyncObjWaitFlags::empty(), Some(Duration::from_millis(0)))
+        .expect("Signaled syncobj didn't signal");
+    let first = syncobj::wait(&[&pending, &signaled], WaitMode::First,
+                              SYNCOBJ_WAIT_FOR_SUBMIT, Some(Duration::from_millis(0)))
+        .expect("Failed to wait");
+    assert_eq!(first, 1);
+
+    pending.signal().expect("Failed to signal");
+    pending.wait(SyncObjWaitFlags::empty(), Some(Duration::from_millis(0)))
+        .expect("Signaled syncobj didn't signal");
+    pending.reset().expect("Failed to reset");
+}
@@ -0,0 +1,58 @@
+#![cfg(feature = "tokio")]
+extern crate drm;
+extern crate libc;
+extern crate tokio;
+
+use drm::{Device, Event};
+use std::fs::File;
+use std::io::Write;
+use std::os::unix::io::FromRawFd;
+
+fn page_flip(user: u64, seq: u32) -> Vec<u8> {
+    let mut data = Vec::new();
+    for word in &[2u32, 32] {
+        data.extend_from_slice(&word.to_ne_bytes());
+    }
+    data.extend_from_slice(&user.to_ne_bytes());
+    for word in &[1u32, 0, seq, 0] {
+        data.extend_from_slice(&word.to_ne_bytes());
+    }
+    data
+}
+
+/// A "device" reading from a pipe, and the other end.
+fn pipe_device() -> (Device, File) {
+    let mut fds = [0; 2];
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+    unsafe { (Device::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) }
+}
+
+#[test]
+fn reads_events() {
+    let rt = tokio::runtime::Builder::new_current_thread().enable_io().build().unwrap();
+    let _guard = rt.enter();
+    let (dev, mut input) = pipe_device();
+    let mut dev = dev.event_stream().unwrap();
+
+    input.write_all(&page_flip(1, 10)).unwrap();
+    input.write_all(&page_flip(2, 11)).unwrap();
+    match rt.block_on(dev.next_event()).unwrap() {
+        Event::PageFlip { user: 1, seq: 10, .. } => {}
+        ev => panic!("Expected first page flip, got {:?}", ev),
+    }
+    match rt.block_on(dev.next_event()).unwrap() {
+        Event::PageFlip { user: 2, seq: 11, .. } => {}
+        ev => panic!("Expected second page flip, got {:?}", ev),
+    }
+}
+
+#[test]
+fn failed_request() {
+    let rt = tokio::runtime::Builder::new_current_thread().enable_io().build().unwrap();
+    let _guard = rt.enter();
+    let (dev, _input) = pipe_device();
+    let mut dev = dev.event_stream().unwrap();
+    // Not a DRM device, so the ioctl fails rather than waiting forever.
+    let crtc = unsafe { drm::mode::Id::from_u32(1).unwrap() };
+    assert!(rt.block_on(dev.next_vblank(crtc)).is_err());
+}
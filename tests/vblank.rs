@@ -0,0 +1,45 @@
+extern crate drm;
+
+use drm::{Device, Event};
+use drm::vblank::Target;
+
+#[test]
+fn wait_vblank() {
+    let dev = Device::first_card().expect("Failed to open card");
+    let res = dev.get_resources().expect("Failed to get resources");
+    for (pipe, &crtc) in res.crtcs().iter().enumerate() {
+        assert_eq!(dev.crtc_pipe(crtc).unwrap(), pipe as u32);
+        let crtc_info = dev.get(crtc).unwrap();
+        if crtc_info.mode().is_none() {
+            // Vblanks only happen on CRTCs that are on.
+            continue;
+        }
+        let now = dev.vblank_count(crtc).expect("Failed to get vblank count");
+        let next = dev.wait_vblank(crtc, Target::Relative(1), false)
+            .expect("Failed to wait for vblank");
+        assert!(next.seq > now.seq);
+        assert!(next.time > now.time);
+        let missed = dev.wait_vblank(crtc, Target::Absolute(now.seq as u32), true)
+            .expect("Failed to wait for vblank");
+        assert!(missed.seq > now.seq);
+    }
+}
+
+#[test]
+fn crtc_sequence() {
+    let mut dev = Device::first_card().expect("Failed to open card");
+    let res = dev.get_resources().expect("Failed to get resources");
+    for &crtc in res.crtcs() {
+        let now = dev.crtc_sequence(crtc).expect("Failed to get CRTC sequence");
+        if !now.active {
+            continue;
+        }
+        let target = dev.queue_sequence(crtc, 1, true, false, 77)
+            .expect("Failed to queue sequence");
+        assert!(target > now.seq);
+        match dev.read_event().expect("Failed to read event") {
+            Event::CrtcSequence { seq, user: 77, .. } => assert_eq!(seq, target),
+            ev => panic!("Expected CRTC sequence event, got {:?}", ev),
+        }
+    }
+}
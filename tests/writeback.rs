@@ -0,0 +1,41 @@
+
+extern crate drm;
+use std::time::Duration;
+use drm::{ClientCapability, Device};
+use drm::fourcc::FourCC;
+use drm::mode::{ConnectorType, DumbBufOptions, Writeback};
+
+#[test]
+fn capture_crtc() {
+    let mut dev = Device::first_card().expect("Failed to open card");
+    dev.set_client_capability(ClientCapability::Atomic, true)
+        .expect("Failed to enable atomic");
+    dev.set_client_capability(ClientCapability::WritebackConnectors, true)
+        .expect("Failed to enable writeback connectors");
+
+    let res = dev.get_resources().expect("Failed to get resources");
+    let connector = res.connectors().iter().cloned()
+        .find(|&id| dev.get(id).unwrap().connector_type() == ConnectorType::WRITEBACK);
+    let connector = match connector {
+        Some(connector) => connector,
+        // Only some drivers (like vkms) have writeback connectors.
+        None => return,
+    };
+    let writeback = Writeback::new(&dev, connector).expect("Failed to get writeback");
+    let format = writeback.pick_format(&[FourCC::XRGB8888]).expect("No formats");
+
+    let crtc = dev.get(res.crtcs()[0]).expect("Failed to get CRTC");
+    let mode = match crtc.mode() {
+        Some(mode) => mode.clone(),
+        None => return,
+    };
+    let buf = DumbBufOptions::new(&dev)
+        .width(mode.hdisplay as u32).height(mode.vdisplay as u32)
+        .format(format)
+        .create()
+        .expect("Failed to create buffer");
+
+    let master = dev.set_master().expect("Failed to become master");
+    writeback.capture(&master, crtc.id(), buf.fb().id(), Some(Duration::from_secs(1)))
+        .expect("Failed to capture");
+}